@@ -0,0 +1,384 @@
+use crate::data::{AlignmentType, CigarColumn, DifferenceColumn, PAFLine, Strand};
+use std::collections::HashMap;
+use std::io::Write;
+
+/// Options for evaluating an assembly from its alignments to a reference.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EvaluationOptions {
+    /// Two adjacent alignments of a contig are a relocation if their distances on the contig and on the reference
+    /// differ by more than this many bases, like the extensive misassembly threshold of QUAST.
+    pub breakpoint_tolerance: usize,
+    /// Alignments with a lower mapping quality are ignored.
+    pub min_mapping_quality: u8,
+    /// Alignments with fewer bases and gaps (column 11) are ignored.
+    pub min_alignment_length: usize,
+}
+
+impl Default for EvaluationOptions {
+    fn default() -> Self {
+        Self {
+            breakpoint_tolerance: 1000,
+            min_mapping_quality: 0,
+            min_alignment_length: 0,
+        }
+    }
+}
+
+impl EvaluationOptions {
+    /// Count adjacent alignments as relocation if their distances differ by more than the given number of bases.
+    pub fn with_breakpoint_tolerance(mut self, breakpoint_tolerance: usize) -> Self {
+        self.breakpoint_tolerance = breakpoint_tolerance;
+        self
+    }
+
+    /// Ignore alignments with a mapping quality lower than the given one.
+    pub fn with_min_mapping_quality(mut self, min_mapping_quality: u8) -> Self {
+        self.min_mapping_quality = min_mapping_quality;
+        self
+    }
+
+    /// Ignore alignments with fewer than the given number of bases and gaps.
+    pub fn with_min_alignment_length(mut self, min_alignment_length: usize) -> Self {
+        self.min_alignment_length = min_alignment_length;
+        self
+    }
+
+    fn accepts(&self, line: &PAFLine) -> bool {
+        !line.is_unmapped()
+            && !matches!(
+                line.alignment_type,
+                Some(AlignmentType::Secondary | AlignmentType::SecondaryInversion)
+            )
+            && line.mapping_quality >= self.min_mapping_quality
+            && line.number_of_bases_and_gaps >= self.min_alignment_length
+    }
+}
+
+/// Quality metrics of an assembly, computed from its contig-to-reference alignments, similar to the report of QUAST.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AssemblyReport {
+    /// The number of contigs, including unaligned ones that occur as unmapped lines.
+    pub contigs: usize,
+    /// The total length of all contigs.
+    pub assembly_length: usize,
+    /// The total length of all reference sequences that have alignments.
+    pub reference_length: usize,
+    /// The total number of contig bases in alignments.
+    pub aligned_length: usize,
+    /// The number of reference bases covered by at least one alignment.
+    pub covered_reference_length: usize,
+    /// The fraction of the reference covered by alignments, between 0 and 1.
+    pub genome_fraction: f64,
+    /// The number of aligned contig bases per covered reference base.
+    pub duplication_ratio: f64,
+    /// The length of the longest alignment on its contig.
+    pub largest_alignment: usize,
+    /// The length such that the alignments of at least this length cover half of the assembly,
+    /// or `None` if less than half of the assembly is aligned.
+    pub na50: Option<usize>,
+    /// The length such that the alignments of at least this length cover half of the reference length,
+    /// or `None` if less than half of the reference length is aligned.
+    pub nga50: Option<usize>,
+    /// The number of junctions between adjacent alignments of a contig that jump on the same reference sequence and strand.
+    pub relocations: usize,
+    /// The number of junctions between adjacent alignments of a contig that change the strand.
+    pub inversions: usize,
+    /// The number of junctions between adjacent alignments of a contig that change the reference sequence.
+    pub translocations: usize,
+    /// The number of mismatches per 100 kbp of aligned contig bases.
+    pub mismatches_per_100kbp: f64,
+    /// The number of insertions and deletions per 100 kbp of aligned contig bases.
+    pub indels_per_100kbp: f64,
+}
+
+impl AssemblyReport {
+    /// Returns the total number of misassemblies, i.e. relocations, inversions and translocations.
+    pub fn misassemblies(&self) -> usize {
+        self.relocations + self.inversions + self.translocations
+    }
+
+    /// Write this report as TSV with a header line and one metric per line.
+    /// Missing values are written as `-`.
+    pub fn write_tsv(&self, mut output: impl Write) -> std::io::Result<()> {
+        writeln!(output, "metric\tvalue")?;
+        for (name, value) in self.metrics() {
+            match value {
+                MetricValue::Count(value) => writeln!(output, "{name}\t{value}")?,
+                MetricValue::Ratio(value) => writeln!(output, "{name}\t{value:.4}")?,
+                MetricValue::Missing => writeln!(output, "{name}\t-")?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns this report as a JSON object with one field per metric.
+    /// Missing values are written as `null`.
+    pub fn to_json(&self) -> String {
+        let fields: Vec<_> = self
+            .metrics()
+            .into_iter()
+            .map(|(name, value)| match value {
+                MetricValue::Count(value) => format!("  \"{name}\": {value}"),
+                MetricValue::Ratio(value) => format!("  \"{name}\": {value}"),
+                MetricValue::Missing => format!("  \"{name}\": null"),
+            })
+            .collect();
+        format!("{{\n{}\n}}\n", fields.join(",\n"))
+    }
+
+    fn metrics(&self) -> Vec<(&'static str, MetricValue)> {
+        let optional =
+            |value: Option<usize>| value.map_or(MetricValue::Missing, MetricValue::Count);
+        vec![
+            ("contigs", MetricValue::Count(self.contigs)),
+            ("assembly_length", MetricValue::Count(self.assembly_length)),
+            (
+                "reference_length",
+                MetricValue::Count(self.reference_length),
+            ),
+            ("aligned_length", MetricValue::Count(self.aligned_length)),
+            (
+                "covered_reference_length",
+                MetricValue::Count(self.covered_reference_length),
+            ),
+            ("genome_fraction", MetricValue::Ratio(self.genome_fraction)),
+            (
+                "duplication_ratio",
+                MetricValue::Ratio(self.duplication_ratio),
+            ),
+            (
+                "largest_alignment",
+                MetricValue::Count(self.largest_alignment),
+            ),
+            ("na50", optional(self.na50)),
+            ("nga50", optional(self.nga50)),
+            ("misassemblies", MetricValue::Count(self.misassemblies())),
+            ("relocations", MetricValue::Count(self.relocations)),
+            ("inversions", MetricValue::Count(self.inversions)),
+            ("translocations", MetricValue::Count(self.translocations)),
+            (
+                "mismatches_per_100kbp",
+                MetricValue::Ratio(self.mismatches_per_100kbp),
+            ),
+            (
+                "indels_per_100kbp",
+                MetricValue::Ratio(self.indels_per_100kbp),
+            ),
+        ]
+    }
+}
+
+enum Misassembly {
+    Relocation,
+    Inversion,
+    Translocation,
+}
+
+/// Returns the misassembly between the given alignments of a contig, where the first one precedes the second one on the contig.
+fn misassembly(first: &PAFLine, second: &PAFLine, tolerance: usize) -> Option<Misassembly> {
+    if first.target_sequence_name != second.target_sequence_name {
+        return Some(Misassembly::Translocation);
+    }
+    if first.strand != second.strand {
+        return Some(Misassembly::Inversion);
+    }
+
+    // the distances between the alignments in the direction of the contig
+    let query_gap = second.query_start_coordinate as isize - first.query_end_coordinate as isize;
+    let target_gap = if first.strand == Strand::Reverse {
+        first.target_start_coordinate_on_original_strand as isize
+            - second.target_end_coordinate_on_original_strand as isize
+    } else {
+        second.target_start_coordinate_on_original_strand as isize
+            - first.target_end_coordinate_on_original_strand as isize
+    };
+    if (target_gap - query_gap).unsigned_abs() > tolerance {
+        Some(Misassembly::Relocation)
+    } else {
+        None
+    }
+}
+
+enum MetricValue {
+    Count(usize),
+    Ratio(f64),
+    Missing,
+}
+
+/// Evaluates an assembly from the alignments of its contigs to a reference, like a quick QUAST run.
+///
+/// The reference lengths are taken from the target lengths of the lines, and the contig lengths from the query lengths.
+/// Misassemblies are counted between adjacent alignments of each contig, ordered by their contig coordinates.
+/// Alignments to different reference sequences are a translocation, alignments to different strands are an inversion,
+/// and alignments whose distances on the contig and on the reference differ by more than the breakpoint tolerance are a relocation.
+/// Consistent overlaps, i.e. by the same number of bases on the contig and the reference, are no misassembly.
+/// Alignments split at the origin of a circular reference count as relocation, unless they were merged with
+/// [merge_circular_alignments](crate::circular::merge_circular_alignments) before,
+/// in which case their coverage wraps around the origin.
+///
+/// Mismatches and indels are counted from the alignment difference string, or from the cigar string and the `NM` tag.
+/// Alignments with neither are not part of these rates.
+pub fn evaluate_assembly(lines: &[PAFLine], options: &EvaluationOptions) -> AssemblyReport {
+    let mut contig_lengths = HashMap::new();
+    let mut reference_lengths = HashMap::new();
+    for line in lines {
+        contig_lengths.insert(
+            line.query_sequence_name.as_str(),
+            line.query_sequence_length,
+        );
+        if !line.is_unmapped() {
+            reference_lengths.insert(
+                line.target_sequence_name.as_str(),
+                line.target_sequence_length,
+            );
+        }
+    }
+    let assembly_length = contig_lengths.values().sum();
+    let reference_length = reference_lengths.values().sum();
+
+    let alignments: Vec<_> = lines.iter().filter(|line| options.accepts(line)).collect();
+    let mut alignment_lengths: Vec<_> = alignments
+        .iter()
+        .map(|line| line.query_end_coordinate - line.query_start_coordinate)
+        .collect();
+    alignment_lengths.sort_unstable_by(|a, b| b.cmp(a));
+    let aligned_length = alignment_lengths.iter().sum();
+
+    let mut intervals: HashMap<&str, Vec<(usize, usize)>> = HashMap::new();
+    for line in &alignments {
+        let target_intervals = intervals.entry(&line.target_sequence_name).or_default();
+        let length = line.target_sequence_length;
+        let start = line.target_start_coordinate_on_original_strand;
+        let end = line.target_end_coordinate_on_original_strand;
+        if end > length {
+            target_intervals.push((start, length));
+            target_intervals.push((0, (end - length).min(length)));
+        } else {
+            target_intervals.push((start, end));
+        }
+    }
+    let covered_reference_length = intervals.into_values().map(union_length).sum();
+
+    let mut report = AssemblyReport {
+        contigs: contig_lengths.len(),
+        assembly_length,
+        reference_length,
+        aligned_length,
+        covered_reference_length,
+        genome_fraction: ratio(covered_reference_length, reference_length),
+        duplication_ratio: ratio(aligned_length, covered_reference_length),
+        largest_alignment: alignment_lengths.first().copied().unwrap_or(0),
+        na50: n50(&alignment_lengths, assembly_length),
+        nga50: n50(&alignment_lengths, reference_length),
+        relocations: 0,
+        inversions: 0,
+        translocations: 0,
+        mismatches_per_100kbp: 0.0,
+        indels_per_100kbp: 0.0,
+    };
+
+    let mut contigs: Vec<Vec<&PAFLine>> = Vec::new();
+    let mut contig_indices = HashMap::new();
+    for line in &alignments {
+        let index = *contig_indices
+            .entry(line.query_sequence_name.as_str())
+            .or_insert_with(|| {
+                contigs.push(Vec::new());
+                contigs.len() - 1
+            });
+        contigs[index].push(line);
+    }
+    for contig_alignments in &mut contigs {
+        contig_alignments
+            .sort_by_key(|line| (line.query_start_coordinate, line.query_end_coordinate));
+        for pair in contig_alignments.windows(2) {
+            match misassembly(pair[0], pair[1], options.breakpoint_tolerance) {
+                Some(Misassembly::Relocation) => report.relocations += 1,
+                Some(Misassembly::Inversion) => report.inversions += 1,
+                Some(Misassembly::Translocation) => report.translocations += 1,
+                None => {}
+            }
+        }
+    }
+
+    let mut mismatches = 0;
+    let mut indels = 0;
+    let mut counted_length = 0;
+    for line in &alignments {
+        if let Some((line_mismatches, line_indels)) = count_differences(line) {
+            mismatches += line_mismatches;
+            indels += line_indels;
+            counted_length += line.query_end_coordinate - line.query_start_coordinate;
+        }
+    }
+    report.mismatches_per_100kbp = ratio(mismatches, counted_length) * 100_000.0;
+    report.indels_per_100kbp = ratio(indels, counted_length) * 100_000.0;
+
+    report
+}
+
+/// Returns the number of mismatches and of insertions and deletions of the given alignment,
+/// or `None` if it has neither an alignment difference string nor a cigar string and an `NM` tag.
+fn count_differences(line: &PAFLine) -> Option<(usize, usize)> {
+    if let Some(difference) = &line.difference_string {
+        let mut mismatches = 0;
+        let mut indels = 0;
+        for column in &difference.0 {
+            match column {
+                DifferenceColumn::Mismatch { .. } => mismatches += 1,
+                DifferenceColumn::Insertion { .. } | DifferenceColumn::Deletion { .. } => {
+                    indels += 1
+                }
+                _ => {}
+            }
+        }
+        Some((mismatches, indels))
+    } else {
+        let cigar = line.cigar_string.as_ref()?;
+        let edit_distance = line.total_number_of_mismatches_and_gaps?;
+        let mut gap_length = 0;
+        let mut indels = 0;
+        for column in &cigar.0 {
+            if let CigarColumn::Insertion(length) | CigarColumn::Deletion(length) = column {
+                gap_length += length;
+                indels += 1;
+            }
+        }
+        Some((edit_distance.saturating_sub(gap_length), indels))
+    }
+}
+
+/// Returns the number of positions covered by the given intervals.
+fn union_length(mut intervals: Vec<(usize, usize)>) -> usize {
+    intervals.sort_unstable();
+    let mut length = 0;
+    let mut covered_end = 0;
+    for (start, end) in intervals {
+        let start = start.max(covered_end);
+        if end > start {
+            length += end - start;
+            covered_end = end;
+        }
+    }
+    length
+}
+
+/// Returns the length of the given lengths, sorted in descending order, at which their sum reaches half of the given total.
+fn n50(sorted_lengths: &[usize], total: usize) -> Option<usize> {
+    let mut sum = 0;
+    for length in sorted_lengths {
+        sum += length;
+        if sum * 2 >= total {
+            return Some(*length);
+        }
+    }
+    None
+}
+
+fn ratio(numerator: usize, denominator: usize) -> f64 {
+    if denominator == 0 {
+        0.0
+    } else {
+        numerator as f64 / denominator as f64
+    }
+}
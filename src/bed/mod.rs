@@ -0,0 +1,352 @@
+use crate::data::{DifferenceColumn, PAFLine};
+use crate::error::{Error, Result};
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, Write};
+
+/// A record of a BED file, i.e. a zero-based half-open interval on a sequence.
+///
+/// The columns after the end coordinate, such as name, score and strand, are kept as text.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct BedRecord {
+    /// The name of the sequence, e.g. `chr7`.
+    pub chromosome: String,
+    /// The zero-based start coordinate, inclusive.
+    pub start: usize,
+    /// The zero-based end coordinate, exclusive.
+    pub end: usize,
+    /// The remaining columns, starting with the name.
+    pub extra_fields: Vec<String>,
+}
+
+impl BedRecord {
+    /// Create a new record without extra fields.
+    pub fn new(chromosome: impl Into<String>, start: usize, end: usize) -> Self {
+        Self {
+            chromosome: chromosome.into(),
+            start,
+            end,
+            extra_fields: Vec::new(),
+        }
+    }
+
+    /// Returns the name column (column 4), if present.
+    pub fn name(&self) -> Option<&str> {
+        self.extra_fields.first().map(String::as_str)
+    }
+
+    /// Returns the strand column (column 6), if present and either `+` or `-`.
+    pub fn strand(&self) -> Option<char> {
+        match self.extra_fields.get(2).map(String::as_str) {
+            Some("+") => Some('+'),
+            Some("-") => Some('-'),
+            _ => None,
+        }
+    }
+
+    /// Returns the length of the interval.
+    pub fn len(&self) -> usize {
+        self.end.saturating_sub(self.start)
+    }
+
+    /// Returns true if the interval is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Display for BedRecord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}\t{}\t{}", self.chromosome, self.start, self.end)?;
+        for field in &self.extra_fields {
+            write!(f, "\t{field}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A streaming reader of BED records.
+///
+/// Empty lines, comments and `track` and `browser` lines are skipped.
+#[derive(Debug)]
+pub struct BedReader<Input: BufRead> {
+    input: Input,
+    buffer: String,
+    line_number: usize,
+    byte_offset: u64,
+}
+
+impl<Input: BufRead> BedReader<Input> {
+    /// Create a new reader that reads BED records from the given input.
+    pub fn new(input: Input) -> Self {
+        Self {
+            input,
+            buffer: String::new(),
+            line_number: 0,
+            byte_offset: 0,
+        }
+    }
+
+    /// Read the next record.
+    /// Returns `None` if the input has no further records.
+    pub fn read_record(&mut self) -> Result<Option<BedRecord>> {
+        loop {
+            self.buffer.clear();
+            let line_byte_offset = self.byte_offset;
+            let length = self.input.read_line(&mut self.buffer)?;
+            if length == 0 {
+                return Ok(None);
+            }
+            self.line_number += 1;
+            self.byte_offset += length as u64;
+
+            let line = self.buffer.trim_end_matches(&['\n', '\r'][..]);
+            if line.is_empty()
+                || line.starts_with('#')
+                || line.starts_with("track ")
+                || line.starts_with("browser ")
+            {
+                continue;
+            }
+            return parse_bed_line(line)
+                .map(Some)
+                .map_err(|error| error.relocate(self.line_number, line_byte_offset));
+        }
+    }
+}
+
+impl<Input: BufRead> Iterator for BedReader<Input> {
+    type Item = Result<BedRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+/// The format in which a [BedWriter] writes PAF lines.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BedFormat {
+    /// BED6 on the target, see [target_bed_record].
+    Target,
+    /// BED6 on the query, see [query_bed_record].
+    Query,
+    /// BEDPE linking the query and target intervals, see [format_bedpe].
+    Bedpe,
+    /// BED12 on the target, with one block per run of aligned bases, see [bed12_record].
+    Bed12,
+}
+
+/// A writer that renders PAF lines as BED or BEDPE lines, e.g. for genome browsers or bedtools.
+///
+/// Unmapped lines are skipped.
+#[derive(Debug)]
+pub struct BedWriter<Output: Write> {
+    output: Output,
+    format: BedFormat,
+}
+
+impl<Output: Write> BedWriter<Output> {
+    /// Create a new writer that writes PAF lines in the given format to the given output.
+    pub fn new(output: Output, format: BedFormat) -> Self {
+        Self { output, format }
+    }
+
+    /// Write the given line, terminated by a newline.
+    pub fn write_line(&mut self, line: &PAFLine) -> std::io::Result<()> {
+        if line.is_unmapped() {
+            return Ok(());
+        }
+        match self.format {
+            BedFormat::Target => writeln!(self.output, "{}", target_bed_record(line)),
+            BedFormat::Query => writeln!(self.output, "{}", query_bed_record(line)),
+            BedFormat::Bedpe => writeln!(self.output, "{}", format_bedpe(line)),
+            BedFormat::Bed12 => writeln!(self.output, "{}", bed12_record(line)),
+        }
+    }
+
+    /// Flush the underlying output.
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.output.flush()
+    }
+
+    /// Returns the underlying output.
+    pub fn into_inner(self) -> Output {
+        self.output
+    }
+}
+
+/// Returns the BED6 record of the aligned interval of the target,
+/// named after the query, with the mapping quality as score and the strand of the alignment.
+pub fn target_bed_record(line: &PAFLine) -> BedRecord {
+    BedRecord {
+        chromosome: line.target_sequence_name.clone(),
+        start: line.target_start_coordinate_on_original_strand,
+        end: line.target_end_coordinate_on_original_strand,
+        extra_fields: vec![
+            line.query_sequence_name.clone(),
+            line.mapping_quality.to_string(),
+            line.strand.to_string(),
+        ],
+    }
+}
+
+/// Returns the BED6 record of the aligned interval of the query,
+/// named after the target, with the mapping quality as score and the strand of the alignment.
+pub fn query_bed_record(line: &PAFLine) -> BedRecord {
+    BedRecord {
+        chromosome: line.query_sequence_name.clone(),
+        start: line.query_start_coordinate,
+        end: line.query_end_coordinate,
+        extra_fields: vec![
+            line.target_sequence_name.clone(),
+            line.mapping_quality.to_string(),
+            line.strand.to_string(),
+        ],
+    }
+}
+
+/// Returns the BEDPE line, without line terminator, linking the aligned intervals of the query and the target.
+///
+/// The query is the first interval, always on the `+` strand, and the target is the second interval,
+/// on the strand of the alignment. The name is the query name and the score is the mapping quality.
+pub fn format_bedpe(line: &PAFLine) -> String {
+    format!(
+        "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t+\t{}",
+        line.query_sequence_name,
+        line.query_start_coordinate,
+        line.query_end_coordinate,
+        line.target_sequence_name,
+        line.target_start_coordinate_on_original_strand,
+        line.target_end_coordinate_on_original_strand,
+        line.query_sequence_name,
+        line.mapping_quality,
+        line.strand,
+    )
+}
+
+/// Returns the BED12 record of the aligned interval of the target, with the fields of [target_bed_record].
+///
+/// Each block is a run of aligned bases, taken from the cigar string or, if it is missing, from the alignment difference string.
+/// Deletions and introns (`N`) split blocks, while insertions, matches and mismatches do not.
+/// Deletions at the ends of the alignment are not part of the record.
+/// If the line has neither string, the record has a single block.
+pub fn bed12_record(line: &PAFLine) -> BedRecord {
+    let alignment_start = line.target_start_coordinate_on_original_strand;
+    let mut blocks = aligned_blocks(line)
+        .filter(|blocks| !blocks.is_empty())
+        .unwrap_or_else(|| {
+            vec![(
+                0,
+                line.target_end_coordinate_on_original_strand
+                    .saturating_sub(alignment_start),
+            )]
+        });
+    let (first_start, _) = blocks[0];
+    let (last_start, last_size) = blocks[blocks.len() - 1];
+    let start = alignment_start + first_start;
+    let end = alignment_start + last_start + last_size;
+    for (block_start, _) in &mut blocks {
+        *block_start -= first_start;
+    }
+
+    let mut record = target_bed_record(line);
+    record.start = start;
+    record.end = end;
+    let mut block_sizes = String::new();
+    let mut block_starts = String::new();
+    for (block_start, block_size) in &blocks {
+        block_sizes.push_str(&format!("{block_size},"));
+        block_starts.push_str(&format!("{block_start},"));
+    }
+    record.extra_fields.extend([
+        start.to_string(),
+        end.to_string(),
+        "0".to_string(),
+        blocks.len().to_string(),
+        block_sizes,
+        block_starts,
+    ]);
+    record
+}
+
+/// Returns the runs of aligned bases as start relative to the target start and length.
+fn aligned_blocks(line: &PAFLine) -> Option<Vec<(usize, usize)>> {
+    // the target length of each operation, and whether it is a gap between blocks
+    let operations: Vec<(usize, bool)> = if let Some(cigar) = &line.cigar_string {
+        cigar
+            .0
+            .iter()
+            .filter(|column| column.consumes_target())
+            .map(|column| (column.length(), !column.consumes_query()))
+            .collect()
+    } else if let Some(difference) = &line.difference_string {
+        difference
+            .0
+            .iter()
+            .filter_map(|column| match column {
+                DifferenceColumn::Match { length } => Some((*length, false)),
+                DifferenceColumn::IdenticalSequence { sequence } => Some((sequence.len(), false)),
+                DifferenceColumn::Insertion { .. } => None,
+                DifferenceColumn::Deletion {
+                    missing_query_characters,
+                } => Some((missing_query_characters.len(), true)),
+                DifferenceColumn::Mismatch { .. } => Some((1, false)),
+                DifferenceColumn::Intron { length, .. } => Some((*length, true)),
+            })
+            .collect()
+    } else {
+        return None;
+    };
+
+    let mut blocks = Vec::new();
+    let mut offset = 0;
+    let mut block_start = 0;
+    for (length, is_gap) in operations {
+        if is_gap {
+            if offset > block_start {
+                blocks.push((block_start, offset - block_start));
+            }
+            block_start = offset + length;
+        }
+        offset += length;
+    }
+    if offset > block_start {
+        blocks.push((block_start, offset - block_start));
+    }
+    Some(blocks)
+}
+
+/// Parses a single line of a BED file, without line terminator.
+pub fn parse_bed_line(line: &str) -> Result<BedRecord> {
+    let mut columns = line.split('\t');
+    let mut byte_offset = 0;
+    let mut next_column = |index: usize| {
+        let column = columns.next();
+        let column_byte_offset = byte_offset;
+        if let Some(column) = column {
+            byte_offset += column.len() + 1;
+        }
+        column
+            .map(|column| (column, column_byte_offset))
+            .ok_or_else(|| Error::UnexpectedEndOfLine.locate(1, index, column_byte_offset as u64))
+    };
+
+    let (chromosome, _) = next_column(1)?;
+    let mut parse_coordinate = |index: usize| {
+        let (column, column_byte_offset) = next_column(index)?;
+        column.parse().map_err(|_| {
+            Error::ColumnParseError {
+                text: column.to_string(),
+            }
+            .locate(1, index, column_byte_offset as u64)
+        })
+    };
+    let start = parse_coordinate(2)?;
+    let end = parse_coordinate(3)?;
+
+    Ok(BedRecord {
+        chromosome: chromosome.to_string(),
+        start,
+        end,
+        extra_fields: columns.map(str::to_string).collect(),
+    })
+}
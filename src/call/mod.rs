@@ -0,0 +1,374 @@
+use crate::data::{DifferenceColumn, PAFLine, Strand};
+use crate::sam::QuerySequences;
+use std::collections::HashMap;
+use std::io::Write;
+
+/// Options for calling variants from alignments, with the defaults of `paftools.js call`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CallOptions {
+    /// Alignments with fewer bases and gaps (column 11) do not count towards the coverage.
+    pub min_coverage_length: usize,
+    /// Alignments with fewer bases and gaps (column 11) are not used to call variants.
+    pub min_variant_length: usize,
+    /// Alignments with a lower mapping quality are ignored.
+    pub min_mapping_quality: u8,
+}
+
+impl Default for CallOptions {
+    fn default() -> Self {
+        Self {
+            min_coverage_length: 10_000,
+            min_variant_length: 50_000,
+            min_mapping_quality: 5,
+        }
+    }
+}
+
+impl CallOptions {
+    /// Count only alignments with at least the given number of bases and gaps towards the coverage.
+    pub fn with_min_coverage_length(mut self, min_coverage_length: usize) -> Self {
+        self.min_coverage_length = min_coverage_length;
+        self
+    }
+
+    /// Call variants only from alignments with at least the given number of bases and gaps.
+    pub fn with_min_variant_length(mut self, min_variant_length: usize) -> Self {
+        self.min_variant_length = min_variant_length;
+        self
+    }
+
+    /// Ignore alignments with a mapping quality lower than the given one.
+    pub fn with_min_mapping_quality(mut self, min_mapping_quality: u8) -> Self {
+        self.min_mapping_quality = min_mapping_quality;
+        self
+    }
+}
+
+/// A variant of a query against the target, i.e. an SNV, an insertion or a deletion.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Variant {
+    /// The name of the target sequence.
+    pub target_name: String,
+    /// The zero-based position of the first base of the reference allele on the target.
+    pub position: usize,
+    /// The reference allele, in uppercase.
+    /// For indels, it starts with the base before the indel, as in VCF.
+    pub reference: String,
+    /// The alternative allele, in uppercase.
+    /// For indels, it starts with the base before the indel, as in VCF.
+    pub alternative: String,
+    /// The name of the query sequence.
+    pub query_name: String,
+    /// The zero-based start of the variant on the original strand of the query.
+    /// For deletions, this is the boundary between the two query bases flanking the deletion.
+    pub query_position: usize,
+    /// The strand of the alignment the variant was called from.
+    pub strand: Strand,
+}
+
+/// Calls SNVs and indels from the alignment difference strings of assembly-to-reference alignments, like `paftools.js call`.
+///
+/// Variants are only called in regions of the target that are covered by exactly one alignment,
+/// counting the alignments that pass [CallOptions::min_coverage_length] and [CallOptions::min_mapping_quality].
+/// Alignments without alignment difference string count towards the coverage, but do not yield variants.
+///
+/// The bases of the alignment difference string are on the forward strand of the target, also for alignments on the reverse strand,
+/// so the alleles are taken from it as is.
+/// The base before an indel is taken from the alignment difference string if it is a mismatch or in the long form,
+/// otherwise from the reference sequences if given.
+/// Indels whose preceding base is not known are skipped, so without reference sequences,
+/// most indels are only called from alignment difference strings in the long form (`--cs=long`).
+#[derive(Clone, Debug, Default)]
+pub struct VariantCaller {
+    options: CallOptions,
+    reference: Option<QuerySequences>,
+}
+
+impl VariantCaller {
+    /// Create a new variant caller with the given options.
+    pub fn new(options: CallOptions) -> Self {
+        Self {
+            options,
+            reference: None,
+        }
+    }
+
+    /// Create a new variant caller with the given options,
+    /// taking the bases before indels from the given target sequences, e.g. read from a FASTA file.
+    pub fn with_reference(options: CallOptions, reference: QuerySequences) -> Self {
+        Self {
+            options,
+            reference: Some(reference),
+        }
+    }
+
+    /// Call the variants of the given alignments.
+    ///
+    /// The variants are ordered by the first occurrence of their target in the given lines, and then by position.
+    pub fn call(&self, lines: &[PAFLine]) -> Vec<Variant> {
+        let target_order = target_order(lines);
+        let single_coverage = self.single_coverage_regions(lines);
+
+        let mut variants = Vec::new();
+        for line in lines {
+            if line.is_unmapped()
+                || line.mapping_quality < self.options.min_mapping_quality
+                || line.number_of_bases_and_gaps < self.options.min_variant_length
+            {
+                continue;
+            }
+            let regions = match single_coverage.get(line.target_sequence_name.as_str()) {
+                Some(regions) => regions,
+                None => continue,
+            };
+            variants.extend(
+                self.line_variants(line)
+                    .into_iter()
+                    .filter(|variant| is_covered(regions, variant)),
+            );
+        }
+
+        variants
+            .sort_by_key(|variant| (target_order[variant.target_name.as_str()], variant.position));
+        variants
+    }
+
+    /// Call the variants of the given alignments and write them as VCF 4.2 with the given sample name.
+    ///
+    /// The `##contig` header lines list the targets of the given lines, and all variants have the haploid genotype `1`.
+    /// Returns the number of variants written.
+    pub fn write_vcf(
+        &self,
+        lines: &[PAFLine],
+        sample_name: &str,
+        mut output: impl Write,
+    ) -> std::io::Result<usize> {
+        writeln!(output, "##fileformat=VCFv4.2")?;
+        writeln!(output, "##source={}", env!("CARGO_PKG_NAME"))?;
+        let mut target_order = target_order(lines).into_iter().collect::<Vec<_>>();
+        target_order.sort_by_key(|(_, index)| *index);
+        for (name, _) in target_order {
+            let length = lines
+                .iter()
+                .find(|line| line.target_sequence_name == name)
+                .map(|line| line.target_sequence_length)
+                .unwrap_or_default();
+            writeln!(output, "##contig=<ID={name},length={length}>")?;
+        }
+        writeln!(
+            output,
+            "##INFO=<ID=QNAME,Number=1,Type=String,Description=\"Name of the query sequence\">"
+        )?;
+        writeln!(output, "##INFO=<ID=QSTART,Number=1,Type=Integer,Description=\"Zero-based start of the variant on the original strand of the query\">")?;
+        writeln!(
+            output,
+            "##INFO=<ID=QSTRAND,Number=1,Type=String,Description=\"Strand of the alignment\">"
+        )?;
+        writeln!(
+            output,
+            "##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">"
+        )?;
+        writeln!(
+            output,
+            "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\t{sample_name}"
+        )?;
+
+        let variants = self.call(lines);
+        for variant in &variants {
+            writeln!(
+                output,
+                "{}\t{}\t.\t{}\t{}\t.\tPASS\tQNAME={};QSTART={};QSTRAND={}\tGT\t1",
+                variant.target_name,
+                variant.position + 1,
+                variant.reference,
+                variant.alternative,
+                variant.query_name,
+                variant.query_position,
+                variant.strand,
+            )?;
+        }
+        Ok(variants.len())
+    }
+
+    /// Returns the regions of each target that are covered by exactly one alignment, sorted by start.
+    fn single_coverage_regions<'line>(
+        &self,
+        lines: &'line [PAFLine],
+    ) -> HashMap<&'line str, Vec<(usize, usize)>> {
+        let mut events: HashMap<&str, Vec<(usize, isize)>> = HashMap::new();
+        for line in lines {
+            if line.is_unmapped()
+                || line.mapping_quality < self.options.min_mapping_quality
+                || line.number_of_bases_and_gaps < self.options.min_coverage_length
+            {
+                continue;
+            }
+            let target_events = events.entry(&line.target_sequence_name).or_default();
+            target_events.push((line.target_start_coordinate_on_original_strand, 1));
+            target_events.push((line.target_end_coordinate_on_original_strand, -1));
+        }
+
+        events
+            .into_iter()
+            .map(|(name, mut events)| {
+                events.sort_unstable();
+                let mut regions = Vec::new();
+                let mut depth = 0;
+                let mut previous_position = 0;
+                for (position, change) in events {
+                    if depth == 1 && position > previous_position {
+                        match regions.last_mut() {
+                            Some((_, end)) if *end == previous_position => *end = position,
+                            _ => regions.push((previous_position, position)),
+                        }
+                    }
+                    depth += change;
+                    previous_position = position;
+                }
+                (name, regions)
+            })
+            .collect()
+    }
+
+    /// Returns all variants of the given line, regardless of coverage.
+    fn line_variants(&self, line: &PAFLine) -> Vec<Variant> {
+        let mut variants = Vec::new();
+        let difference = match &line.difference_string {
+            Some(difference) => difference,
+            None => return variants,
+        };
+
+        let mut target_position = line.target_start_coordinate_on_original_strand;
+        let mut query_offset = 0;
+        // the target base before the current column, if known from the alignment difference string
+        let mut previous_base = None;
+        let variant = |position, reference: String, alternative: String, query_position| Variant {
+            target_name: line.target_sequence_name.clone(),
+            position,
+            reference,
+            alternative,
+            query_name: line.query_sequence_name.clone(),
+            query_position,
+            strand: line.strand,
+        };
+
+        for column in &difference.0 {
+            match column {
+                DifferenceColumn::Match { length } => {
+                    target_position += length;
+                    query_offset += length;
+                    previous_base = None;
+                }
+                DifferenceColumn::IdenticalSequence { sequence } => {
+                    target_position += sequence.len();
+                    query_offset += sequence.len();
+                    previous_base = sequence.chars().last();
+                }
+                DifferenceColumn::Mismatch { reference, query } => {
+                    if let Some(query_position) = line.aligned_to_query(query_offset) {
+                        variants.push(variant(
+                            target_position,
+                            reference.to_ascii_uppercase().to_string(),
+                            query.to_ascii_uppercase().to_string(),
+                            query_position,
+                        ));
+                    }
+                    target_position += 1;
+                    query_offset += 1;
+                    previous_base = Some(*reference);
+                }
+                DifferenceColumn::Insertion {
+                    superfluous_query_characters,
+                } => {
+                    let length = superfluous_query_characters.len();
+                    // the lower boundary of the inserted bases on the original strand of the query
+                    let query_boundary = if line.strand == Strand::Reverse {
+                        query_offset + length
+                    } else {
+                        query_offset
+                    };
+                    if let (Some(query_position), Some(anchor)) = (
+                        line.aligned_boundary_to_query(query_boundary),
+                        self.anchor_base(line, target_position, previous_base),
+                    ) {
+                        variants.push(variant(
+                            target_position.saturating_sub(1),
+                            anchor.to_string(),
+                            format!("{anchor}{superfluous_query_characters}").to_ascii_uppercase(),
+                            query_position,
+                        ));
+                    }
+                    query_offset += length;
+                }
+                DifferenceColumn::Deletion {
+                    missing_query_characters,
+                } => {
+                    if let (Some(query_position), Some(anchor)) = (
+                        line.aligned_boundary_to_query(query_offset),
+                        self.anchor_base(line, target_position, previous_base),
+                    ) {
+                        variants.push(variant(
+                            target_position.saturating_sub(1),
+                            format!("{anchor}{missing_query_characters}").to_ascii_uppercase(),
+                            anchor.to_string(),
+                            query_position,
+                        ));
+                    }
+                    target_position += missing_query_characters.len();
+                    previous_base = missing_query_characters.chars().last();
+                }
+                DifferenceColumn::Intron { length, .. } => {
+                    target_position += length;
+                    previous_base = None;
+                }
+            }
+        }
+
+        variants
+    }
+
+    /// Returns the uppercase target base before the given position, if it is known.
+    fn anchor_base(
+        &self,
+        line: &PAFLine,
+        position: usize,
+        previous_base: Option<char>,
+    ) -> Option<char> {
+        previous_base
+            .or_else(|| {
+                let sequence = self.reference.as_ref()?.get(&line.target_sequence_name)?;
+                sequence
+                    .sequence
+                    .as_bytes()
+                    .get(position.checked_sub(1)?)
+                    .map(|base| char::from(*base))
+            })
+            .map(|base| base.to_ascii_uppercase())
+    }
+}
+
+/// Returns the index of the first occurrence of each target in the given lines.
+fn target_order(lines: &[PAFLine]) -> HashMap<&str, usize> {
+    let mut order = HashMap::new();
+    for line in lines {
+        if !line.is_unmapped() {
+            let next_index = order.len();
+            order
+                .entry(line.target_sequence_name.as_str())
+                .or_insert(next_index);
+        }
+    }
+    order
+}
+
+/// Returns true if all target bases of the reference allele of the given variant lie within a single-coverage region.
+fn is_covered(regions: &[(usize, usize)], variant: &Variant) -> bool {
+    let start = variant.position;
+    let end = start + variant.reference.len();
+    let index = regions.partition_point(|(_, region_end)| *region_end <= start);
+    regions
+        .get(index)
+        .map_or(false, |(region_start, region_end)| {
+            *region_start <= start && end <= *region_end
+        })
+}
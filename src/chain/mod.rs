@@ -0,0 +1,460 @@
+use crate::data::{Cigar, CigarColumn, PAFLine, Strand};
+use crate::error::{Error, Result};
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, Write};
+
+/// A record of a UCSC chain file, as used by `liftOver` and `CrossMap`.
+///
+/// See the [format description](https://genome.ucsc.edu/goldenPath/help/chain.html) for more information.
+/// The target is always on the forward strand.
+/// If the query is on the reverse strand, the query coordinates are on the reverse complement of the query.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ChainRecord {
+    /// The score of the chain.
+    pub score: i64,
+    /// The name of the target sequence.
+    pub target_name: String,
+    /// The length of the target sequence.
+    pub target_size: usize,
+    /// The start of the chain on the target.
+    pub target_start: usize,
+    /// The end of the chain on the target.
+    pub target_end: usize,
+    /// The name of the query sequence.
+    pub query_name: String,
+    /// The length of the query sequence.
+    pub query_size: usize,
+    /// The strand of the query, either [Strand::Forward] or [Strand::Reverse].
+    pub query_strand: Strand,
+    /// The start of the chain on the query strand given by `query_strand`.
+    pub query_start: usize,
+    /// The end of the chain on the query strand given by `query_strand`.
+    pub query_end: usize,
+    /// The identifier of the chain.
+    pub id: usize,
+    /// The ungapped blocks of the chain.
+    pub blocks: Vec<ChainBlock>,
+}
+
+/// An ungapped block of a chain, followed by the gaps to the next block.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ChainBlock {
+    /// The length of the ungapped block.
+    pub size: usize,
+    /// The number of target bases between the end of this block and the start of the next one (`dt`).
+    pub target_gap: usize,
+    /// The number of query bases between the end of this block and the start of the next one (`dq`).
+    pub query_gap: usize,
+}
+
+impl ChainRecord {
+    /// Converts the given line into a chain record with the given identifier.
+    ///
+    /// The blocks are taken from the cigar string or, if it is missing, from the alignment difference string.
+    /// The score is taken from the DP alignment score (`AS:i`), or the number of matching bases if it is missing.
+    /// Insertions and deletions before the first or after the last aligned base are not part of the chain,
+    /// so its start and end coordinates exclude them.
+    /// Returns `None` if the line is unmapped or has neither a cigar nor an alignment difference string.
+    pub fn from_paf_line(line: &PAFLine, id: usize) -> Option<Self> {
+        let mut blocks: Vec<ChainBlock> = Vec::new();
+        // gaps before the first block are (target, query) bases outside of the chain
+        let mut leading_gap = (0, 0);
+        let mut pending_gap = (0, 0);
+        for operation in line.alignment_operations()? {
+            if operation.query_length > 0 && operation.target_length > 0 {
+                match blocks.last_mut() {
+                    Some(last) if pending_gap == (0, 0) => last.size += operation.query_length,
+                    Some(last) => {
+                        last.target_gap = pending_gap.0;
+                        last.query_gap = pending_gap.1;
+                        blocks.push(ChainBlock::new(operation.query_length));
+                    }
+                    None => blocks.push(ChainBlock::new(operation.query_length)),
+                }
+                pending_gap = (0, 0);
+            } else if blocks.is_empty() {
+                leading_gap.0 += operation.target_length;
+                leading_gap.1 += operation.query_length;
+            } else {
+                pending_gap.0 += operation.target_length;
+                pending_gap.1 += operation.query_length;
+            }
+        }
+        // the pending gap is now the gap after the last block
+        if blocks.is_empty() {
+            return None;
+        }
+
+        let (query_start, query_end) = if line.strand == Strand::Reverse {
+            (
+                line.query_sequence_length - line.query_end_coordinate,
+                line.query_sequence_length - line.query_start_coordinate,
+            )
+        } else {
+            (line.query_start_coordinate, line.query_end_coordinate)
+        };
+        let query_start = query_start + leading_gap.1;
+        let query_end = query_end - pending_gap.1;
+
+        Some(Self {
+            score: line
+                .dp_alignment_score
+                .map(|score| score as i64)
+                .unwrap_or(line.number_of_matching_bases as i64),
+            target_name: line.target_sequence_name.clone(),
+            target_size: line.target_sequence_length,
+            target_start: line.target_start_coordinate_on_original_strand + leading_gap.0,
+            target_end: line.target_end_coordinate_on_original_strand - pending_gap.0,
+            query_name: line.query_sequence_name.clone(),
+            query_size: line.query_sequence_length,
+            query_strand: line.strand,
+            query_start,
+            query_end,
+            id,
+            blocks,
+        })
+    }
+
+    /// Converts this chain record into a PAF line.
+    ///
+    /// Blocks become `M` cigar columns, and gaps become `D` and `I` columns.
+    /// Since chains do not record mismatches, the number of matching bases is the total size of all blocks.
+    /// The mapping quality is set to 255 (missing), and the score is stored as DP alignment score.
+    pub fn to_paf_line(&self) -> PAFLine {
+        let mut cigar = Vec::new();
+        let mut number_of_bases_and_gaps = 0;
+        for block in &self.blocks {
+            cigar.push(CigarColumn::Match(block.size));
+            if block.target_gap > 0 {
+                cigar.push(CigarColumn::Deletion(block.target_gap));
+            }
+            if block.query_gap > 0 {
+                cigar.push(CigarColumn::Insertion(block.query_gap));
+            }
+            number_of_bases_and_gaps += block.size + block.target_gap + block.query_gap;
+        }
+
+        let (query_start, query_end) = if self.query_strand == Strand::Reverse {
+            (
+                self.query_size - self.query_end,
+                self.query_size - self.query_start,
+            )
+        } else {
+            (self.query_start, self.query_end)
+        };
+
+        PAFLine {
+            query_sequence_name: self.query_name.clone(),
+            query_sequence_length: self.query_size,
+            query_start_coordinate: query_start,
+            query_end_coordinate: query_end,
+            strand: self.query_strand,
+            target_sequence_name: self.target_name.clone(),
+            target_sequence_length: self.target_size,
+            target_start_coordinate_on_original_strand: self.target_start,
+            target_end_coordinate_on_original_strand: self.target_end,
+            number_of_matching_bases: self.blocks.iter().map(|block| block.size).sum(),
+            number_of_bases_and_gaps,
+            mapping_quality: 255,
+            dp_alignment_score: Some(self.score as isize),
+            cigar_string: Some(Cigar(cigar)),
+            ..PAFLine::default()
+        }
+    }
+
+    /// Returns the chain with target and query swapped, e.g. to lift over from the query to the target of an alignment.
+    ///
+    /// If the query is on the reverse strand, the order of the blocks is reversed,
+    /// such that the new target stays on the forward strand.
+    pub fn swapped(&self) -> Self {
+        let reverse = self.query_strand == Strand::Reverse;
+        let (target_start, target_end, query_start, query_end) = if reverse {
+            (
+                self.query_size - self.query_end,
+                self.query_size - self.query_start,
+                self.target_size - self.target_end,
+                self.target_size - self.target_start,
+            )
+        } else {
+            (
+                self.query_start,
+                self.query_end,
+                self.target_start,
+                self.target_end,
+            )
+        };
+
+        let mut blocks: Vec<_> = self
+            .blocks
+            .iter()
+            .map(|block| ChainBlock {
+                size: block.size,
+                target_gap: block.query_gap,
+                query_gap: block.target_gap,
+            })
+            .collect();
+        if reverse {
+            // the gaps follow the blocks, so they move one block to the left when reversing
+            blocks.reverse();
+            for index in 0..blocks.len() {
+                let (target_gap, query_gap) = blocks
+                    .get(index + 1)
+                    .map(|block| (block.target_gap, block.query_gap))
+                    .unwrap_or((0, 0));
+                blocks[index].target_gap = target_gap;
+                blocks[index].query_gap = query_gap;
+            }
+        }
+
+        Self {
+            score: self.score,
+            target_name: self.query_name.clone(),
+            target_size: self.query_size,
+            target_start,
+            target_end,
+            query_name: self.target_name.clone(),
+            query_size: self.target_size,
+            query_strand: self.query_strand,
+            query_start,
+            query_end,
+            id: self.id,
+            blocks,
+        }
+    }
+}
+
+impl ChainBlock {
+    fn new(size: usize) -> Self {
+        Self {
+            size,
+            target_gap: 0,
+            query_gap: 0,
+        }
+    }
+}
+
+impl Display for ChainRecord {
+    /// Writes the header line, the block lines and the empty line that terminates the chain.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "chain {} {} {} + {} {} {} {} {} {} {} {}",
+            self.score,
+            self.target_name,
+            self.target_size,
+            self.target_start,
+            self.target_end,
+            self.query_name,
+            self.query_size,
+            self.query_strand,
+            self.query_start,
+            self.query_end,
+            self.id
+        )?;
+        if let Some((last, blocks)) = self.blocks.split_last() {
+            for block in blocks {
+                writeln!(
+                    f,
+                    "{}\t{}\t{}",
+                    block.size, block.target_gap, block.query_gap
+                )?;
+            }
+            writeln!(f, "{}", last.size)?;
+        }
+        writeln!(f)
+    }
+}
+
+/// Convert all given lines to chain records and write them to the given output.
+///
+/// The chains are assigned consecutive identifiers starting from 1.
+/// Lines that cannot be converted, see [ChainRecord::from_paf_line], are skipped.
+/// Returns the number of written chains.
+pub fn write_chains<'line>(
+    lines: impl IntoIterator<Item = &'line PAFLine>,
+    mut output: impl Write,
+) -> std::io::Result<usize> {
+    let mut id = 0;
+    for line in lines {
+        if let Some(chain) = ChainRecord::from_paf_line(line, id + 1) {
+            id += 1;
+            write!(output, "{chain}")?;
+        }
+    }
+    Ok(id)
+}
+
+/// A streaming reader of UCSC chain records.
+///
+/// Comment lines starting with `#` are skipped.
+#[derive(Debug)]
+pub struct ChainReader<Input: BufRead> {
+    input: Input,
+    buffer: String,
+    line_number: usize,
+    byte_offset: u64,
+    line_byte_offset: u64,
+}
+
+impl<Input: BufRead> ChainReader<Input> {
+    /// Create a new reader that reads chain records from the given input.
+    pub fn new(input: Input) -> Self {
+        Self {
+            input,
+            buffer: String::new(),
+            line_number: 0,
+            byte_offset: 0,
+            line_byte_offset: 0,
+        }
+    }
+
+    /// Read the next chain record.
+    /// Returns `None` if the input has no further records.
+    pub fn read_record(&mut self) -> Result<Option<ChainRecord>> {
+        loop {
+            if !self.read_line()? {
+                return Ok(None);
+            }
+            let line = self.buffer.trim();
+            if !line.is_empty() && !line.starts_with('#') {
+                break;
+            }
+        }
+        let mut record = self.parse_header()?;
+
+        loop {
+            if !self.read_line()? {
+                return Err(Error::UnexpectedEndOfFile.locate(
+                    self.line_number,
+                    1,
+                    self.line_byte_offset,
+                ));
+            }
+            let columns = split_columns(&self.buffer);
+            let size = self.parse_number(&columns, 0)?;
+            if columns.len() > 1 {
+                record.blocks.push(ChainBlock {
+                    size,
+                    target_gap: self.parse_number(&columns, 1)?,
+                    query_gap: self.parse_number(&columns, 2)?,
+                });
+            } else {
+                record.blocks.push(ChainBlock::new(size));
+                return Ok(Some(record));
+            }
+        }
+    }
+
+    fn read_line(&mut self) -> Result<bool> {
+        self.buffer.clear();
+        let length = self.input.read_line(&mut self.buffer)?;
+        if length == 0 {
+            return Ok(false);
+        }
+        self.line_number += 1;
+        self.line_byte_offset = self.byte_offset;
+        self.byte_offset += length as u64;
+        Ok(true)
+    }
+
+    /// Parses the header line in the buffer.
+    fn parse_header(&self) -> Result<ChainRecord> {
+        let columns = split_columns(&self.buffer);
+        if columns[0].0 != "chain" {
+            return Err(Error::UnexpectedCharacter {
+                text: columns[0].0.to_string(),
+            }
+            .locate(self.line_number, 1, self.line_byte_offset + columns[0].1));
+        }
+        if columns.len() < 13 {
+            return Err(Error::UnexpectedEndOfLine.locate(
+                self.line_number,
+                columns.len() + 1,
+                self.end_of_line_byte_offset(),
+            ));
+        }
+        let parse_strand = |index: usize| match columns[index] {
+            ("+", _) => Ok(Strand::Forward),
+            ("-", _) => Ok(Strand::Reverse),
+            (text, column_byte_offset) => Err(Error::UnexpectedCharacter {
+                text: text.to_string(),
+            }
+            .locate(
+                self.line_number,
+                index + 1,
+                self.line_byte_offset + column_byte_offset,
+            )),
+        };
+        if parse_strand(4)? != Strand::Forward {
+            return Err(Error::UnexpectedCharacter {
+                text: columns[4].0.to_string(),
+            }
+            .locate(self.line_number, 5, self.line_byte_offset + columns[4].1));
+        }
+
+        Ok(ChainRecord {
+            score: self.parse_number(&columns, 1)?,
+            target_name: columns[2].0.to_string(),
+            target_size: self.parse_number(&columns, 3)?,
+            target_start: self.parse_number(&columns, 5)?,
+            target_end: self.parse_number(&columns, 6)?,
+            query_name: columns[7].0.to_string(),
+            query_size: self.parse_number(&columns, 8)?,
+            query_strand: parse_strand(9)?,
+            query_start: self.parse_number(&columns, 10)?,
+            query_end: self.parse_number(&columns, 11)?,
+            id: self.parse_number(&columns, 12)?,
+            blocks: Vec::new(),
+        })
+    }
+
+    /// Parses the column with the given zero-based index as a number.
+    fn parse_number<T: std::str::FromStr>(
+        &self,
+        columns: &[(&str, u64)],
+        index: usize,
+    ) -> Result<T> {
+        let (text, column_byte_offset) = *columns.get(index).ok_or_else(|| {
+            Error::UnexpectedEndOfLine.locate(
+                self.line_number,
+                index + 1,
+                self.end_of_line_byte_offset(),
+            )
+        })?;
+        text.parse().map_err(|_| {
+            Error::ColumnParseError {
+                text: text.to_string(),
+            }
+            .locate(
+                self.line_number,
+                index + 1,
+                self.line_byte_offset + column_byte_offset,
+            )
+        })
+    }
+
+    /// Returns the byte offset of the end of the current line, without line terminator and trailing whitespace.
+    fn end_of_line_byte_offset(&self) -> u64 {
+        self.line_byte_offset + self.buffer.trim_end().len() as u64
+    }
+}
+
+/// Splits the given line at whitespace into columns, paired with their byte offsets within the line.
+fn split_columns(line: &str) -> Vec<(&str, u64)> {
+    line.split_whitespace()
+        .map(|column| {
+            (
+                column,
+                (column.as_ptr() as usize - line.as_ptr() as usize) as u64,
+            )
+        })
+        .collect()
+}
+
+impl<Input: BufRead> Iterator for ChainReader<Input> {
+    type Item = Result<ChainRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
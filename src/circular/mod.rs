@@ -0,0 +1,347 @@
+use crate::data::{
+    AlignmentDifference, AlignmentType, Cigar, CigarColumn, DifferenceColumn, PAFLine, Strand,
+};
+use crate::projection::ProjectionKind;
+use std::collections::{HashMap, HashSet};
+
+/// The names of the circular sequences, e.g. bacterial chromosomes and plasmids.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CircularSequences {
+    targets: HashSet<String>,
+    queries: HashSet<String>,
+}
+
+impl CircularSequences {
+    /// Mark the target sequences with the given names as circular.
+    pub fn with_targets(mut self, names: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.targets.extend(names.into_iter().map(Into::into));
+        self
+    }
+
+    /// Mark the query sequences with the given names as circular.
+    pub fn with_queries(mut self, names: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.queries.extend(names.into_iter().map(Into::into));
+        self
+    }
+
+    /// Returns true if the target sequence with the given name is circular.
+    pub fn is_circular_target(&self, name: &str) -> bool {
+        self.targets.contains(name)
+    }
+
+    /// Returns true if the query sequence with the given name is circular.
+    pub fn is_circular_query(&self, name: &str) -> bool {
+        self.queries.contains(name)
+    }
+}
+
+/// Merges the pairs of alignments that are split at the origin of a circular target or query into single alignments.
+///
+/// Two alignments of the same query to the same target on the same strand are merged if one of them ends at the end of a circular sequence,
+/// the other one starts at its beginning, and both are contiguous on the other sequence.
+/// The coordinates of the merged alignment on the circular sequence continue past its end,
+/// i.e. its end coordinate is the end coordinate of the wrapped part plus the sequence length.
+///
+/// The cigar and alignment difference strings, the counts and the scores `NM`, `AS`, `ms`, `cm` and `nn` are concatenated or summed
+/// if both alignments have them, and dropped otherwise. The mapping quality is the minimum of both alignments.
+/// Fields that cannot be combined, such as `s1`, `de`, `MD` and `SA`, are dropped,
+/// while the remaining fields are taken from the alignment that comes first on the target.
+///
+/// Unmapped lines and secondary alignments are never merged.
+/// The merged alignment replaces the first of its parts in the given order, and all other lines are kept as they are.
+pub fn merge_circular_alignments(lines: &[PAFLine], circular: &CircularSequences) -> Vec<PAFLine> {
+    // only alignments of the same query to the same target on the same strand that touch an origin can be merged
+    let mut candidates: HashMap<(&str, &str, Strand), Vec<usize>> = HashMap::new();
+    for (index, line) in lines.iter().enumerate() {
+        if is_mergeable(line) && touches_origin(line, circular) {
+            candidates
+                .entry((
+                    &line.query_sequence_name,
+                    &line.target_sequence_name,
+                    line.strand,
+                ))
+                .or_default()
+                .push(index);
+        }
+    }
+
+    let mut partners: Vec<Option<usize>> = vec![None; lines.len()];
+    let mut merged_into = vec![None; lines.len()];
+    for bucket in candidates.values() {
+        for &index in bucket {
+            if partners[index].is_some() || merged_into[index].is_some() {
+                continue;
+            }
+            let line = &lines[index];
+            let partner = bucket.iter().copied().find(|&other_index| {
+                let other = &lines[other_index];
+                other_index != index
+                    && partners[other_index].is_none()
+                    && merged_into[other_index].is_none()
+                    && (wraps(line, other, circular) || wraps(other, line, circular))
+            });
+            if let Some(partner) = partner {
+                partners[index] = Some(partner);
+                merged_into[partner] = Some(index);
+            }
+        }
+    }
+
+    lines
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| merged_into[*index].is_none())
+        .map(|(index, line)| match partners[index] {
+            Some(partner) if wraps(line, &lines[partner], circular) => {
+                merge(line, &lines[partner], circular)
+            }
+            Some(partner) => merge(&lines[partner], line, circular),
+            None => line.clone(),
+        })
+        .collect()
+}
+
+/// How to rotate a circular query such that it starts at the origin of the target.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Rotation {
+    /// True if the query has to be reverse complemented before rotating it, because it is aligned to the reverse strand.
+    pub reverse_complement: bool,
+    /// The number of bases to move from the start to the end of the (reverse complemented) query.
+    pub offset: usize,
+}
+
+/// Returns the rotation that restarts the given circular query at the origin of the given target,
+/// e.g. to normalise the start of a circular bacterial assembly to the reference.
+///
+/// The rotation is computed from the alignment with the most matching bases that starts at the origin of the target,
+/// or that wraps it after [merge_circular_alignments].
+/// The query base aligned to the origin is found through the cigar or alignment difference string, or, if the alignment has neither,
+/// from the query coordinates.
+/// Returns `None` if no primary alignment of the query covers the origin of the target.
+/// Alignments with a query length of zero are ignored, since no rotation can be derived from them.
+pub fn origin_rotation(lines: &[PAFLine], query_name: &str, target_name: &str) -> Option<Rotation> {
+    let line = lines
+        .iter()
+        .filter(|line| {
+            is_mergeable(line)
+                && line.query_sequence_name == query_name
+                && line.target_sequence_name == target_name
+                && line.query_sequence_length > 0
+                && (line.target_start_coordinate_on_original_strand == 0
+                    || line.target_end_coordinate_on_original_strand > line.target_sequence_length)
+        })
+        .max_by_key(|line| line.number_of_matching_bases)?;
+
+    let origin = if line.target_start_coordinate_on_original_strand == 0 {
+        0
+    } else {
+        line.target_sequence_length
+    };
+    let reverse = line.strand == Strand::Reverse;
+    // the boundary between two query bases on its original strand that is aligned to the origin
+    let boundary = match line.project_target_to_query(origin) {
+        Some(projected) if projected.kind == ProjectionKind::Match && reverse => {
+            projected.position + 1
+        }
+        Some(projected) => projected.position,
+        None if reverse => line.query_end_coordinate,
+        None => line.query_start_coordinate,
+    };
+
+    let query_length = line.query_sequence_length;
+    let boundary = boundary % query_length;
+    Some(Rotation {
+        reverse_complement: reverse,
+        offset: if reverse {
+            (query_length - boundary) % query_length
+        } else {
+            boundary
+        },
+    })
+}
+
+fn is_mergeable(line: &PAFLine) -> bool {
+    !line.is_unmapped()
+        && !matches!(
+            line.alignment_type,
+            Some(AlignmentType::Secondary | AlignmentType::SecondaryInversion)
+        )
+}
+
+/// Returns true if the alignment starts or ends at the origin of a circular target or query.
+fn touches_origin(line: &PAFLine, circular: &CircularSequences) -> bool {
+    (circular.is_circular_target(&line.target_sequence_name)
+        && (line.target_start_coordinate_on_original_strand == 0
+            || line.target_end_coordinate_on_original_strand == line.target_sequence_length))
+        || (circular.is_circular_query(&line.query_sequence_name)
+            && (line.query_start_coordinate == 0
+                || line.query_end_coordinate == line.query_sequence_length))
+}
+
+/// Returns true if the second alignment continues the first one across the origin of the target or of the query.
+/// The alignments are in the order of the target, which is the order of their cigar and alignment difference strings.
+fn wraps(first: &PAFLine, second: &PAFLine, circular: &CircularSequences) -> bool {
+    if first.query_sequence_name != second.query_sequence_name
+        || first.target_sequence_name != second.target_sequence_name
+        || first.strand != second.strand
+    {
+        return false;
+    }
+
+    let reverse = first.strand == Strand::Reverse;
+    let target_wraps = circular.is_circular_target(&first.target_sequence_name)
+        && first.target_end_coordinate_on_original_strand == first.target_sequence_length
+        && second.target_start_coordinate_on_original_strand == 0;
+    let target_contiguous = first.target_end_coordinate_on_original_strand
+        == second.target_start_coordinate_on_original_strand;
+    // on the reverse strand, the query is aligned from its end to its start
+    let (query_wraps, query_contiguous) = if reverse {
+        (
+            first.query_start_coordinate == 0
+                && second.query_end_coordinate == second.query_sequence_length,
+            first.query_start_coordinate == second.query_end_coordinate,
+        )
+    } else {
+        (
+            first.query_end_coordinate == first.query_sequence_length
+                && second.query_start_coordinate == 0,
+            first.query_end_coordinate == second.query_start_coordinate,
+        )
+    };
+    let query_wraps = query_wraps && circular.is_circular_query(&first.query_sequence_name);
+
+    (target_wraps && query_contiguous) || (query_wraps && target_contiguous)
+}
+
+/// Merges the given alignments, where the second one continues the first one across an origin, see [wraps].
+fn merge(first: &PAFLine, second: &PAFLine, circular: &CircularSequences) -> PAFLine {
+    let reverse = first.strand == Strand::Reverse;
+    let target_length = first.target_sequence_length;
+    let query_length = first.query_sequence_length;
+    let target_wraps = circular.is_circular_target(&first.target_sequence_name)
+        && first.target_end_coordinate_on_original_strand == target_length
+        && second.target_start_coordinate_on_original_strand == 0;
+
+    // the part that comes first on the original strand of the query
+    let (query_first, query_second) = if reverse {
+        (second, first)
+    } else {
+        (first, second)
+    };
+    let (query_start, query_end) = if target_wraps {
+        (
+            query_first.query_start_coordinate,
+            query_second.query_end_coordinate,
+        )
+    } else {
+        (
+            query_first.query_start_coordinate,
+            query_length + query_second.query_end_coordinate,
+        )
+    };
+    let target_end = if target_wraps {
+        target_length + second.target_end_coordinate_on_original_strand
+    } else {
+        second.target_end_coordinate_on_original_strand
+    };
+
+    let sum = |a: Option<usize>, b: Option<usize>| Some(a? + b?);
+    let sum_signed = |a: Option<isize>, b: Option<isize>| Some(a? + b?);
+    PAFLine {
+        query_start_coordinate: query_start,
+        query_end_coordinate: query_end,
+        target_end_coordinate_on_original_strand: target_end,
+        number_of_matching_bases: first.number_of_matching_bases + second.number_of_matching_bases,
+        number_of_bases_and_gaps: first.number_of_bases_and_gaps + second.number_of_bases_and_gaps,
+        mapping_quality: first.mapping_quality.min(second.mapping_quality),
+        number_of_minimisers: sum(first.number_of_minimisers, second.number_of_minimisers),
+        chaining_score: None,
+        best_secondary_chaining_score: None,
+        total_number_of_mismatches_and_gaps: sum(
+            first.total_number_of_mismatches_and_gaps,
+            second.total_number_of_mismatches_and_gaps,
+        ),
+        unknown_md: None,
+        dp_alignment_score: sum_signed(first.dp_alignment_score, second.dp_alignment_score),
+        supplementary_alignments: None,
+        best_segment_dp_score: sum_signed(
+            first.best_segment_dp_score,
+            second.best_segment_dp_score,
+        ),
+        number_of_ambiguous_bases: sum(
+            first.number_of_ambiguous_bases,
+            second.number_of_ambiguous_bases,
+        ),
+        cigar_string: match (&first.cigar_string, &second.cigar_string) {
+            (Some(first), Some(second)) => Some(concatenate_cigars(first, second)),
+            _ => None,
+        },
+        difference_string: match (&first.difference_string, &second.difference_string) {
+            (Some(first), Some(second)) => Some(concatenate_differences(first, second)),
+            _ => None,
+        },
+        approximate_per_base_sequence_divergence: None,
+        gap_compressed_per_base_sequence_divergence: None,
+        length_of_query_regions_with_repetitive_seeds: None,
+        raw_fields: Vec::new(),
+        original_fields: None,
+        ..first.clone()
+    }
+}
+
+/// Concatenates the given cigars, joining the columns at the junction if they are of the same kind.
+fn concatenate_cigars(first: &Cigar, second: &Cigar) -> Cigar {
+    let mut columns = first.0.clone();
+    let mut remaining = second.0.iter();
+    if let (Some(last), Some(next)) = (columns.last_mut(), second.0.first()) {
+        if std::mem::discriminant(last) == std::mem::discriminant(next) {
+            let length = last.length() + next.length();
+            *last = match last {
+                CigarColumn::Match(_) => CigarColumn::Match(length),
+                CigarColumn::Insertion(_) => CigarColumn::Insertion(length),
+                CigarColumn::Deletion(_) => CigarColumn::Deletion(length),
+                CigarColumn::Mismatch(_) => CigarColumn::Mismatch(length),
+                CigarColumn::SequenceMatch(_) => CigarColumn::SequenceMatch(length),
+                CigarColumn::Skip(_) => CigarColumn::Skip(length),
+                CigarColumn::SoftClip(_) => CigarColumn::SoftClip(length),
+                CigarColumn::HardClip(_) => CigarColumn::HardClip(length),
+                CigarColumn::Padding(_) => CigarColumn::Padding(length),
+            };
+            remaining.next();
+        }
+    }
+    columns.extend(remaining.cloned());
+    Cigar(columns)
+}
+
+/// Concatenates the given alignment difference strings, joining matches at the junction.
+fn concatenate_differences(
+    first: &AlignmentDifference,
+    second: &AlignmentDifference,
+) -> AlignmentDifference {
+    let mut columns = first.0.clone();
+    let mut remaining = second.0.iter();
+    match (columns.last_mut(), second.0.first()) {
+        (
+            Some(DifferenceColumn::Match { length }),
+            Some(DifferenceColumn::Match {
+                length: next_length,
+            }),
+        ) => {
+            *length += next_length;
+            remaining.next();
+        }
+        (
+            Some(DifferenceColumn::IdenticalSequence { sequence }),
+            Some(DifferenceColumn::IdenticalSequence {
+                sequence: next_sequence,
+            }),
+        ) => {
+            sequence.push_str(next_sequence);
+            remaining.next();
+        }
+        _ => {}
+    }
+    columns.extend(remaining.cloned());
+    AlignmentDifference(columns)
+}
@@ -0,0 +1,479 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+#[cfg(feature = "gzip")]
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// The magic bytes at the start of a gzip member.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+/// The magic bytes at the start of a zstd frame.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+/// The maximum number of uncompressed bytes stored in a single BGZF block.
+pub const BGZF_BLOCK_SIZE: usize = 0xff00;
+/// The empty BGZF block that marks the end of a BGZF file.
+#[cfg_attr(not(feature = "gzip"), allow(dead_code))]
+pub(crate) const BGZF_EOF_BLOCK: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00,
+    0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// The compression format of a file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Compression {
+    /// The file is not compressed.
+    None,
+    /// The file is compressed with gzip.
+    Gzip,
+    /// The file is compressed with BGZF, the blocked gzip format used by `bgzip` and `tabix`.
+    /// BGZF files are valid gzip files.
+    Bgzf,
+    /// The file is compressed with zstd.
+    Zstd,
+}
+
+impl Compression {
+    /// Detects the compression format from the magic bytes at the start of the given data.
+    pub fn detect(data: &[u8]) -> Self {
+        if data.starts_with(&ZSTD_MAGIC) {
+            Self::Zstd
+        } else if data.starts_with(&GZIP_MAGIC) {
+            // BGZF blocks have the extra flag set and start their extra field with the `BC` subfield.
+            if data.len() >= 14 && data[3] & 0x04 != 0 && data[12..14] == *b"BC" {
+                Self::Bgzf
+            } else {
+                Self::Gzip
+            }
+        } else {
+            Self::None
+        }
+    }
+
+    /// Guesses the compression format from the extension of the given path.
+    ///
+    /// Files ending in `.gz` are assumed to be BGZF compressed, since BGZF files can be read by all gzip tools.
+    pub fn from_extension(path: impl AsRef<Path>) -> Self {
+        match path
+            .as_ref()
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("gz" | "bgz") => Self::Bgzf,
+            Some("zst" | "zstd") => Self::Zstd,
+            _ => Self::None,
+        }
+    }
+}
+
+/// Open the file at the given path for reading, decompressing it if necessary.
+///
+/// The compression format is detected from the magic bytes at the start of the file.
+/// The returned reader can be passed to e.g. [PafReader::new](crate::input::PafReader::new).
+pub fn open_path(path: impl AsRef<Path>) -> std::io::Result<Box<dyn BufRead + Send>> {
+    decompress(BufReader::new(File::open(path)?))
+}
+
+/// Wrap the given reader into a decompressor, if necessary.
+///
+/// The compression format is detected from the magic bytes at the start of the input.
+pub fn decompress<Input: BufRead + Send + 'static>(
+    mut input: Input,
+) -> std::io::Result<Box<dyn BufRead + Send>> {
+    let compression = Compression::detect(input.fill_buf()?);
+
+    match compression {
+        Compression::None => Ok(Box::new(input)),
+        #[cfg(feature = "gzip")]
+        Compression::Gzip | Compression::Bgzf => Ok(Box::new(BufReader::new(
+            flate2::bufread::MultiGzDecoder::new(input),
+        ))),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Ok(Box::new(BufReader::new(
+            zstd_rs::stream::read::Decoder::with_buffer(input)?,
+        ))),
+        #[allow(unreachable_patterns)]
+        compression => Err(unsupported_compression(compression)),
+    }
+}
+
+/// Create the file at the given path for writing, compressing it according to the extension of the path.
+///
+/// See [Compression::from_extension] for how the extension is interpreted.
+/// The returned writer can be passed to e.g. [PafWriter::new](crate::output::PafWriter::new).
+pub fn create_path(path: impl AsRef<Path>) -> std::io::Result<CompressedWriter<BufWriter<File>>> {
+    let compression = Compression::from_extension(path.as_ref());
+    CompressedWriter::new(BufWriter::new(File::create(path)?), compression)
+}
+
+/// A writer that compresses its output.
+///
+/// Call [finish](CompressedWriter::finish) after writing, to write the end of the compressed stream and to receive errors while doing so.
+pub struct CompressedWriter<Output: Write> {
+    encoder: Encoder<Output>,
+}
+
+enum Encoder<Output: Write> {
+    None(Output),
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::write::GzEncoder<Output>),
+    #[cfg(feature = "gzip")]
+    Bgzf(BgzfWriter<Output>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd_rs::stream::write::Encoder<'static, Output>),
+}
+
+impl<Output: Write> CompressedWriter<Output> {
+    /// Create a new writer that writes to the given output using the given compression format.
+    ///
+    /// Returns an error if support for the compression format was not enabled via its cargo feature.
+    pub fn new(output: Output, compression: Compression) -> std::io::Result<Self> {
+        let encoder = match compression {
+            Compression::None => Encoder::None(output),
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => Encoder::Gzip(flate2::write::GzEncoder::new(
+                output,
+                flate2::Compression::default(),
+            )),
+            #[cfg(feature = "gzip")]
+            Compression::Bgzf => Encoder::Bgzf(BgzfWriter::new(output)),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Encoder::Zstd(zstd_rs::stream::write::Encoder::new(output, 0)?),
+            #[allow(unreachable_patterns)]
+            compression => return Err(unsupported_compression(compression)),
+        };
+
+        Ok(Self { encoder })
+    }
+
+    /// Write the end of the compressed stream and return the underlying output.
+    pub fn finish(self) -> std::io::Result<Output> {
+        match self.encoder {
+            Encoder::None(mut output) => {
+                output.flush()?;
+                Ok(output)
+            }
+            #[cfg(feature = "gzip")]
+            Encoder::Gzip(encoder) => encoder.finish(),
+            #[cfg(feature = "gzip")]
+            Encoder::Bgzf(encoder) => encoder.finish(),
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl<Output: Write> Write for CompressedWriter<Output> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match &mut self.encoder {
+            Encoder::None(output) => output.write(buf),
+            #[cfg(feature = "gzip")]
+            Encoder::Gzip(encoder) => encoder.write(buf),
+            #[cfg(feature = "gzip")]
+            Encoder::Bgzf(encoder) => encoder.write(buf),
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.encoder {
+            Encoder::None(output) => output.flush(),
+            #[cfg(feature = "gzip")]
+            Encoder::Gzip(encoder) => encoder.flush(),
+            #[cfg(feature = "gzip")]
+            Encoder::Bgzf(encoder) => encoder.flush(),
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// A writer that compresses its output into BGZF blocks.
+///
+/// Each block holds at most [BGZF_BLOCK_SIZE] uncompressed bytes.
+/// Flushing the writer ends the current block, so flushing after each line makes every line start at the beginning of a block.
+/// The end-of-file marker block is written by [finish](BgzfWriter::finish), or when the writer is dropped.
+#[cfg(feature = "gzip")]
+#[derive(Debug)]
+pub struct BgzfWriter<Output: Write> {
+    output: Option<Output>,
+    buffer: Vec<u8>,
+    compressed: Vec<u8>,
+    level: flate2::Compression,
+    compressed_offset: u64,
+}
+
+#[cfg(feature = "gzip")]
+impl<Output: Write> BgzfWriter<Output> {
+    /// Create a new writer that writes BGZF blocks to the given output.
+    pub fn new(output: Output) -> Self {
+        Self::with_level(output, flate2::Compression::default())
+    }
+
+    /// Create a new writer that writes BGZF blocks to the given output using the given compression level.
+    pub fn with_level(output: Output, level: flate2::Compression) -> Self {
+        Self {
+            output: Some(output),
+            buffer: Vec::with_capacity(BGZF_BLOCK_SIZE),
+            compressed: Vec::new(),
+            level,
+            compressed_offset: 0,
+        }
+    }
+
+    /// Returns the BGZF virtual offset of the next byte that is written.
+    ///
+    /// The upper 48 bits are the offset of the current block in the compressed output,
+    /// and the lower 16 bits are the offset within the uncompressed data of the block.
+    pub fn virtual_position(&self) -> u64 {
+        (self.compressed_offset << 16) | self.buffer.len() as u64
+    }
+
+    /// Write the remaining data and the end-of-file marker block and return the underlying output.
+    pub fn finish(mut self) -> std::io::Result<Output> {
+        self.write_end()?;
+        Ok(self.output.take().unwrap())
+    }
+
+    fn write_end(&mut self) -> std::io::Result<()> {
+        self.write_block()?;
+        let output = self.output.as_mut().unwrap();
+        output.write_all(&BGZF_EOF_BLOCK)?;
+        output.flush()
+    }
+
+    /// Compress the buffered data into a single block.
+    fn write_block(&mut self) -> std::io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        let mut encoder =
+            flate2::write::DeflateEncoder::new(std::mem::take(&mut self.compressed), self.level);
+        encoder.write_all(&self.buffer)?;
+        self.compressed = encoder.finish()?;
+        let mut crc = flate2::Crc::new();
+        crc.update(&self.buffer);
+
+        let block_size = self.compressed.len() + 26;
+        let block_size_field = u16::try_from(block_size - 1).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "compressed BGZF block is too large",
+            )
+        })?;
+
+        let output = self.output.as_mut().unwrap();
+        output.write_all(&[
+            0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, b'B', b'C',
+            0x02, 0x00,
+        ])?;
+        output.write_all(&block_size_field.to_le_bytes())?;
+        output.write_all(&self.compressed)?;
+        output.write_all(&crc.sum().to_le_bytes())?;
+        output.write_all(&(self.buffer.len() as u32).to_le_bytes())?;
+
+        self.compressed_offset += block_size as u64;
+        self.buffer.clear();
+        self.compressed.clear();
+        Ok(())
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl<Output: Write> Write for BgzfWriter<Output> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let length = buf.len().min(BGZF_BLOCK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..length]);
+        if self.buffer.len() == BGZF_BLOCK_SIZE {
+            self.write_block()?;
+        }
+        Ok(length)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.write_block()?;
+        self.output.as_mut().unwrap().flush()
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl<Output: Write> Drop for BgzfWriter<Output> {
+    fn drop(&mut self) {
+        if self.output.is_some() {
+            let _ = self.write_end();
+        }
+    }
+}
+
+#[cfg_attr(all(feature = "gzip", feature = "zstd"), allow(dead_code))]
+fn unsupported_compression(compression: Compression) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        format!("support for {compression:?} compression is not enabled"),
+    )
+}
+
+/// A reader of BGZF compressed data that supports seeking to virtual offsets.
+///
+/// A virtual offset stores the offset of a block in the compressed input in its upper 48 bits,
+/// and the offset within the uncompressed data of the block in its lower 16 bits.
+#[cfg(feature = "gzip")]
+#[derive(Debug)]
+pub struct BgzfReader<Input: Read> {
+    input: Input,
+    block: Vec<u8>,
+    position: usize,
+    compressed: Vec<u8>,
+    block_offset: u64,
+    next_block_offset: u64,
+}
+
+#[cfg(feature = "gzip")]
+impl<Input: Read> BgzfReader<Input> {
+    /// Create a new reader that reads BGZF blocks from the given input.
+    pub fn new(input: Input) -> Self {
+        Self {
+            input,
+            block: Vec::new(),
+            position: 0,
+            compressed: Vec::new(),
+            block_offset: 0,
+            next_block_offset: 0,
+        }
+    }
+
+    /// Returns the BGZF virtual offset of the next byte that is read.
+    pub fn virtual_position(&self) -> u64 {
+        if self.position == self.block.len() {
+            self.next_block_offset << 16
+        } else {
+            (self.block_offset << 16) | self.position as u64
+        }
+    }
+
+    /// Returns the underlying input.
+    pub fn into_inner(self) -> Input {
+        self.input
+    }
+
+    /// Read and decompress the next block.
+    /// At the end of the input, the block is left empty.
+    fn read_block(&mut self) -> std::io::Result<()> {
+        self.block.clear();
+        self.position = 0;
+        self.block_offset = self.next_block_offset;
+
+        let mut header = [0; 12];
+        let mut length = 0;
+        while length < header.len() {
+            let read = self.input.read(&mut header[length..])?;
+            if read == 0 {
+                if length == 0 {
+                    return Ok(());
+                }
+                return Err(std::io::ErrorKind::UnexpectedEof.into());
+            }
+            length += read;
+        }
+        if header[..2] != GZIP_MAGIC || header[3] & 0x04 == 0 {
+            return Err(malformed_bgzf_block("missing BGZF header"));
+        }
+
+        let extra_length = u16::from_le_bytes([header[10], header[11]]) as usize;
+        self.compressed.resize(extra_length, 0);
+        self.input.read_exact(&mut self.compressed)?;
+        let mut block_size = None;
+        let mut extra = self.compressed.as_slice();
+        while extra.len() >= 4 {
+            let subfield_length = u16::from_le_bytes([extra[2], extra[3]]) as usize;
+            if extra[..2] == *b"BC" && subfield_length == 2 && extra.len() >= 6 {
+                block_size = Some(u16::from_le_bytes([extra[4], extra[5]]) as usize + 1);
+            }
+            extra = &extra[(4 + subfield_length).min(extra.len())..];
+        }
+        let block_size =
+            block_size.ok_or_else(|| malformed_bgzf_block("missing block size subfield"))?;
+        let data_length = block_size
+            .checked_sub(header.len() + extra_length + 8)
+            .ok_or_else(|| malformed_bgzf_block("block size too small"))?;
+
+        self.compressed.resize(data_length + 8, 0);
+        self.input.read_exact(&mut self.compressed)?;
+        let (data, trailer) = self.compressed.split_at(data_length);
+        let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+        let uncompressed_length =
+            u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]) as usize;
+
+        self.block.resize(uncompressed_length, 0);
+        let mut decompress = flate2::Decompress::new(false);
+        decompress
+            .decompress(data, &mut self.block, flate2::FlushDecompress::Finish)
+            .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))?;
+        let mut actual_crc = flate2::Crc::new();
+        actual_crc.update(&self.block);
+        if decompress.total_out() as usize != uncompressed_length || actual_crc.sum() != crc {
+            return Err(malformed_bgzf_block("corrupt block data"));
+        }
+
+        self.next_block_offset += block_size as u64;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl<Input: Read + Seek> BgzfReader<Input> {
+    /// Seek to the given BGZF virtual offset.
+    pub fn seek_virtual(&mut self, virtual_offset: u64) -> std::io::Result<()> {
+        let block_offset = virtual_offset >> 16;
+        let position = (virtual_offset & 0xffff) as usize;
+
+        // avoid decompressing the current block again
+        if block_offset != self.block_offset || self.block.is_empty() {
+            self.input.seek(SeekFrom::Start(block_offset))?;
+            self.next_block_offset = block_offset;
+            self.read_block()?;
+        }
+        if position > self.block.len() {
+            return Err(malformed_bgzf_block("virtual offset out of range"));
+        }
+        self.position = position;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl<Input: Read> Read for BgzfReader<Input> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let available = self.fill_buf()?;
+        let length = available.len().min(buf.len());
+        buf[..length].copy_from_slice(&available[..length]);
+        self.consume(length);
+        Ok(length)
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl<Input: Read> BufRead for BgzfReader<Input> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        while self.position == self.block.len() {
+            let previous_block_offset = self.next_block_offset;
+            self.read_block()?;
+            if self.next_block_offset == previous_block_offset {
+                break;
+            }
+        }
+        Ok(&self.block[self.position..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.position = (self.position + amount).min(self.block.len());
+    }
+}
+
+#[cfg(feature = "gzip")]
+fn malformed_bgzf_block(message: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("malformed BGZF block: {message}"),
+    )
+}
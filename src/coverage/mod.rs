@@ -0,0 +1,346 @@
+use crate::bed::BedRecord;
+use crate::data::{AlignmentType, PAFLine};
+use std::collections::HashMap;
+use std::io::Write;
+
+/// Options for selecting the alignments that count towards the coverage.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CoverageOptions {
+    /// Alignments with a lower mapping quality are ignored.
+    pub min_mapping_quality: u8,
+    /// If true, only primary alignments (`tp:A:P`) are used.
+    pub primary_only: bool,
+    /// Alignments with a lower identity, i.e. matching bases (column 10) per bases and gaps (column 11), are ignored.
+    pub min_identity: f64,
+}
+
+impl CoverageOptions {
+    /// Ignore alignments with a mapping quality lower than the given one.
+    pub fn with_min_mapping_quality(mut self, min_mapping_quality: u8) -> Self {
+        self.min_mapping_quality = min_mapping_quality;
+        self
+    }
+
+    /// Use only primary alignments (`tp:A:P`), if set to true.
+    pub fn with_primary_only(mut self, primary_only: bool) -> Self {
+        self.primary_only = primary_only;
+        self
+    }
+
+    /// Ignore alignments with an identity lower than the given one, between 0 and 1.
+    pub fn with_min_identity(mut self, min_identity: f64) -> Self {
+        self.min_identity = min_identity;
+        self
+    }
+
+    fn accepts(&self, line: &PAFLine) -> bool {
+        let identity = if line.number_of_bases_and_gaps == 0 {
+            0.0
+        } else {
+            line.number_of_matching_bases as f64 / line.number_of_bases_and_gaps as f64
+        };
+        !line.is_unmapped()
+            && line.mapping_quality >= self.min_mapping_quality
+            && (!self.primary_only || line.alignment_type == Some(AlignmentType::Primary))
+            && identity >= self.min_identity
+    }
+}
+
+/// A run of target positions with the same depth.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DepthRun {
+    /// The zero-based start of the run, inclusive.
+    pub start: usize,
+    /// The zero-based end of the run, exclusive.
+    pub end: usize,
+    /// The number of alignments covering each position of the run.
+    pub depth: u32,
+}
+
+/// The run-length encoded depth of coverage of a target sequence.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TargetCoverage {
+    /// The name of the target sequence.
+    pub name: String,
+    /// The length of the target sequence.
+    pub length: usize,
+    /// The runs of equal depth, covering the whole sequence in order, including runs of depth zero.
+    pub runs: Vec<DepthRun>,
+}
+
+impl TargetCoverage {
+    /// Returns the depth at the given position, or zero if it is outside of the sequence.
+    pub fn depth_at(&self, position: usize) -> u32 {
+        let index = self.runs.partition_point(|run| run.end <= position);
+        self.runs
+            .get(index)
+            .filter(|run| run.start <= position)
+            .map_or(0, |run| run.depth)
+    }
+
+    /// Returns the mean depth of the given interval, clipped to the sequence.
+    pub fn mean_depth(&self, start: usize, end: usize) -> f64 {
+        let end = end.min(self.length);
+        if start >= end {
+            return 0.0;
+        }
+
+        let first_run = self.runs.partition_point(|run| run.end <= start);
+        let total: u64 = self.runs[first_run..]
+            .iter()
+            .take_while(|run| run.start < end)
+            .map(|run| (run.end.min(end) - run.start.max(start)) as u64 * run.depth as u64)
+            .sum();
+        total as f64 / (end - start) as f64
+    }
+
+    /// Write the runs with non-zero depth as bedGraph lines.
+    pub fn write_bedgraph(&self, mut output: impl Write) -> std::io::Result<()> {
+        for run in self.runs.iter().filter(|run| run.depth > 0) {
+            writeln!(
+                output,
+                "{}\t{}\t{}\t{}",
+                self.name, run.start, run.end, run.depth
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Write the mean depth of consecutive windows of the given size as TSV lines with the columns name, start, end and mean depth.
+    /// The last window is shorter if the sequence length is not a multiple of the window size.
+    pub fn write_window_means(
+        &self,
+        window_size: usize,
+        mut output: impl Write,
+    ) -> std::io::Result<()> {
+        for start in (0..self.length).step_by(window_size.max(1)) {
+            let end = (start + window_size.max(1)).min(self.length);
+            writeln!(
+                output,
+                "{}\t{start}\t{end}\t{:.4}",
+                self.name,
+                self.mean_depth(start, end)
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Returns the maximal regions with a depth lower than the given one, e.g. the uncovered regions for a minimum depth of 1.
+    pub fn low_coverage_regions(&self, min_depth: u32) -> Vec<BedRecord> {
+        let mut regions: Vec<BedRecord> = Vec::new();
+        for run in self.runs.iter().filter(|run| run.depth < min_depth) {
+            match regions.last_mut() {
+                Some(region) if region.end == run.start => region.end = run.end,
+                _ => regions.push(BedRecord::new(self.name.clone(), run.start, run.end)),
+            }
+        }
+        regions
+    }
+}
+
+/// Accumulates the depth of coverage of target sequences from a stream of alignments, like `samtools depth`.
+///
+/// Each alignment covers its aligned target bases, taken from the cigar string or the alignment difference string,
+/// such that deletions and introns (`N`) are not covered. Alignments with neither string cover their whole target interval.
+/// Coordinates past the end of a target, as produced by [merge_circular_alignments](crate::circular::merge_circular_alignments),
+/// wrap around to its start.
+///
+/// The alignments may come in any order, e.g. grouped by query as output by minimap2.
+/// For each target, only the positions where the depth changes are stored, and they are merged whenever their number doubles.
+/// Since a target has at most one change per position, memory is bounded by the lengths of the targets rather than by the number of alignments,
+/// and adding an alignment takes amortised logarithmic time per cigar or alignment difference column.
+#[derive(Clone, Debug, Default)]
+pub struct CoverageCalculator {
+    options: CoverageOptions,
+    targets: Vec<DepthChanges>,
+    target_indices: HashMap<String, usize>,
+}
+
+/// The positions where the depth of a target changes, and by how much.
+#[derive(Clone, Debug)]
+struct DepthChanges {
+    name: String,
+    length: usize,
+    changes: Vec<(usize, i64)>,
+    /// The number of changes after the last merge.
+    merged_count: usize,
+}
+
+/// The minimum number of changes of a target before they are merged.
+const MIN_MERGE_COUNT: usize = 1024;
+
+impl CoverageCalculator {
+    /// Create a new calculator that counts the alignments selected by the given options.
+    pub fn new(options: CoverageOptions) -> Self {
+        Self {
+            options,
+            ..Self::default()
+        }
+    }
+
+    /// Add the given alignment to the coverage.
+    /// Returns false if the alignment was ignored because of the options, because it is unmapped,
+    /// or because its target end is before its target start.
+    /// The target of an ignored mapped alignment is still reported, with zero depth if no other alignment covers it.
+    pub fn add_line(&mut self, line: &PAFLine) -> bool {
+        if line.is_unmapped() || line.target_sequence_length == 0 {
+            return false;
+        }
+        let targets = &mut self.targets;
+        let index = *self
+            .target_indices
+            .entry(line.target_sequence_name.clone())
+            .or_insert_with(|| {
+                targets.push(DepthChanges {
+                    name: line.target_sequence_name.clone(),
+                    length: line.target_sequence_length,
+                    changes: Vec::new(),
+                    merged_count: 0,
+                });
+                targets.len() - 1
+            });
+        if !self.options.accepts(line)
+            || line.target_end_coordinate_on_original_strand
+                < line.target_start_coordinate_on_original_strand
+        {
+            return false;
+        }
+
+        let target = &mut self.targets[index];
+        let start = line.target_start_coordinate_on_original_strand;
+        if let Some(operations) = line.alignment_operations() {
+            let mut position = start;
+            for operation in operations {
+                if operation.query_length > 0 && operation.target_length > 0 {
+                    target.add_interval(position, position + operation.target_length);
+                }
+                position += operation.target_length;
+            }
+        } else {
+            target.add_interval(start, line.target_end_coordinate_on_original_strand);
+        }
+        if target.changes.len() >= 2 * target.merged_count.max(MIN_MERGE_COUNT) {
+            target.merge();
+        }
+        true
+    }
+
+    /// Returns the coverage of all targets of mapped alignments, in the order of their first alignment,
+    /// including targets whose alignments were all ignored.
+    pub fn finish(self) -> Vec<TargetCoverage> {
+        self.targets
+            .into_iter()
+            .map(DepthChanges::into_coverage)
+            .collect()
+    }
+}
+
+impl DepthChanges {
+    /// Adds one to the depth of the given interval, wrapping around the end of the target.
+    fn add_interval(&mut self, start: usize, end: usize) {
+        let length = self.length;
+        let (start, end) = (start % length, start % length + (end - start).min(length));
+        if end > length {
+            self.changes
+                .extend([(start, 1), (length, -1), (0, 1), (end - length, -1)]);
+        } else if start < end {
+            self.changes.extend([(start, 1), (end, -1)]);
+        }
+    }
+
+    /// Sorts the changes by position and merges the changes at the same position.
+    fn merge(&mut self) {
+        self.changes.sort_unstable_by_key(|(position, _)| *position);
+        let mut merged: Vec<(usize, i64)> = Vec::with_capacity(self.changes.len());
+        for &(position, change) in &self.changes {
+            match merged.last_mut() {
+                Some((last_position, last_change)) if *last_position == position => {
+                    *last_change += change
+                }
+                _ => merged.push((position, change)),
+            }
+        }
+        merged.retain(|(_, change)| *change != 0);
+        self.changes = merged;
+        self.merged_count = self.changes.len();
+    }
+
+    fn into_coverage(mut self) -> TargetCoverage {
+        self.merge();
+        let mut runs = Vec::new();
+        let mut depth = 0;
+        let mut start = 0;
+        for &(position, change) in &self.changes {
+            if position >= self.length {
+                break;
+            }
+            if position > start {
+                runs.push(DepthRun {
+                    start,
+                    end: position,
+                    depth: depth as u32,
+                });
+            }
+            depth += change;
+            start = position;
+        }
+        runs.push(DepthRun {
+            start,
+            end: self.length,
+            depth: depth as u32,
+        });
+
+        TargetCoverage {
+            name: self.name,
+            length: self.length,
+            runs,
+        }
+    }
+}
+
+/// Computes the coverage of all targets of the given alignments, see [CoverageCalculator].
+pub fn compute_coverage<'line>(
+    lines: impl IntoIterator<Item = &'line PAFLine>,
+    options: CoverageOptions,
+) -> Vec<TargetCoverage> {
+    let mut calculator = CoverageCalculator::new(options);
+    for line in lines {
+        calculator.add_line(line);
+    }
+    calculator.finish()
+}
+
+/// Write the coverage of the given targets as bedGraph, see [TargetCoverage::write_bedgraph].
+pub fn write_bedgraph(coverage: &[TargetCoverage], mut output: impl Write) -> std::io::Result<()> {
+    for target in coverage {
+        target.write_bedgraph(&mut output)?;
+    }
+    Ok(())
+}
+
+/// Write the mean depth of windows of the given size as TSV with a header line, see [TargetCoverage::write_window_means].
+pub fn write_window_means(
+    coverage: &[TargetCoverage],
+    window_size: usize,
+    mut output: impl Write,
+) -> std::io::Result<()> {
+    writeln!(output, "chromosome\tstart\tend\tmean_depth")?;
+    for target in coverage {
+        target.write_window_means(window_size, &mut output)?;
+    }
+    Ok(())
+}
+
+/// Write the regions with a depth lower than the given one as BED, see [TargetCoverage::low_coverage_regions].
+pub fn write_low_coverage_regions(
+    coverage: &[TargetCoverage],
+    min_depth: u32,
+    mut output: impl Write,
+) -> std::io::Result<()> {
+    for target in coverage {
+        for region in target.low_coverage_regions(min_depth) {
+            writeln!(output, "{region}")?;
+        }
+    }
+    Ok(())
+}
@@ -3,7 +3,7 @@
 /// The field names are adapted from the [minimap2 man-page][1]. Check it out for more info.
 ///
 /// [1]: https://lh3.github.io/minimap2/minimap2.html#10
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, Default, PartialEq)]
 #[allow(missing_docs)]
 pub struct PAFLine {
     // required fields
@@ -11,7 +11,7 @@ pub struct PAFLine {
     pub query_sequence_length: usize,
     pub query_start_coordinate: usize,
     pub query_end_coordinate: usize,
-    pub strand: bool,
+    pub strand: Strand,
     pub target_sequence_name: String,
     pub target_sequence_length: usize,
     pub target_start_coordinate_on_original_strand: usize,
@@ -38,7 +38,214 @@ pub struct PAFLine {
     pub gap_compressed_per_base_sequence_divergence: Option<f64>,
     pub length_of_query_regions_with_repetitive_seeds: Option<usize>,
 
-    pub unknown_fields: Vec<String>,
+    /// Optional fields with tags that are not known to minimap2.
+    pub unknown_fields: Vec<Tag>,
+
+    /// Known optional fields whose values were kept unparsed, as selected by [FieldMode::Raw](crate::input::FieldMode::Raw).
+    pub raw_fields: Vec<RawTag>,
+
+    /// The original layout of the optional fields, recorded only when parsing in lossless mode.
+    /// If present, the optional fields are written in this layout.
+    pub original_fields: Option<Vec<OriginalField>>,
+}
+
+/// The names of the optional fields known to minimap2, in the order in which minimap2 outputs them.
+///
+/// `cg` and `cs` are not ordered by minimap2, and the remaining fields are ordered as on the [minimap2 man-page](https://lh3.github.io/minimap2/minimap2.html#10).
+pub const KNOWN_TAGS: [[u8; 2]; 16] = [
+    *b"NM", *b"ms", *b"AS", *b"nn", *b"tp", *b"cm", *b"s1", *b"s2", *b"de", *b"rl", *b"MD", *b"SA",
+    *b"ts", *b"cg", *b"cs", *b"dv",
+];
+
+/// The headers of the optional fields that are stored in their own struct fields of [PAFLine].
+pub(crate) const KNOWN_HEADERS: [&str; 16] = [
+    "NM:i:", "ms:i:", "AS:i:", "nn:i:", "tp:A:", "cm:i:", "s1:i:", "s2:i:", "de:f:", "rl:i:",
+    "MD:Z:", "SA:Z:", "ts:A:", "cg:Z:", "cs:Z:", "dv:f:",
+];
+
+/// An optional field as it appeared in the original input, recorded when parsing in lossless mode.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OriginalField {
+    /// The name of the field.
+    pub name: [u8; 2],
+    /// True if the field was stored in its own struct field of [PAFLine],
+    /// and false if it was stored in [PAFLine::unknown_fields], e.g. because it was a duplicate.
+    pub known: bool,
+    /// The original text of the value, if its type has no canonical text representation, i.e. for types `f`, `H` and `B`,
+    /// or if it is an integer that differs from its canonical text representation, e.g. `007` or `+1`.
+    pub text: Option<String>,
+}
+
+impl PAFLine {
+    /// Returns true if this line reports an unmapped query, as output by minimap2 with `--paf-no-hit`.
+    ///
+    /// In this case, the target sequence name is `*` and all coordinates and counts are zero.
+    pub fn is_unmapped(&self) -> bool {
+        self.strand == Strand::Unmapped
+    }
+
+    /// Returns the value of the first unknown field with the given name, e.g. `line.tag(b"zd")`.
+    ///
+    /// Fields known to minimap2 are stored in their own struct fields and are not found by this method.
+    pub fn tag(&self, name: &[u8; 2]) -> Option<&TagValue> {
+        self.unknown_fields
+            .iter()
+            .find(|tag| &tag.name == name)
+            .map(|tag| &tag.value)
+    }
+
+    /// Returns the known field with the given name, if its value was kept unparsed.
+    pub fn raw_tag(&self, name: &[u8; 2]) -> Option<&RawTag> {
+        self.raw_fields.iter().find(|tag| &tag.name == name)
+    }
+
+    /// Returns the cigar string, if it was kept unparsed.
+    /// It can then be parsed lazily by iterating over it.
+    pub fn raw_cigar_string(&self) -> Option<CigarRef<'_>> {
+        self.raw_tag(b"cg").map(|tag| CigarRef(&tag.value))
+    }
+
+    /// Returns the alignment difference string, if it was kept unparsed.
+    /// It can then be parsed lazily by iterating over it.
+    pub fn raw_difference_string(&self) -> Option<AlignmentDifferenceRef<'_>> {
+        self.raw_tag(b"cs")
+            .map(|tag| AlignmentDifferenceRef(&tag.value))
+    }
+
+    /// Returns the value of the first unknown field with the given name, if it is of type `A`.
+    pub fn tag_char(&self, name: &[u8; 2]) -> Option<char> {
+        if let Some(TagValue::Character(value)) = self.tag(name) {
+            Some(*value)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value of the first unknown field with the given name, if it is of type `i`.
+    pub fn tag_i64(&self, name: &[u8; 2]) -> Option<i64> {
+        if let Some(TagValue::Integer(value)) = self.tag(name) {
+            Some(*value)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value of the first unknown field with the given name, if it is of type `f`.
+    pub fn tag_f64(&self, name: &[u8; 2]) -> Option<f64> {
+        if let Some(TagValue::Float(value)) = self.tag(name) {
+            Some(*value)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value of the first unknown field with the given name, if it is of type `Z`.
+    pub fn tag_str(&self, name: &[u8; 2]) -> Option<&str> {
+        if let Some(TagValue::String(value)) = self.tag(name) {
+            Some(value)
+        } else {
+            None
+        }
+    }
+}
+
+/// An optional field whose value was kept unparsed, see [PAFLine::raw_fields].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RawTag {
+    /// The two-character name of the tag.
+    pub name: [u8; 2],
+    /// The SAM type character of the value.
+    pub value_type: u8,
+    /// The unparsed value.
+    pub value: String,
+}
+
+impl RawTag {
+    /// Returns a [TagRef] borrowing from this tag, which allows to parse the value.
+    pub fn as_tag_ref(&self) -> TagRef<'_> {
+        TagRef {
+            name: self.name,
+            value_type: self.value_type,
+            value: &self.value,
+        }
+    }
+}
+
+/// An optional field in SAM format, e.g. `zd:i:3`.
+/// See the [SAM specification](https://samtools.github.io/hts-specs/SAMv1.pdf) for more information.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tag {
+    /// The two-character name of the tag.
+    pub name: [u8; 2],
+    /// The typed value of the tag.
+    pub value: TagValue,
+}
+
+/// The typed value of a [Tag].
+#[derive(Clone, Debug, PartialEq)]
+pub enum TagValue {
+    /// `A`: a printable character.
+    Character(char),
+    /// `i`: a signed integer.
+    Integer(i64),
+    /// `f`: a floating point number.
+    Float(f64),
+    /// `Z`: a string.
+    String(String),
+    /// `H`: a byte array, written as hexadecimal digits.
+    Hex(Vec<u8>),
+    /// `B`: an array of numbers.
+    Array(TagArray),
+}
+
+impl TagValue {
+    /// Returns the SAM type character of this value.
+    pub fn type_character(&self) -> char {
+        match self {
+            TagValue::Character(_) => 'A',
+            TagValue::Integer(_) => 'i',
+            TagValue::Float(_) => 'f',
+            TagValue::String(_) => 'Z',
+            TagValue::Hex(_) => 'H',
+            TagValue::Array(_) => 'B',
+        }
+    }
+}
+
+/// A typed array of numbers, the value of a [Tag] of type `B`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TagArray {
+    /// `c`
+    Int8(Vec<i8>),
+    /// `C`
+    UInt8(Vec<u8>),
+    /// `s`
+    Int16(Vec<i16>),
+    /// `S`
+    UInt16(Vec<u16>),
+    /// `i`
+    Int32(Vec<i32>),
+    /// `I`
+    UInt32(Vec<u32>),
+    /// `f`
+    Float(Vec<f32>),
+}
+
+/// The strand of the query relative to the target.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Strand {
+    /// The query is aligned to the forward strand of the target (`+`).
+    Forward,
+    /// The query is aligned to the reverse strand of the target (`-`).
+    Reverse,
+    /// The query is unmapped (`*`), as output by minimap2 with `--paf-no-hit`.
+    Unmapped,
+}
+
+impl Default for Strand {
+    fn default() -> Self {
+        Self::Forward
+    }
 }
 
 /// The type of a minimap2 alignment. See the [minimap2 readme](https://github.com/lh3/minimap2#algorithm-overview) for more information.
@@ -56,13 +263,89 @@ pub enum AlignmentType {
 pub struct Cigar(pub Vec<CigarColumn>);
 
 /// A column of a CIGAR string. See the [this page](https://www.drive5.com/usearch/manual/cigar.html) for more information.
-#[allow(missing_docs)]
+///
+/// The operators are the full set of the [SAM specification](https://samtools.github.io/hts-specs/SAMv1.pdf).
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum CigarColumn {
+    /// `M`: an alignment match, which can be a sequence match or mismatch.
     Match(usize),
+    /// `I`: an insertion into the target.
     Insertion(usize),
+    /// `D`: a deletion from the target.
     Deletion(usize),
+    /// `X`: a sequence mismatch.
     Mismatch(usize),
+    /// `=`: a sequence match.
+    SequenceMatch(usize),
+    /// `N`: a skipped region of the target, e.g. an intron in a spliced alignment.
+    Skip(usize),
+    /// `S`: a soft clip, i.e. query characters that are not aligned but still present in the query.
+    SoftClip(usize),
+    /// `H`: a hard clip, i.e. query characters that are not aligned and not present in the query.
+    HardClip(usize),
+    /// `P`: padding, i.e. a silent deletion from a padded reference.
+    Padding(usize),
+}
+
+impl Cigar {
+    /// Returns the number of query characters consumed by this cigar.
+    pub fn query_length(&self) -> usize {
+        self.0
+            .iter()
+            .filter(|column| column.consumes_query())
+            .map(CigarColumn::length)
+            .sum()
+    }
+
+    /// Returns the number of target characters consumed by this cigar.
+    pub fn target_length(&self) -> usize {
+        self.0
+            .iter()
+            .filter(|column| column.consumes_target())
+            .map(CigarColumn::length)
+            .sum()
+    }
+}
+
+impl CigarColumn {
+    /// Returns the length of this column.
+    pub fn length(&self) -> usize {
+        match self {
+            CigarColumn::Match(length)
+            | CigarColumn::Insertion(length)
+            | CigarColumn::Deletion(length)
+            | CigarColumn::Mismatch(length)
+            | CigarColumn::SequenceMatch(length)
+            | CigarColumn::Skip(length)
+            | CigarColumn::SoftClip(length)
+            | CigarColumn::HardClip(length)
+            | CigarColumn::Padding(length) => *length,
+        }
+    }
+
+    /// Returns true if this column consumes characters of the query.
+    pub fn consumes_query(&self) -> bool {
+        matches!(
+            self,
+            CigarColumn::Match(_)
+                | CigarColumn::Insertion(_)
+                | CigarColumn::Mismatch(_)
+                | CigarColumn::SequenceMatch(_)
+                | CigarColumn::SoftClip(_)
+        )
+    }
+
+    /// Returns true if this column consumes characters of the target.
+    pub fn consumes_target(&self) -> bool {
+        matches!(
+            self,
+            CigarColumn::Match(_)
+                | CigarColumn::Deletion(_)
+                | CigarColumn::Mismatch(_)
+                | CigarColumn::SequenceMatch(_)
+                | CigarColumn::Skip(_)
+        )
+    }
 }
 
 /// An alignment difference string. See the [minimap2 man-page](https://lh3.github.io/minimap2/minimap2.html#10) for more information.
@@ -73,9 +356,14 @@ pub struct AlignmentDifference(pub Vec<DifferenceColumn>);
 #[allow(missing_docs)]
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum DifferenceColumn {
+    /// `:`: a match of the given length, as written in the short form.
     Match {
         length: usize,
     },
+    /// `=`: a match of the given sequence, as written in the long form (`--cs=long`).
+    IdenticalSequence {
+        sequence: String,
+    },
     Insertion {
         superfluous_query_characters: String,
     },
@@ -86,4 +374,274 @@ pub enum DifferenceColumn {
         reference: char,
         query: char,
     },
+    /// `~`: an intron in a spliced alignment.
+    /// The length is the length of the whole intron, including the donor and acceptor sites.
+    Intron {
+        donor: String,
+        length: usize,
+        acceptor: String,
+    },
+}
+
+impl AlignmentDifference {
+    /// Returns true if this alignment difference string is in the long form (`--cs=long`),
+    /// i.e. it contains no [DifferenceColumn::Match], but at least one [DifferenceColumn::IdenticalSequence].
+    pub fn is_long_form(&self) -> bool {
+        self.0
+            .iter()
+            .all(|column| !matches!(column, DifferenceColumn::Match { .. }))
+            && self
+                .0
+                .iter()
+                .any(|column| matches!(column, DifferenceColumn::IdenticalSequence { .. }))
+    }
+
+    /// Converts this alignment difference string into the short form,
+    /// i.e. replaces all [DifferenceColumn::IdenticalSequence] with [DifferenceColumn::Match].
+    pub fn to_short_form(&self) -> Self {
+        Self(
+            self.0
+                .iter()
+                .map(|column| match column {
+                    DifferenceColumn::IdenticalSequence { sequence } => DifferenceColumn::Match {
+                        length: sequence.chars().count(),
+                    },
+                    other => other.clone(),
+                })
+                .collect(),
+        )
+    }
+
+    /// Converts this alignment difference string into the long form,
+    /// i.e. replaces all [DifferenceColumn::Match] with [DifferenceColumn::IdenticalSequence].
+    ///
+    /// The sequences of the matches are taken from the given target sequence,
+    /// which must be the aligned part of the target on its original strand.
+    /// Returns `None` if the given target sequence is too short.
+    pub fn to_long_form(&self, target: &str) -> Option<Self> {
+        let mut target = target;
+        let mut result = Vec::with_capacity(self.0.len());
+
+        for column in &self.0 {
+            let target_length = match column {
+                DifferenceColumn::Match { length } => {
+                    let sequence = target.get(..*length)?;
+                    result.push(DifferenceColumn::IdenticalSequence {
+                        sequence: sequence.to_string(),
+                    });
+                    *length
+                }
+                other => {
+                    result.push(other.clone());
+                    match other {
+                        DifferenceColumn::IdenticalSequence { sequence } => sequence.len(),
+                        DifferenceColumn::Deletion {
+                            missing_query_characters,
+                        } => missing_query_characters.len(),
+                        DifferenceColumn::Mismatch { reference, .. } => reference.len_utf8(),
+                        DifferenceColumn::Intron { length, .. } => *length,
+                        DifferenceColumn::Match { .. } | DifferenceColumn::Insertion { .. } => 0,
+                    }
+                }
+            };
+            target = target.get(target_length..)?;
+        }
+
+        Some(Self(result))
+    }
+
+    /// Converts this alignment difference string into a cigar string with `M`, `I`, `D` and `N` columns.
+    /// Adjacent columns of the same kind are merged.
+    pub fn to_cigar(&self) -> Cigar {
+        let mut result: Vec<CigarColumn> = Vec::new();
+
+        for column in &self.0 {
+            let cigar_column = match column {
+                DifferenceColumn::Match { length } => CigarColumn::Match(*length),
+                DifferenceColumn::IdenticalSequence { sequence } => {
+                    CigarColumn::Match(sequence.len())
+                }
+                DifferenceColumn::Mismatch { .. } => CigarColumn::Match(1),
+                DifferenceColumn::Insertion {
+                    superfluous_query_characters,
+                } => CigarColumn::Insertion(superfluous_query_characters.len()),
+                DifferenceColumn::Deletion {
+                    missing_query_characters,
+                } => CigarColumn::Deletion(missing_query_characters.len()),
+                DifferenceColumn::Intron { length, .. } => CigarColumn::Skip(*length),
+            };
+
+            match (result.last_mut(), cigar_column) {
+                (Some(CigarColumn::Match(last)), CigarColumn::Match(length))
+                | (Some(CigarColumn::Insertion(last)), CigarColumn::Insertion(length))
+                | (Some(CigarColumn::Deletion(last)), CigarColumn::Deletion(length))
+                | (Some(CigarColumn::Skip(last)), CigarColumn::Skip(length)) => *last += length,
+                (_, cigar_column) => result.push(cigar_column),
+            }
+        }
+
+        Cigar(result)
+    }
+}
+
+/// A line in a minimap PAF file that borrows its text from the input, see [PAFLine] for the owned version.
+///
+/// Names and textual values are slices of the input, and the cigar and alignment difference strings are parsed lazily.
+/// Lossless mode is not supported, and if a known field occurs multiple times, the last occurrence is stored.
+#[derive(Clone, Debug, PartialEq)]
+#[allow(missing_docs)]
+pub struct PAFLineRef<'a> {
+    // required fields
+    pub query_sequence_name: &'a str,
+    pub query_sequence_length: usize,
+    pub query_start_coordinate: usize,
+    pub query_end_coordinate: usize,
+    pub strand: Strand,
+    pub target_sequence_name: &'a str,
+    pub target_sequence_length: usize,
+    pub target_start_coordinate_on_original_strand: usize,
+    pub target_end_coordinate_on_original_strand: usize,
+    pub number_of_matching_bases: usize,
+    pub number_of_bases_and_gaps: usize,
+    pub mapping_quality: u8,
+
+    // optional fields
+    pub alignment_type: Option<AlignmentType>,
+    pub number_of_minimisers: Option<usize>,
+    pub chaining_score: Option<isize>,
+    pub best_secondary_chaining_score: Option<isize>,
+    pub total_number_of_mismatches_and_gaps: Option<usize>,
+    pub unknown_md: Option<&'a str>,
+    pub dp_alignment_score: Option<isize>,
+    pub supplementary_alignments: Option<&'a str>,
+    pub best_segment_dp_score: Option<isize>,
+    pub number_of_ambiguous_bases: Option<usize>,
+    pub transcript_strand: Option<&'a str>,
+    pub cigar_string: Option<CigarRef<'a>>,
+    pub difference_string: Option<AlignmentDifferenceRef<'a>>,
+    pub approximate_per_base_sequence_divergence: Option<f64>,
+    pub gap_compressed_per_base_sequence_divergence: Option<f64>,
+    pub length_of_query_regions_with_repetitive_seeds: Option<usize>,
+
+    /// Optional fields with tags that are not known to minimap2.
+    pub unknown_fields: UnknownFieldsRef<'a>,
+}
+
+impl<'a> PAFLineRef<'a> {
+    /// Returns true if this line reports an unmapped query, see [PAFLine::is_unmapped].
+    pub fn is_unmapped(&self) -> bool {
+        self.strand == Strand::Unmapped
+    }
+
+    /// Returns the first unknown field with the given name, e.g. `line.tag(b"zd")`.
+    pub fn tag(&self, name: &[u8; 2]) -> Option<TagRef<'a>> {
+        self.unknown_fields.iter().find(|tag| &tag.name == name)
+    }
+}
+
+/// The unknown fields of a [PAFLineRef].
+///
+/// This is the text of all optional fields of the line, and iterating it skips the fields known to minimap2.
+/// The headers of the unknown fields are validated when parsing the line.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct UnknownFieldsRef<'a>(pub &'a str);
+
+impl<'a> UnknownFieldsRef<'a> {
+    /// Iterate over the unknown fields.
+    pub fn iter(&self) -> impl Iterator<Item = TagRef<'a>> {
+        self.0.split('\t').filter_map(|column| {
+            let header = column.get(..5)?;
+            if KNOWN_HEADERS.contains(&header) {
+                None
+            } else {
+                Some(TagRef {
+                    name: [header.as_bytes()[0], header.as_bytes()[1]],
+                    value_type: header.as_bytes()[3],
+                    value: &column[5..],
+                })
+            }
+        })
+    }
+}
+
+/// An optional field in SAM format that borrows its value from the input, see [Tag] for the owned version.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TagRef<'a> {
+    /// The two-character name of the tag.
+    pub name: [u8; 2],
+    /// The SAM type character of the value.
+    pub value_type: u8,
+    /// The unparsed value.
+    pub value: &'a str,
+}
+
+/// A CIGAR string that borrows its text from the input, see [Cigar] for the owned version.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CigarRef<'a>(pub &'a str);
+
+/// An alignment difference string that borrows its text from the input, see [AlignmentDifference] for the owned version.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct AlignmentDifferenceRef<'a>(pub &'a str);
+
+/// A column of a difference string that borrows its text from the input, see [DifferenceColumn] for the owned version.
+#[allow(missing_docs)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DifferenceColumnRef<'a> {
+    Match {
+        length: usize,
+    },
+    IdenticalSequence {
+        sequence: &'a str,
+    },
+    Insertion {
+        superfluous_query_characters: &'a str,
+    },
+    Deletion {
+        missing_query_characters: &'a str,
+    },
+    Mismatch {
+        reference: char,
+        query: char,
+    },
+    Intron {
+        donor: &'a str,
+        length: usize,
+        acceptor: &'a str,
+    },
+}
+
+impl DifferenceColumnRef<'_> {
+    /// Converts this column into an owned [DifferenceColumn].
+    pub fn to_owned(&self) -> DifferenceColumn {
+        match *self {
+            DifferenceColumnRef::Match { length } => DifferenceColumn::Match { length },
+            DifferenceColumnRef::IdenticalSequence { sequence } => {
+                DifferenceColumn::IdenticalSequence {
+                    sequence: sequence.to_string(),
+                }
+            }
+            DifferenceColumnRef::Insertion {
+                superfluous_query_characters,
+            } => DifferenceColumn::Insertion {
+                superfluous_query_characters: superfluous_query_characters.to_string(),
+            },
+            DifferenceColumnRef::Deletion {
+                missing_query_characters,
+            } => DifferenceColumn::Deletion {
+                missing_query_characters: missing_query_characters.to_string(),
+            },
+            DifferenceColumnRef::Mismatch { reference, query } => {
+                DifferenceColumn::Mismatch { reference, query }
+            }
+            DifferenceColumnRef::Intron {
+                donor,
+                length,
+                acceptor,
+            } => DifferenceColumn::Intron {
+                donor: donor.to_string(),
+                length,
+                acceptor: acceptor.to_string(),
+            },
+        }
+    }
 }
@@ -1,3 +1,5 @@
+use std::fmt::{Display, Formatter};
+
 /// The result type of this crate using the error type of this crate.
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -11,7 +13,10 @@ pub enum Error {
     IOError(std::io::Error),
 
     /// Parsing error when parsing the column into the expected type.
-    ColumnParseError,
+    ColumnParseError {
+        /// The text of the column.
+        text: String,
+    },
 
     /// The line was ended, but it was not expected (e.g. further columns are expected instead).
     UnexpectedEndOfLine,
@@ -20,7 +25,10 @@ pub enum Error {
     UnexpectedEndOfFile,
 
     /// An unexpected character was found.
-    UnexpectedCharacter,
+    UnexpectedCharacter {
+        /// The text containing the unexpected character.
+        text: String,
+    },
 
     /// An optional column with an unexpected name or type was found.
     UnexpectedOptionalColumn {
@@ -29,14 +37,85 @@ pub enum Error {
         column_header: String,
     },
 
+    /// The value of an optional column could not be parsed into the type expected for its tag.
+    MalformedTagValue {
+        /// The tag of the column, i.e. its name and type, e.g. `cm:i:`.
+        tag: String,
+        /// The value of the column.
+        value: String,
+    },
+
     /// A cigar string could not be parsed.
-    MalformedCigar,
+    MalformedCigar {
+        /// The part of the cigar string that could not be parsed.
+        text: String,
+    },
 
     /// An alignment difference string could not be parsed.
-    MalformedAlignmentDifference,
+    MalformedAlignmentDifference {
+        /// The part of the alignment difference string that could not be parsed.
+        text: String,
+    },
+
+    /// An error that occurred at a known position of the input.
+    Located {
+        /// The position of the error.
+        position: Box<ErrorPosition>,
+        /// The error that occurred.
+        error: Box<Error>,
+    },
+}
+
+/// The position of a parsing error within the input.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ErrorPosition {
+    /// The 1-based number of the line, or 0 if it is not known.
+    pub line: usize,
+    /// The offset of the erroneous column in bytes from the start of the input.
+    pub byte_offset: u64,
+    /// The 1-based index of the erroneous column within the line.
+    pub column: usize,
+    /// The tag of the erroneous column, e.g. `cg:Z:`, if it is an optional column.
+    pub tag: Option<String>,
+}
+
+impl Error {
+    /// Returns the position of this error, if it is known.
+    pub fn position(&self) -> Option<&ErrorPosition> {
+        if let Self::Located { position, .. } = self {
+            Some(position)
+        } else {
+            None
+        }
+    }
+
+    /// Locates this error at the given line and column, which is at the given byte offset.
+    pub(crate) fn locate(self, line: usize, column: usize, byte_offset: u64) -> Self {
+        Self::Located {
+            position: Box::new(ErrorPosition {
+                line,
+                byte_offset,
+                column,
+                tag: None,
+            }),
+            error: Box::new(self),
+        }
+    }
 
-    /// Quick and dirty: simply use strings to report errors.
-    Message(String),
+    /// Moves the position of this error to the given line, which starts at the given byte offset.
+    pub(crate) fn relocate(self, line: usize, line_byte_offset: u64) -> Self {
+        if let Self::Located {
+            mut position,
+            error,
+        } = self
+        {
+            position.line = line;
+            position.byte_offset += line_byte_offset;
+            Self::Located { position, error }
+        } else {
+            self
+        }
+    }
 }
 
 impl From<std::io::Error> for Error {
@@ -44,3 +123,71 @@ impl From<std::io::Error> for Error {
         Self::IOError(error)
     }
 }
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::IOError(error) => write!(f, "I/O error: {error}"),
+            Error::ColumnParseError { text } => {
+                write!(f, "could not parse column `{}`", abbreviate(text))
+            }
+            Error::UnexpectedEndOfLine => write!(f, "unexpected end of line"),
+            Error::UnexpectedEndOfFile => write!(f, "unexpected end of file"),
+            Error::UnexpectedCharacter { text } => {
+                write!(f, "unexpected character in `{}`", abbreviate(text))
+            }
+            Error::UnexpectedOptionalColumn { column_header } => {
+                write!(f, "unexpected optional column `{column_header}`")
+            }
+            Error::MalformedTagValue { tag, value } => {
+                write!(f, "malformed value `{}` for tag `{tag}`", abbreviate(value))
+            }
+            Error::MalformedCigar { text } => {
+                write!(f, "malformed cigar string at `{}`", abbreviate(text))
+            }
+            Error::MalformedAlignmentDifference { text } => write!(
+                f,
+                "malformed alignment difference string at `{}`",
+                abbreviate(text)
+            ),
+            Error::Located { position, error } => {
+                writeln!(f, "error: {error}")?;
+                write!(f, "  --> {position}")
+            }
+        }
+    }
+}
+
+impl Display for ErrorPosition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.line > 0 {
+            write!(f, "line {}, ", self.line)?;
+        }
+        write!(f, "column {}", self.column)?;
+        if let Some(tag) = &self.tag {
+            write!(f, " (`{tag}`)")?;
+        }
+        write!(f, ", byte offset {}", self.byte_offset)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::IOError(error) => Some(error),
+            Error::Located { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// Cut off long texts such as cigar strings, such that error messages stay readable.
+fn abbreviate(text: &str) -> String {
+    const MAX_LENGTH: usize = 40;
+
+    if let Some((limit, _)) = text.char_indices().nth(MAX_LENGTH) {
+        format!("{}...", &text[..limit])
+    } else {
+        text.to_string()
+    }
+}
@@ -0,0 +1,495 @@
+#[cfg(feature = "gzip")]
+use crate::compression::BgzfReader;
+use crate::compression::Compression;
+use crate::data::PAFLine;
+use crate::error::Result;
+use crate::input::{parse_line_with_options, strip_line_terminator, ParserOptions};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const MAGIC: [u8; 4] = *b"PAFI";
+const VERSION: u8 = 1;
+/// The binary logarithm of the size of the smallest bins.
+const MIN_SHIFT: u32 = 14;
+/// The number of levels of bins below the root bin.
+/// With the minimum shift of 14, this covers sequences of up to 2^35 bases.
+const DEPTH: u32 = 7;
+
+/// The column of a PAF line that an index is keyed by.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum IndexKey {
+    /// Index by the target sequence name and the target coordinates on the original strand.
+    /// Unmapped lines are not indexed.
+    Target,
+    /// Index by the query sequence name and the query coordinates on the original strand.
+    Query,
+}
+
+impl IndexKey {
+    /// Returns the extension that is appended to the path of a PAF file to get the path of its index.
+    pub fn sidecar_extension(self) -> &'static str {
+        match self {
+            IndexKey::Target => "pti",
+            IndexKey::Query => "pqi",
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            IndexKey::Target => 0,
+            IndexKey::Query => 1,
+        }
+    }
+
+    /// Returns the indexed sequence name and region of the given line, or `None` if the line is not indexed.
+    fn region(self, line: &PAFLine) -> Option<(&str, u64, u64)> {
+        match self {
+            IndexKey::Target if line.is_unmapped() => None,
+            IndexKey::Target => Some((
+                &line.target_sequence_name,
+                line.target_start_coordinate_on_original_strand as u64,
+                line.target_end_coordinate_on_original_strand as u64,
+            )),
+            IndexKey::Query => Some((
+                &line.query_sequence_name,
+                line.query_start_coordinate as u64,
+                line.query_end_coordinate as u64,
+            )),
+        }
+    }
+}
+
+/// A range of offsets in an indexed PAF file that contains complete lines.
+///
+/// For BGZF compressed files, the offsets are BGZF virtual offsets, otherwise they are byte offsets.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct IndexChunk {
+    /// The offset of the first line of the chunk.
+    pub start: u64,
+    /// The offset directly after the last line of the chunk.
+    pub end: u64,
+}
+
+/// A random-access index of a PAF file that maps regions of sequences to the offsets of the lines overlapping them.
+///
+/// Lines are assigned to hierarchical bins like in the CSI index format used for SAM and VCF files.
+/// The indexed file must be either uncompressed or BGZF compressed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PafIndex {
+    key: IndexKey,
+    compression: Compression,
+    sequences: BTreeMap<String, HashMap<u32, Vec<IndexChunk>>>,
+}
+
+impl PafIndex {
+    /// Build an index of the PAF file at the given path.
+    pub fn build(path: impl AsRef<Path>, key: IndexKey) -> Result<Self> {
+        let mut file = File::open(path)?;
+        let compression = detect_file_compression(&mut file)?;
+        let mut input = SeekableInput::new(file, compression)?;
+
+        let mut index = Self {
+            key,
+            compression,
+            sequences: BTreeMap::new(),
+        };
+        let mut buffer = String::new();
+        let mut line = PAFLine::default();
+        let options = ParserOptions::default().with_only_fields([]);
+        let mut line_number = 0;
+        let mut byte_offset = 0;
+
+        loop {
+            let start = input.position();
+            buffer.clear();
+            let length = input.read_line(&mut buffer)?;
+            if length == 0 {
+                break;
+            }
+            line_number += 1;
+
+            let mut string = strip_line_terminator(&buffer);
+            parse_line_with_options(&mut string, &mut line, &options)
+                .map_err(|error| error.relocate(line_number, byte_offset))?;
+            byte_offset += length as u64;
+
+            if let Some((name, region_start, region_end)) = key.region(&line) {
+                let chunk = IndexChunk {
+                    start,
+                    end: input.position(),
+                };
+                index.insert(name, region_start, region_end, chunk);
+            }
+        }
+
+        Ok(index)
+    }
+
+    /// Returns the column this index is keyed by.
+    pub fn key(&self) -> IndexKey {
+        self.key
+    }
+
+    /// Returns the names of the indexed sequences in lexicographic order.
+    pub fn sequence_names(&self) -> impl Iterator<Item = &str> {
+        self.sequences.keys().map(String::as_str)
+    }
+
+    /// Returns the sorted and non-overlapping chunks of the indexed file that contain all lines overlapping the given region.
+    ///
+    /// The region is given as zero-based half-open interval.
+    /// The chunks may also contain lines that do not overlap the region.
+    pub fn chunks(&self, sequence_name: &str, start: u64, end: u64) -> Vec<IndexChunk> {
+        let bins = if let Some(bins) = self.sequences.get(sequence_name) {
+            bins
+        } else {
+            return Vec::new();
+        };
+
+        let mut chunks: Vec<_> = bins
+            .iter()
+            .filter(|(&bin, _)| bin_overlaps(bin, start, end))
+            .flat_map(|(_, chunks)| chunks.iter().copied())
+            .collect();
+        chunks.sort_unstable();
+
+        let mut merged_chunks: Vec<IndexChunk> = Vec::with_capacity(chunks.len());
+        for chunk in chunks {
+            match merged_chunks.last_mut() {
+                Some(last) if chunk.start <= last.end => last.end = last.end.max(chunk.end),
+                _ => merged_chunks.push(chunk),
+            }
+        }
+        merged_chunks
+    }
+
+    /// Returns the path of the index file of the PAF file at the given path.
+    pub fn sidecar_path(paf_path: impl AsRef<Path>, key: IndexKey) -> PathBuf {
+        let mut path = paf_path.as_ref().as_os_str().to_owned();
+        path.push(".");
+        path.push(key.sidecar_extension());
+        path.into()
+    }
+
+    /// Write this index to the given path.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut output = BufWriter::new(File::create(path)?);
+        self.write(&mut output)?;
+        output.flush()?;
+        Ok(())
+    }
+
+    /// Read an index from the given path.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+
+    /// Write this index in binary format to the given output.
+    pub fn write(&self, mut output: impl Write) -> std::io::Result<()> {
+        output.write_all(&MAGIC)?;
+        output.write_all(&[
+            VERSION,
+            self.key.to_byte(),
+            (self.compression == Compression::Bgzf) as u8,
+            MIN_SHIFT as u8,
+            DEPTH as u8,
+        ])?;
+
+        output.write_all(&(self.sequences.len() as u64).to_le_bytes())?;
+        for (name, bins) in &self.sequences {
+            output.write_all(&(name.len() as u64).to_le_bytes())?;
+            output.write_all(name.as_bytes())?;
+
+            let mut bins: Vec<_> = bins.iter().collect();
+            bins.sort_unstable_by_key(|(&bin, _)| bin);
+            output.write_all(&(bins.len() as u64).to_le_bytes())?;
+            for (bin, chunks) in bins {
+                output.write_all(&bin.to_le_bytes())?;
+                output.write_all(&(chunks.len() as u64).to_le_bytes())?;
+                for chunk in chunks {
+                    output.write_all(&chunk.start.to_le_bytes())?;
+                    output.write_all(&chunk.end.to_le_bytes())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Read an index in binary format from the given input.
+    pub fn read(mut input: impl Read) -> Result<Self> {
+        let mut header = [0; 9];
+        input.read_exact(&mut header)?;
+        if header[..4] != MAGIC {
+            return Err(malformed_index("missing magic bytes").into());
+        }
+        if header[4] != VERSION || header[7] as u32 != MIN_SHIFT || header[8] as u32 != DEPTH {
+            return Err(malformed_index("unsupported version").into());
+        }
+        let key = match header[5] {
+            0 => IndexKey::Target,
+            1 => IndexKey::Query,
+            _ => return Err(malformed_index("unknown key").into()),
+        };
+        let compression = match header[6] {
+            0 => Compression::None,
+            1 => Compression::Bgzf,
+            _ => return Err(malformed_index("unknown compression").into()),
+        };
+
+        let mut sequences = BTreeMap::new();
+        for _ in 0..read_u64(&mut input)? {
+            let mut name = vec![0; read_u64(&mut input)? as usize];
+            input.read_exact(&mut name)?;
+            let name =
+                String::from_utf8(name).map_err(|_| malformed_index("sequence name not UTF-8"))?;
+
+            let mut bins = HashMap::new();
+            for _ in 0..read_u64(&mut input)? {
+                let mut bin = [0; 4];
+                input.read_exact(&mut bin)?;
+                let chunks = (0..read_u64(&mut input)?)
+                    .map(|_| {
+                        Ok(IndexChunk {
+                            start: read_u64(&mut input)?,
+                            end: read_u64(&mut input)?,
+                        })
+                    })
+                    .collect::<std::io::Result<_>>()?;
+                bins.insert(u32::from_le_bytes(bin), chunks);
+            }
+            sequences.insert(name, bins);
+        }
+
+        Ok(Self {
+            key,
+            compression,
+            sequences,
+        })
+    }
+
+    fn insert(&mut self, sequence_name: &str, start: u64, end: u64, chunk: IndexChunk) {
+        let bins = if let Some(bins) = self.sequences.get_mut(sequence_name) {
+            bins
+        } else {
+            self.sequences.entry(sequence_name.to_string()).or_default()
+        };
+        let chunks = bins.entry(region_to_bin(start, end)).or_default();
+
+        match chunks.last_mut() {
+            Some(last) if last.end == chunk.start => last.end = chunk.end,
+            _ => chunks.push(chunk),
+        }
+    }
+}
+
+/// A reader of a PAF file that uses a [PafIndex] to read only the lines overlapping a region.
+#[derive(Debug)]
+pub struct IndexedPafReader<Input: Read + Seek> {
+    input: SeekableInput<Input>,
+    index: PafIndex,
+    options: ParserOptions,
+    buffer: String,
+}
+
+impl IndexedPafReader<File> {
+    /// Open the PAF file at the given path together with its index of the given key.
+    ///
+    /// The index is loaded from the path returned by [PafIndex::sidecar_path].
+    pub fn open(path: impl AsRef<Path>, key: IndexKey) -> Result<Self> {
+        let index = PafIndex::load(PafIndex::sidecar_path(path.as_ref(), key))?;
+        Self::new(File::open(path)?, index)
+    }
+}
+
+impl<Input: Read + Seek> IndexedPafReader<Input> {
+    /// Create a new reader that reads PAF lines from the given input using the given index.
+    pub fn new(input: Input, index: PafIndex) -> Result<Self> {
+        Self::with_options(input, index, ParserOptions::default())
+    }
+
+    /// Create a new reader that reads PAF lines from the given input using the given index and parser options.
+    pub fn with_options(input: Input, index: PafIndex, options: ParserOptions) -> Result<Self> {
+        Ok(Self {
+            input: SeekableInput::new(input, index.compression)?,
+            index,
+            options,
+            buffer: String::new(),
+        })
+    }
+
+    /// Returns the index of this reader.
+    pub fn index(&self) -> &PafIndex {
+        &self.index
+    }
+
+    /// Returns all lines that overlap the given region of the given sequence, in the order of the file.
+    ///
+    /// The region is given as zero-based half-open interval on the original strand,
+    /// and the sequence is a target or query sequence depending on the key of the index.
+    ///
+    /// Parsing errors are located by the offset of the erroneous line plus the offset of the column within it, with line number 0.
+    pub fn query(&mut self, sequence_name: &str, start: u64, end: u64) -> Result<Vec<PAFLine>> {
+        let mut lines = Vec::new();
+
+        for chunk in self.index.chunks(sequence_name, start, end) {
+            self.input.seek(chunk.start)?;
+            while self.input.position() < chunk.end {
+                let line_offset = self.input.position();
+                self.buffer.clear();
+                if self.input.read_line(&mut self.buffer)? == 0 {
+                    break;
+                }
+
+                let mut line = PAFLine::default();
+                let mut string = strip_line_terminator(&self.buffer);
+                // the line number is not known when reading from an offset
+                parse_line_with_options(&mut string, &mut line, &self.options)
+                    .map_err(|error| error.relocate(0, line_offset))?;
+                if let Some((name, line_start, line_end)) = self.index.key.region(&line) {
+                    if name == sequence_name
+                        && line_start < end
+                        && line_end.max(line_start + 1) > start
+                    {
+                        lines.push(line);
+                    }
+                }
+            }
+        }
+
+        Ok(lines)
+    }
+
+    /// Returns all lines of the given sequence, in the order of the file.
+    pub fn query_sequence(&mut self, sequence_name: &str) -> Result<Vec<PAFLine>> {
+        self.query(sequence_name, 0, u64::MAX)
+    }
+}
+
+/// An uncompressed or BGZF compressed input that allows to seek to line offsets.
+#[derive(Debug)]
+enum SeekableInput<Input: Read> {
+    Plain {
+        input: BufReader<Input>,
+        position: u64,
+    },
+    #[cfg(feature = "gzip")]
+    Bgzf(BgzfReader<Input>),
+}
+
+impl<Input: Read> SeekableInput<Input> {
+    fn new(input: Input, compression: Compression) -> std::io::Result<Self> {
+        match compression {
+            Compression::None => Ok(Self::Plain {
+                input: BufReader::new(input),
+                position: 0,
+            }),
+            #[cfg(feature = "gzip")]
+            Compression::Bgzf => Ok(Self::Bgzf(BgzfReader::new(input))),
+            compression => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!("cannot index {compression:?} compressed files"),
+            )),
+        }
+    }
+
+    fn position(&self) -> u64 {
+        match self {
+            Self::Plain { position, .. } => *position,
+            #[cfg(feature = "gzip")]
+            Self::Bgzf(input) => input.virtual_position(),
+        }
+    }
+
+    fn read_line(&mut self, buffer: &mut String) -> std::io::Result<usize> {
+        match self {
+            Self::Plain { input, position } => {
+                let length = input.read_line(buffer)?;
+                *position += length as u64;
+                Ok(length)
+            }
+            #[cfg(feature = "gzip")]
+            Self::Bgzf(input) => input.read_line(buffer),
+        }
+    }
+}
+
+impl<Input: Read + Seek> SeekableInput<Input> {
+    fn seek(&mut self, offset: u64) -> std::io::Result<()> {
+        match self {
+            Self::Plain { input, position } => {
+                input.seek_relative(offset as i64 - *position as i64)?;
+                *position = offset;
+                Ok(())
+            }
+            #[cfg(feature = "gzip")]
+            Self::Bgzf(input) => input.seek_virtual(offset),
+        }
+    }
+}
+
+/// Detect the compression of the given file and rewind it.
+fn detect_file_compression(file: &mut File) -> std::io::Result<Compression> {
+    let mut header = Vec::with_capacity(18);
+    file.take(18).read_to_end(&mut header)?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(Compression::detect(&header))
+}
+
+/// Returns the largest coordinate that can be binned.
+fn max_coordinate() -> u64 {
+    (1 << (MIN_SHIFT + DEPTH * 3)) - 1
+}
+
+/// Returns the smallest bin that fully contains the given zero-based half-open region.
+fn region_to_bin(start: u64, end: u64) -> u32 {
+    let start = start.min(max_coordinate());
+    let end = (end.max(start + 1) - 1).min(max_coordinate());
+    let mut level = DEPTH;
+    let mut shift = MIN_SHIFT;
+    let mut offset = ((1u64 << (DEPTH * 3)) - 1) / 7;
+
+    while level > 0 {
+        if start >> shift == end >> shift {
+            return (offset + (start >> shift)) as u32;
+        }
+        level -= 1;
+        shift += 3;
+        offset -= 1 << (level * 3);
+    }
+    0
+}
+
+/// Returns true if the given bin overlaps the given zero-based half-open region.
+fn bin_overlaps(bin: u32, start: u64, end: u64) -> bool {
+    let bin = bin as u64;
+    let mut offset = 0;
+    let mut shift = MIN_SHIFT + DEPTH * 3;
+
+    for level in 0..=DEPTH {
+        let level_size = 1 << (level * 3);
+        if bin < offset + level_size {
+            let bin_start = (bin - offset) << shift;
+            let bin_end = bin_start + (1 << shift);
+            return bin_start < end && bin_end > start;
+        }
+        offset += level_size;
+        shift -= 3;
+    }
+    false
+}
+
+fn read_u64(input: &mut impl Read) -> std::io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn malformed_index(message: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("malformed PAF index: {message}"),
+    )
+}
@@ -1,247 +1,1066 @@
 use crate::data::{
-    AlignmentDifference, AlignmentType, Cigar, CigarColumn, DifferenceColumn, PAFLine,
+    AlignmentDifference, AlignmentDifferenceRef, AlignmentType, Cigar, CigarColumn, CigarRef,
+    DifferenceColumnRef, OriginalField, PAFLine, PAFLineRef, RawTag, Strand, Tag, TagArray, TagRef,
+    TagValue, UnknownFieldsRef, KNOWN_HEADERS,
 };
-use crate::error::{Error, Result};
+use crate::error::{Error, ErrorPosition, Result};
 use log::warn;
-use std::io::{BufRead, BufReader, Read};
+use std::io::{BufRead, Read};
 use std::str::FromStr;
 
 /// Parse all lines in the given input string.
-pub fn parse_input_str(mut string: &str) -> Result<Vec<PAFLine>> {
-    let mut result = Vec::new();
-    while !string.is_empty() {
-        result.push(parse_line(&mut string)?);
-    }
-    Ok(result)
+pub fn parse_input_str(string: &str) -> Result<Vec<PAFLine>> {
+    PafReader::new(string.as_bytes()).collect()
 }
 
 /// Parse all lines in the given input.
 /// Terminate when the input has no further lines.
 pub fn parse_input_read<Input: Read>(input: &mut Input) -> Result<Vec<PAFLine>> {
-    let mut result = Vec::new();
-    let reader = BufReader::new(input);
+    PafReader::new(std::io::BufReader::new(input)).collect()
+}
+
+/// Options for parsing PAF lines.
+#[derive(Clone, Debug, Default)]
+pub struct ParserOptions {
+    /// If true, the original order of the optional fields as well as the original text of float values and of non-canonical integers
+    /// is recorded in [PAFLine::original_fields], such that writing a line reproduces it byte by byte.
+    /// Duplicates of known fields are then stored in [PAFLine::unknown_fields] instead of overwriting the first occurrence.
+    pub lossless: bool,
+    /// The modes of optional fields that deviate from the default mode.
+    pub field_modes: Vec<([u8; 2], FieldMode)>,
+    /// The mode of optional fields that are not listed in [ParserOptions::field_modes].
+    pub default_field_mode: FieldMode,
+}
+
+/// Selects how an optional field is parsed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FieldMode {
+    /// The field is fully parsed.
+    Decode,
+    /// The value of the field is kept unparsed in [PAFLine::raw_fields], from where it can be parsed lazily.
+    /// This applies only to fields known to minimap2, unknown fields are parsed as with [FieldMode::Decode].
+    Raw,
+    /// The field is skipped without parsing its value.
+    Skip,
+}
+
+impl Default for FieldMode {
+    fn default() -> Self {
+        Self::Decode
+    }
+}
+
+impl ParserOptions {
+    /// Set the lossless mode, see [ParserOptions::lossless].
+    pub fn with_lossless(mut self, lossless: bool) -> Self {
+        self.lossless = lossless;
+        self
+    }
+
+    /// Set the mode of the optional field with the given name, e.g. `*b"cg"`.
+    pub fn with_field_mode(mut self, name: [u8; 2], mode: FieldMode) -> Self {
+        self.field_modes
+            .retain(|(existing_name, _)| existing_name != &name);
+        self.field_modes.push((name, mode));
+        self
+    }
+
+    /// Set the mode of optional fields whose mode is not set explicitly.
+    pub fn with_default_field_mode(mut self, mode: FieldMode) -> Self {
+        self.default_field_mode = mode;
+        self
+    }
+
+    /// Decode only the optional fields with the given names, and skip all others.
+    pub fn with_only_fields(self, names: impl IntoIterator<Item = [u8; 2]>) -> Self {
+        names.into_iter().fold(
+            self.with_default_field_mode(FieldMode::Skip),
+            |options, name| options.with_field_mode(name, FieldMode::Decode),
+        )
+    }
+
+    /// Returns the mode of the optional field with the given name.
+    pub fn field_mode(&self, name: &[u8; 2]) -> FieldMode {
+        self.field_modes
+            .iter()
+            .find(|(existing_name, _)| existing_name == name)
+            .map(|(_, mode)| *mode)
+            .unwrap_or(self.default_field_mode)
+    }
+}
+
+/// A streaming reader of PAF lines.
+///
+/// Lines are read one at a time from the underlying input, so the whole input never needs to be in memory.
+/// Use the [Iterator] implementation to get owned lines, [read_into](PafReader::read_into) to reuse the allocations of an existing line,
+/// or [read_ref](PafReader::read_ref) to get lines that borrow from the internal buffer.
+#[derive(Debug)]
+pub struct PafReader<Input: BufRead> {
+    input: Input,
+    buffer: String,
+    line_number: usize,
+    byte_offset: u64,
+    options: ParserOptions,
+}
+
+impl<Input: BufRead> PafReader<Input> {
+    /// Create a new reader that reads PAF lines from the given input.
+    pub fn new(input: Input) -> Self {
+        Self::with_options(input, ParserOptions::default())
+    }
+
+    /// Create a new reader that reads PAF lines from the given input using the given options.
+    pub fn with_options(input: Input, options: ParserOptions) -> Self {
+        Self {
+            input,
+            buffer: String::new(),
+            line_number: 0,
+            byte_offset: 0,
+            options,
+        }
+    }
+
+    /// Continue counting lines and bytes from the given position, for inputs that are part of a larger input.
+    #[cfg_attr(not(feature = "parallel"), allow(dead_code))]
+    pub(crate) fn starting_at(mut self, line_number: usize, byte_offset: u64) -> Self {
+        self.line_number = line_number;
+        self.byte_offset = byte_offset;
+        self
+    }
+
+    /// Read the next line into the given [PAFLine], reusing its `String` and `Vec` allocations.
+    ///
+    /// Returns `false` if the input has no further lines, in which case the given line is left unchanged.
+    pub fn read_into(&mut self, line: &mut PAFLine) -> Result<bool> {
+        let line_byte_offset = self.byte_offset;
+        if !self.read_next_line()? {
+            return Ok(false);
+        }
+        let line_number = self.line_number;
+        let mut string = strip_line_terminator(&self.buffer);
+
+        parse_line_with_options(&mut string, line, &self.options)
+            .map_err(|error| error.relocate(line_number, line_byte_offset))?;
+        Ok(true)
+    }
 
-    for line in reader.lines() {
-        result.push(parse_line(&mut line?.as_str())?);
+    /// Read the next line as a [PAFLineRef] that borrows from the internal buffer of this reader.
+    ///
+    /// Returns `None` if the input has no further lines.
+    /// The options of this reader are ignored, see [PAFLineRef].
+    pub fn read_ref(&mut self) -> Result<Option<PAFLineRef<'_>>> {
+        let line_byte_offset = self.byte_offset;
+        if !self.read_next_line()? {
+            return Ok(None);
+        }
+        let line_number = self.line_number;
+        let mut string = strip_line_terminator(&self.buffer);
+
+        parse_line_ref(&mut string)
+            .map(Some)
+            .map_err(|error| error.relocate(line_number, line_byte_offset))
+    }
+
+    /// Read the next line into the internal buffer.
+    /// Returns `false` if the input has no further lines.
+    fn read_next_line(&mut self) -> Result<bool> {
+        self.buffer.clear();
+        let length = self.input.read_line(&mut self.buffer)?;
+        if length == 0 {
+            return Ok(false);
+        }
+        self.line_number += 1;
+        self.byte_offset += length as u64;
+        Ok(true)
+    }
+
+    /// Returns the number of lines read so far.
+    pub fn line_number(&self) -> usize {
+        self.line_number
+    }
+
+    /// Returns a reference to the underlying input.
+    pub fn get_ref(&self) -> &Input {
+        &self.input
     }
 
-    Ok(result)
+    /// Returns the underlying input.
+    pub fn into_inner(self) -> Input {
+        self.input
+    }
+}
+
+impl<Input: BufRead> Iterator for PafReader<Input> {
+    type Item = Result<PAFLine>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut line = PAFLine::default();
+        match self.read_into(&mut line) {
+            Ok(true) => Some(Ok(line)),
+            Ok(false) => None,
+            Err(error) => Some(Err(error)),
+        }
+    }
+}
+
+pub(crate) fn strip_line_terminator(line: &str) -> &str {
+    if let Some(stripped) = line.strip_suffix('\n') {
+        stripped.strip_suffix('\r').unwrap_or(stripped)
+    } else {
+        line
+    }
 }
 
 /// Parses a line of input into a [PAFLine].
 /// The given string slice is advanced past the parsed line of input.
 pub fn parse_line(string: &mut &str) -> Result<PAFLine> {
+    let mut line = PAFLine::default();
+    parse_line_into(string, &mut line)?;
+    Ok(line)
+}
+
+/// Parses a line of input into the given [PAFLine], reusing its `String` and `Vec` allocations.
+/// The given string slice is advanced past the parsed line of input.
+///
+/// If an error is returned, the content of the given line is unspecified.
+/// The position of the error is given relative to the start of the given string slice, which is assumed to be line 1.
+pub fn parse_line_into(string: &mut &str, line: &mut PAFLine) -> Result<()> {
+    parse_line_with_options(string, line, &ParserOptions::default())
+}
+
+/// Parses a line of input into the given [PAFLine] like [parse_line_into], but with the given options.
+pub fn parse_line_with_options(
+    string: &mut &str,
+    line: &mut PAFLine,
+    options: &ParserOptions,
+) -> Result<()> {
+    let mut column = ColumnTracker::new(string);
+    parse_columns(string, line, &mut column, options).map_err(|error| column.locate(error))
+}
+
+/// Parses a line of input into a [PAFLineRef] that borrows from the input.
+/// The given string slice is advanced past the parsed line of input.
+///
+/// The cigar and alignment difference strings as well as the values of unknown fields are not parsed,
+/// so errors in them are only reported when they are accessed.
+pub fn parse_line_ref<'input>(string: &mut &'input str) -> Result<PAFLineRef<'input>> {
+    let mut column = ColumnTracker::new(string);
+    parse_columns_ref(string, &mut column).map_err(|error| column.locate(error))
+}
+
+/// Tracks the position of the column that is currently being parsed, to be able to report errors.
+struct ColumnTracker<'input> {
+    line: &'input str,
+    index: usize,
+    byte_offset: usize,
+    tag: Option<&'input str>,
+}
+
+impl<'input> ColumnTracker<'input> {
+    fn new(line: &'input str) -> Self {
+        Self {
+            line,
+            index: 0,
+            byte_offset: 0,
+            tag: None,
+        }
+    }
+
+    /// Start the next column, which begins at the start of the given remaining input.
+    fn next(&mut self, remaining: &str) {
+        self.index += 1;
+        self.byte_offset = self.line.len() - remaining.len();
+        self.tag = None;
+    }
+
+    /// Wrap the given error into an error located at the current column.
+    fn locate(&self, error: Error) -> Error {
+        Error::Located {
+            position: Box::new(ErrorPosition {
+                line: 1,
+                byte_offset: self.byte_offset as u64,
+                column: self.index,
+                tag: self.tag.map(ToString::to_string),
+            }),
+            error: Box::new(error),
+        }
+    }
+}
+
+/// The required columns of a line, borrowing from the input.
+struct RequiredColumns<'input> {
+    query_sequence_name: &'input str,
+    query_sequence_length: usize,
+    query_start_coordinate: usize,
+    query_end_coordinate: usize,
+    strand: Strand,
+    target_sequence_name: &'input str,
+    target_sequence_length: usize,
+    target_start_coordinate_on_original_strand: usize,
+    target_end_coordinate_on_original_strand: usize,
+    number_of_matching_bases: usize,
+    number_of_bases_and_gaps: usize,
+    mapping_quality: u8,
+}
+
+fn parse_required_columns<'input>(
+    string: &mut &'input str,
+    column: &mut ColumnTracker<'input>,
+) -> Result<RequiredColumns<'input>> {
+    let query_sequence_name = extract_required_column(string, column, false)?;
+    let query_sequence_length = parse_column(string, column, false)?;
+    let query_start_coordinate = parse_column(string, column, false)?;
+    let query_end_coordinate = parse_column(string, column, false)?;
+    let strand = extract_required_column(string, column, false)?;
+    let strand = match strand {
+        "+" => Strand::Forward,
+        "-" => Strand::Reverse,
+        "*" => Strand::Unmapped,
+        _ => {
+            return Err(Error::UnexpectedCharacter {
+                text: strand.to_string(),
+            })
+        }
+    };
+
+    Ok(RequiredColumns {
+        query_sequence_name,
+        query_sequence_length,
+        query_start_coordinate,
+        query_end_coordinate,
+        strand,
+        target_sequence_name: extract_required_column(string, column, false)?,
+        target_sequence_length: parse_column(string, column, false)?,
+        target_start_coordinate_on_original_strand: parse_column(string, column, false)?,
+        target_end_coordinate_on_original_strand: parse_column(string, column, false)?,
+        number_of_matching_bases: parse_column(string, column, false)?,
+        number_of_bases_and_gaps: parse_column(string, column, false)?,
+        mapping_quality: parse_column(string, column, true)?,
+    })
+}
+
+/// An optional field with its value parsed, except for values that can be borrowed from the input.
+enum OptionalField<'input> {
+    AlignmentType(AlignmentType),
+    NumberOfMinimisers(usize),
+    ChainingScore(isize),
+    BestSecondaryChainingScore(isize),
+    TotalNumberOfMismatchesAndGaps(usize),
+    UnknownMd(&'input str),
+    DpAlignmentScore(isize),
+    SupplementaryAlignments(&'input str),
+    BestSegmentDpScore(isize),
+    NumberOfAmbiguousBases(usize),
+    TranscriptStrand(&'input str),
+    CigarString(&'input str),
+    DifferenceString(&'input str),
+    ApproximatePerBaseSequenceDivergence(f64),
+    GapCompressedPerBaseSequenceDivergence(f64),
+    LengthOfQueryRegionsWithRepetitiveSeeds(usize),
+    Unknown,
+}
+
+fn parse_optional_field<'input>(header: &str, value: &'input str) -> Result<OptionalField<'input>> {
+    Ok(match header {
+        "tp:A:" => OptionalField::AlignmentType(match value {
+            "P" => AlignmentType::Primary,
+            "S" => AlignmentType::Secondary,
+            "I" => AlignmentType::PrimaryInversion,
+            "i" => AlignmentType::SecondaryInversion,
+            _ => {
+                return Err(Error::MalformedTagValue {
+                    tag: header.to_string(),
+                    value: value.to_string(),
+                })
+            }
+        }),
+        "cm:i:" => OptionalField::NumberOfMinimisers(parse_tag_value(header, value)?),
+        "s1:i:" => OptionalField::ChainingScore(parse_tag_value(header, value)?),
+        "s2:i:" => OptionalField::BestSecondaryChainingScore(parse_tag_value(header, value)?),
+        "NM:i:" => OptionalField::TotalNumberOfMismatchesAndGaps(parse_tag_value(header, value)?),
+        "MD:Z:" => OptionalField::UnknownMd(value),
+        "AS:i:" => OptionalField::DpAlignmentScore(parse_tag_value(header, value)?),
+        "SA:Z:" => OptionalField::SupplementaryAlignments(value),
+        "ms:i:" => OptionalField::BestSegmentDpScore(parse_tag_value(header, value)?),
+        "nn:i:" => OptionalField::NumberOfAmbiguousBases(parse_tag_value(header, value)?),
+        "ts:A:" => OptionalField::TranscriptStrand(value),
+        "cg:Z:" => OptionalField::CigarString(value),
+        "cs:Z:" => OptionalField::DifferenceString(value),
+        "dv:f:" => {
+            OptionalField::ApproximatePerBaseSequenceDivergence(parse_tag_value(header, value)?)
+        }
+        "de:f:" => {
+            OptionalField::GapCompressedPerBaseSequenceDivergence(parse_tag_value(header, value)?)
+        }
+        "rl:i:" => {
+            OptionalField::LengthOfQueryRegionsWithRepetitiveSeeds(parse_tag_value(header, value)?)
+        }
+        _ => OptionalField::Unknown,
+    })
+}
+
+/// Extracts the header (e.g. `cg:Z:`) and the value of the next optional column.
+/// Returns `None` if the line has no further columns, in which case the line terminator is consumed.
+fn next_optional_column<'input>(
+    string: &mut &'input str,
+    column: &mut ColumnTracker<'input>,
+) -> Result<Option<(&'input str, &'input str)>> {
+    if string.starts_with('\n') {
+        *string = &string[1..];
+        return Ok(None);
+    }
+
+    column.next(string);
+    if string.len() < 6 {
+        if !string.is_empty() && *string != "\n" {
+            return Err(Error::UnexpectedCharacter {
+                text: string.to_string(),
+            });
+        } else {
+            return Ok(None);
+        }
+    }
+
+    let header = string.get(..5).ok_or_else(|| Error::UnexpectedCharacter {
+        text: extract_column_value(&mut { *string }).to_string(),
+    })?;
+    column.tag = Some(header);
+    *string = &string[5..];
+    Ok(Some((header, extract_column_value(string))))
+}
+
+fn parse_columns<'input>(
+    string: &mut &'input str,
+    line: &mut PAFLine,
+    column: &mut ColumnTracker<'input>,
+    options: &ParserOptions,
+) -> Result<()> {
     // required fields
-    let query_sequence_name = parse_column(string, false)?;
-    let query_sequence_length = parse_column(string, false)?;
-    let query_start_coordinate = parse_column(string, false)?;
-    let query_end_coordinate = parse_column(string, false)?;
-    let strand: String = parse_column(string, false)?;
-    let strand = if strand == "+" {
-        true
-    } else if strand == "-" {
-        false
+    let required_columns = parse_required_columns(string, column)?;
+    reuse_string_into(
+        &mut line.query_sequence_name,
+        required_columns.query_sequence_name,
+    );
+    line.query_sequence_length = required_columns.query_sequence_length;
+    line.query_start_coordinate = required_columns.query_start_coordinate;
+    line.query_end_coordinate = required_columns.query_end_coordinate;
+    line.strand = required_columns.strand;
+    reuse_string_into(
+        &mut line.target_sequence_name,
+        required_columns.target_sequence_name,
+    );
+    line.target_sequence_length = required_columns.target_sequence_length;
+    line.target_start_coordinate_on_original_strand =
+        required_columns.target_start_coordinate_on_original_strand;
+    line.target_end_coordinate_on_original_strand =
+        required_columns.target_end_coordinate_on_original_strand;
+    line.number_of_matching_bases = required_columns.number_of_matching_bases;
+    line.number_of_bases_and_gaps = required_columns.number_of_bases_and_gaps;
+    line.mapping_quality = required_columns.mapping_quality;
+
+    parse_optional_columns(string, line, column, options)
+}
+
+/// Parses the optional fields of a line that is otherwise in SAM format into the given [PAFLine],
+/// replacing its previous optional fields.
+/// The given string slice starts at the first optional column of the given line and is advanced past the line.
+///
+/// The position of the error is given relative to the start of the line, which is assumed to be line 1,
+/// with the first optional column being the given one-based column.
+pub(crate) fn parse_optional_fields_into<'input>(
+    line_text: &'input str,
+    string: &mut &'input str,
+    first_column: usize,
+    line: &mut PAFLine,
+    options: &ParserOptions,
+) -> Result<()> {
+    let mut column = ColumnTracker::new(line_text);
+    column.index = first_column - 1;
+    parse_optional_columns(string, line, &mut column, options).map_err(|error| column.locate(error))
+}
+
+fn parse_optional_columns<'input>(
+    string: &mut &'input str,
+    line: &mut PAFLine,
+    column: &mut ColumnTracker<'input>,
+    options: &ParserOptions,
+) -> Result<()> {
+    // the allocations of previous values are kept to be reused
+    let mut unknown_md_buffer = line.unknown_md.take();
+    let mut supplementary_alignments_buffer = line.supplementary_alignments.take();
+    let mut transcript_strand_buffer = line.transcript_strand.take();
+    let mut cigar_buffer = line.cigar_string.take().map(|cigar| cigar.0);
+    let mut difference_buffer = line.difference_string.take().map(|difference| difference.0);
+    line.unknown_fields.clear();
+    line.raw_fields.clear();
+    let mut original_fields = if options.lossless {
+        let mut original_fields = line.original_fields.take().unwrap_or_default();
+        original_fields.clear();
+        Some(original_fields)
     } else {
-        return Err(Error::UnexpectedCharacter);
+        line.original_fields = None;
+        None
     };
-    let target_sequence_name = parse_column(string, false)?;
-    let target_sequence_length = parse_column(string, false)?;
-    let target_start_coordinate_on_original_strand = parse_column(string, false)?;
-    let target_end_coordinate_on_original_strand = parse_column(string, false)?;
-    let number_of_matching_bases = parse_column(string, false)?;
-    let number_of_bases_and_gaps = parse_column(string, false)?;
-    let mapping_quality = parse_column(string, true)?;
-
-    // optional fields
-    let mut alignment_type = None;
-    let mut number_of_minimisers = None;
-    let mut chaining_score = None;
-    let mut best_secondary_chaining_score = None;
-    let mut total_number_of_mismatches_and_gaps = None;
-    let mut unknown_md = None;
-    let mut dp_alignment_score = None;
-    let mut supplementary_alignments = None;
-    let mut best_segment_dp_score = None;
-    let mut number_of_ambiguous_bases = None;
-    let mut transcript_strand = None;
-    let mut cigar_string = None;
-    let mut difference_string = None;
-    let mut approximate_per_base_sequence_divergence = None;
-    let mut gap_compressed_per_base_sequence_divergence = None;
-    let mut length_of_query_regions_with_repetitive_seeds = None;
-    let mut unknown_fields = Vec::new();
-
-    loop {
-        if string.starts_with('\n') {
-            *string = &string[1..];
-            break;
+
+    line.alignment_type = None;
+    line.number_of_minimisers = None;
+    line.chaining_score = None;
+    line.best_secondary_chaining_score = None;
+    line.total_number_of_mismatches_and_gaps = None;
+    line.dp_alignment_score = None;
+    line.best_segment_dp_score = None;
+    line.number_of_ambiguous_bases = None;
+    line.approximate_per_base_sequence_divergence = None;
+    line.gap_compressed_per_base_sequence_divergence = None;
+    line.length_of_query_regions_with_repetitive_seeds = None;
+
+    while let Some((header, value)) = next_optional_column(string, column)? {
+        let name = [header.as_bytes()[0], header.as_bytes()[1]];
+        let mode = options.field_mode(&name);
+        if mode == FieldMode::Skip {
+            continue;
         }
 
-        if string.len() < 6 {
-            if !string.is_empty() && *string != "\n" {
-                return Err(Error::UnexpectedCharacter);
-            } else {
-                break;
+        if let Some(original_fields) = &mut original_fields {
+            let duplicate = KNOWN_HEADERS.contains(&header)
+                && original_fields
+                    .iter()
+                    .any(|field| field.known && field.name == name);
+            let text = match header.as_bytes()[3] {
+                b'f' | b'H' | b'B' => Some(value.to_string()),
+                // integers with leading zeros or a plus sign are not reproduced by formatting their value
+                b'i' if value.parse::<i64>().ok().map(|x| x.to_string()).as_deref()
+                    != Some(value) =>
+                {
+                    Some(value.to_string())
+                }
+                _ => None,
+            };
+            original_fields.push(OriginalField {
+                name,
+                known: KNOWN_HEADERS.contains(&header) && !duplicate,
+                text,
+            });
+
+            if duplicate {
+                line.unknown_fields.push(parse_tag(header, value)?);
+                continue;
             }
         }
 
-        let header = &string[..5];
-        *string = &string[5..];
-        match header {
-            "tp:A:" => {
-                alignment_type = Some(match extract_column_value(string)? {
-                    "P" => AlignmentType::Primary,
-                    "S" => AlignmentType::Secondary,
-                    "I" => AlignmentType::PrimaryInversion,
-                    "i" => AlignmentType::SecondaryInversion,
-                    _ => return Err(Error::UnexpectedCharacter),
-                });
+        if mode == FieldMode::Raw && KNOWN_HEADERS.contains(&header) {
+            // like parsed fields, later occurrences overwrite earlier ones
+            line.raw_fields.retain(|tag| tag.name != name);
+            line.raw_fields.push(RawTag {
+                name,
+                value_type: header.as_bytes()[3],
+                value: value.to_string(),
+            });
+            continue;
+        }
+
+        match parse_optional_field(header, value)? {
+            OptionalField::AlignmentType(x) => line.alignment_type = Some(x),
+            OptionalField::NumberOfMinimisers(x) => line.number_of_minimisers = Some(x),
+            OptionalField::ChainingScore(x) => line.chaining_score = Some(x),
+            OptionalField::BestSecondaryChainingScore(x) => {
+                line.best_secondary_chaining_score = Some(x)
+            }
+            OptionalField::TotalNumberOfMismatchesAndGaps(x) => {
+                line.total_number_of_mismatches_and_gaps = Some(x)
+            }
+            OptionalField::UnknownMd(x) => {
+                line.unknown_md = Some(reuse_string(unknown_md_buffer.take(), x))
             }
-            "cm:i:" => {
-                number_of_minimisers = Some(
-                    extract_column_value(string)?
-                        .parse()
-                        .map_err(|_| Error::Message("Malformed cm:i:".to_string()))?,
-                )
+            OptionalField::DpAlignmentScore(x) => line.dp_alignment_score = Some(x),
+            OptionalField::SupplementaryAlignments(x) => {
+                line.supplementary_alignments =
+                    Some(reuse_string(supplementary_alignments_buffer.take(), x))
             }
-            "s1:i:" => {
-                chaining_score = Some(
-                    extract_column_value(string)?
-                        .parse()
-                        .map_err(|_| Error::Message("Malformed s1:i:".to_string()))?,
-                )
+            OptionalField::BestSegmentDpScore(x) => line.best_segment_dp_score = Some(x),
+            OptionalField::NumberOfAmbiguousBases(x) => line.number_of_ambiguous_bases = Some(x),
+            OptionalField::TranscriptStrand(x) => {
+                line.transcript_strand = Some(reuse_string(transcript_strand_buffer.take(), x))
             }
-            "s2:i:" => {
-                best_secondary_chaining_score = Some(
-                    extract_column_value(string)?
-                        .parse()
-                        .map_err(|_| Error::Message("Malformed s2:i:".to_string()))?,
-                )
+            OptionalField::CigarString(x) => {
+                let mut columns = cigar_buffer
+                    .take()
+                    .or_else(|| line.cigar_string.take().map(|cigar| cigar.0))
+                    .unwrap_or_default();
+                columns.clear();
+                for cigar_column in CigarRef(x).iter() {
+                    columns.push(cigar_column?);
+                }
+                line.cigar_string = Some(Cigar(columns));
+            }
+            OptionalField::DifferenceString(x) => {
+                let mut columns = difference_buffer
+                    .take()
+                    .or_else(|| line.difference_string.take().map(|difference| difference.0))
+                    .unwrap_or_default();
+                columns.clear();
+                for difference_column in AlignmentDifferenceRef(x).iter() {
+                    columns.push(difference_column?.to_owned());
+                }
+                line.difference_string = Some(AlignmentDifference(columns));
+            }
+            OptionalField::ApproximatePerBaseSequenceDivergence(x) => {
+                line.approximate_per_base_sequence_divergence = Some(x)
             }
-            "NM:i:" => {
-                total_number_of_mismatches_and_gaps = Some(
-                    extract_column_value(string)?
-                        .parse()
-                        .map_err(|_| Error::Message("Malformed NM:i:".to_string()))?,
-                )
+            OptionalField::GapCompressedPerBaseSequenceDivergence(x) => {
+                line.gap_compressed_per_base_sequence_divergence = Some(x)
+            }
+            OptionalField::LengthOfQueryRegionsWithRepetitiveSeeds(x) => {
+                line.length_of_query_regions_with_repetitive_seeds = Some(x)
+            }
+            OptionalField::Unknown => {
+                warn!("Found unknown field: {header}");
+                line.unknown_fields.push(parse_tag(header, value)?);
+            }
+        }
+    }
+
+    line.original_fields = original_fields;
+    Ok(())
+}
+
+fn parse_columns_ref<'input>(
+    string: &mut &'input str,
+    column: &mut ColumnTracker<'input>,
+) -> Result<PAFLineRef<'input>> {
+    let required_columns = parse_required_columns(string, column)?;
+    let mut line = PAFLineRef {
+        // required fields
+        query_sequence_name: required_columns.query_sequence_name,
+        query_sequence_length: required_columns.query_sequence_length,
+        query_start_coordinate: required_columns.query_start_coordinate,
+        query_end_coordinate: required_columns.query_end_coordinate,
+        strand: required_columns.strand,
+        target_sequence_name: required_columns.target_sequence_name,
+        target_sequence_length: required_columns.target_sequence_length,
+        target_start_coordinate_on_original_strand: required_columns
+            .target_start_coordinate_on_original_strand,
+        target_end_coordinate_on_original_strand: required_columns
+            .target_end_coordinate_on_original_strand,
+        number_of_matching_bases: required_columns.number_of_matching_bases,
+        number_of_bases_and_gaps: required_columns.number_of_bases_and_gaps,
+        mapping_quality: required_columns.mapping_quality,
+
+        // optional fields
+        alignment_type: None,
+        number_of_minimisers: None,
+        chaining_score: None,
+        best_secondary_chaining_score: None,
+        total_number_of_mismatches_and_gaps: None,
+        unknown_md: None,
+        dp_alignment_score: None,
+        supplementary_alignments: None,
+        best_segment_dp_score: None,
+        number_of_ambiguous_bases: None,
+        transcript_strand: None,
+        cigar_string: None,
+        difference_string: None,
+        approximate_per_base_sequence_divergence: None,
+        gap_compressed_per_base_sequence_divergence: None,
+        length_of_query_regions_with_repetitive_seeds: None,
+
+        unknown_fields: UnknownFieldsRef::default(),
+    };
+
+    let optional_fields = *string;
+    while let Some((header, value)) = next_optional_column(string, column)? {
+        match parse_optional_field(header, value)? {
+            OptionalField::AlignmentType(x) => line.alignment_type = Some(x),
+            OptionalField::NumberOfMinimisers(x) => line.number_of_minimisers = Some(x),
+            OptionalField::ChainingScore(x) => line.chaining_score = Some(x),
+            OptionalField::BestSecondaryChainingScore(x) => {
+                line.best_secondary_chaining_score = Some(x)
             }
-            "MD:Z:" => {
-                unknown_md = Some(
-                    extract_column_value(string)?
-                        .parse()
-                        .map_err(|_| Error::Message("Malformed MD:Z:".to_string()))?,
-                )
+            OptionalField::TotalNumberOfMismatchesAndGaps(x) => {
+                line.total_number_of_mismatches_and_gaps = Some(x)
             }
-            "AS:i:" => {
-                dp_alignment_score = Some(
-                    extract_column_value(string)?
-                        .parse()
-                        .map_err(|_| Error::Message("Malformed AS:i:".to_string()))?,
-                )
+            OptionalField::UnknownMd(x) => line.unknown_md = Some(x),
+            OptionalField::DpAlignmentScore(x) => line.dp_alignment_score = Some(x),
+            OptionalField::SupplementaryAlignments(x) => line.supplementary_alignments = Some(x),
+            OptionalField::BestSegmentDpScore(x) => line.best_segment_dp_score = Some(x),
+            OptionalField::NumberOfAmbiguousBases(x) => line.number_of_ambiguous_bases = Some(x),
+            OptionalField::TranscriptStrand(x) => line.transcript_strand = Some(x),
+            OptionalField::CigarString(x) => line.cigar_string = Some(CigarRef(x)),
+            OptionalField::DifferenceString(x) => {
+                line.difference_string = Some(AlignmentDifferenceRef(x))
             }
-            "SA:Z:" => {
-                supplementary_alignments = Some(
-                    extract_column_value(string)?
-                        .parse()
-                        .map_err(|_| Error::Message("Malformed SA:Z:".to_string()))?,
-                )
+            OptionalField::ApproximatePerBaseSequenceDivergence(x) => {
+                line.approximate_per_base_sequence_divergence = Some(x)
             }
-            "ms:i:" => {
-                best_segment_dp_score = Some(
-                    extract_column_value(string)?
-                        .parse()
-                        .map_err(|_| Error::Message("Malformed ms:i:".to_string()))?,
-                )
+            OptionalField::GapCompressedPerBaseSequenceDivergence(x) => {
+                line.gap_compressed_per_base_sequence_divergence = Some(x)
             }
-            "nn:i:" => {
-                number_of_ambiguous_bases = Some(
-                    extract_column_value(string)?
-                        .parse()
-                        .map_err(|_| Error::Message("Malformed nn:i:".to_string()))?,
-                )
+            OptionalField::LengthOfQueryRegionsWithRepetitiveSeeds(x) => {
+                line.length_of_query_regions_with_repetitive_seeds = Some(x)
             }
-            "ts:A:" => {
-                transcript_strand = Some(
-                    extract_column_value(string)?
-                        .parse()
-                        .map_err(|_| Error::Message("Malformed ts:i:".to_string()))?,
-                )
+            OptionalField::Unknown => {
+                parse_tag_header(header)?;
             }
-            "cg:Z:" => cigar_string = Some(parse_cigar(string)?),
-            "cs:Z:" => difference_string = Some(parse_alignment_difference(string)?),
-            "dv:f:" => {
-                approximate_per_base_sequence_divergence = Some(
-                    extract_column_value(string)?
-                        .parse()
-                        .map_err(|_| Error::Message("Malformed dv:f:".to_string()))?,
-                )
+        }
+    }
+
+    let optional_fields = &optional_fields[..optional_fields.len() - string.len()];
+    line.unknown_fields = UnknownFieldsRef(
+        optional_fields
+            .strip_suffix('\n')
+            .unwrap_or(optional_fields)
+            .trim_end_matches('\t'),
+    );
+    Ok(line)
+}
+
+impl PAFLineRef<'_> {
+    /// Converts this line into an owned [PAFLine].
+    ///
+    /// This parses the cigar and alignment difference strings as well as the values of unknown fields,
+    /// which may fail.
+    pub fn to_owned(&self) -> Result<PAFLine> {
+        Ok(PAFLine {
+            // required fields
+            query_sequence_name: self.query_sequence_name.to_string(),
+            query_sequence_length: self.query_sequence_length,
+            query_start_coordinate: self.query_start_coordinate,
+            query_end_coordinate: self.query_end_coordinate,
+            strand: self.strand,
+            target_sequence_name: self.target_sequence_name.to_string(),
+            target_sequence_length: self.target_sequence_length,
+            target_start_coordinate_on_original_strand: self
+                .target_start_coordinate_on_original_strand,
+            target_end_coordinate_on_original_strand: self.target_end_coordinate_on_original_strand,
+            number_of_matching_bases: self.number_of_matching_bases,
+            number_of_bases_and_gaps: self.number_of_bases_and_gaps,
+            mapping_quality: self.mapping_quality,
+
+            // optional fields
+            alignment_type: self.alignment_type.clone(),
+            number_of_minimisers: self.number_of_minimisers,
+            chaining_score: self.chaining_score,
+            best_secondary_chaining_score: self.best_secondary_chaining_score,
+            total_number_of_mismatches_and_gaps: self.total_number_of_mismatches_and_gaps,
+            unknown_md: self.unknown_md.map(ToString::to_string),
+            dp_alignment_score: self.dp_alignment_score,
+            supplementary_alignments: self.supplementary_alignments.map(ToString::to_string),
+            best_segment_dp_score: self.best_segment_dp_score,
+            number_of_ambiguous_bases: self.number_of_ambiguous_bases,
+            transcript_strand: self.transcript_strand.map(ToString::to_string),
+            cigar_string: self.cigar_string.map(|x| x.to_owned()).transpose()?,
+            difference_string: self.difference_string.map(|x| x.to_owned()).transpose()?,
+            approximate_per_base_sequence_divergence: self.approximate_per_base_sequence_divergence,
+            gap_compressed_per_base_sequence_divergence: self
+                .gap_compressed_per_base_sequence_divergence,
+            length_of_query_regions_with_repetitive_seeds: self
+                .length_of_query_regions_with_repetitive_seeds,
+
+            unknown_fields: self
+                .unknown_fields
+                .iter()
+                .map(|tag| tag.to_owned())
+                .collect::<Result<_>>()?,
+            raw_fields: Vec::new(),
+            original_fields: None,
+        })
+    }
+}
+
+impl TagRef<'_> {
+    /// Parses the value of this tag.
+    pub fn parse_value(&self) -> Result<TagValue> {
+        Ok(self.to_owned()?.value)
+    }
+
+    /// Converts this tag into an owned [Tag], parsing its value.
+    pub fn to_owned(&self) -> Result<Tag> {
+        let header = [self.name[0], self.name[1], b':', self.value_type, b':'];
+        let header = std::str::from_utf8(&header).map_err(|_| Error::UnexpectedOptionalColumn {
+            column_header: String::from_utf8_lossy(&header).into_owned(),
+        })?;
+        parse_tag(header, self.value)
+    }
+}
+
+impl<'a> CigarRef<'a> {
+    /// Iterate over the columns of this cigar string, parsing them lazily.
+    pub fn iter(&self) -> CigarColumns<'a> {
+        CigarColumns { remaining: self.0 }
+    }
+
+    /// Converts this cigar string into an owned [Cigar], parsing it.
+    pub fn to_owned(&self) -> Result<Cigar> {
+        self.iter().collect::<Result<_>>().map(Cigar)
+    }
+}
+
+impl<'a> AlignmentDifferenceRef<'a> {
+    /// Iterate over the columns of this alignment difference string, parsing them lazily.
+    pub fn iter(&self) -> DifferenceColumns<'a> {
+        DifferenceColumns { remaining: self.0 }
+    }
+
+    /// Converts this alignment difference string into an owned [AlignmentDifference], parsing it.
+    pub fn to_owned(&self) -> Result<AlignmentDifference> {
+        self.iter()
+            .map(|column| column.map(|column| column.to_owned()))
+            .collect::<Result<_>>()
+            .map(AlignmentDifference)
+    }
+}
+
+/// An iterator over the columns of a [CigarRef] that parses them lazily.
+///
+/// After an error, the iterator is exhausted.
+#[derive(Clone, Debug)]
+pub struct CigarColumns<'a> {
+    remaining: &'a str,
+}
+
+impl Iterator for CigarColumns<'_> {
+    type Item = Result<CigarColumn>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let string = self.remaining;
+        if string.is_empty() {
+            return None;
+        }
+        // stop after errors
+        self.remaining = "";
+
+        let limit = if let Some(limit) = string.find(|character: char| !character.is_ascii_digit())
+        {
+            limit
+        } else {
+            return Some(Err(malformed_cigar(string)));
+        };
+
+        let count = match string[..limit].parse() {
+            Ok(count) => count,
+            Err(_) => return Some(Err(malformed_cigar(string))),
+        };
+        let operation = string[limit..].chars().next().unwrap();
+        let cigar_column = match operation {
+            'M' => CigarColumn::Match(count),
+            'D' => CigarColumn::Deletion(count),
+            'I' => CigarColumn::Insertion(count),
+            'X' => CigarColumn::Mismatch(count),
+            '=' => CigarColumn::SequenceMatch(count),
+            'N' => CigarColumn::Skip(count),
+            'S' => CigarColumn::SoftClip(count),
+            'H' => CigarColumn::HardClip(count),
+            'P' => CigarColumn::Padding(count),
+            _ => return Some(Err(malformed_cigar(string))),
+        };
+        self.remaining = &string[limit + operation.len_utf8()..];
+
+        Some(Ok(cigar_column))
+    }
+}
+
+/// An iterator over the columns of an [AlignmentDifferenceRef] that parses them lazily.
+///
+/// After an error, the iterator is exhausted.
+#[derive(Clone, Debug)]
+pub struct DifferenceColumns<'a> {
+    remaining: &'a str,
+}
+
+impl<'a> Iterator for DifferenceColumns<'a> {
+    type Item = Result<DifferenceColumnRef<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let string = self.remaining;
+        let marker = string.chars().next()?;
+        // stop after errors
+        self.remaining = "";
+
+        let limit = string[marker.len_utf8()..]
+            .find([':', '=', '-', '+', '*', '~'])
+            .map(|limit| limit + marker.len_utf8())
+            .unwrap_or(string.len());
+        let characters = &string[marker.len_utf8()..limit];
+        let malformed = || malformed_alignment_difference(marker, characters);
+
+        let difference_column = match marker {
+            ':' => match characters.parse() {
+                Ok(length) => DifferenceColumnRef::Match { length },
+                Err(_) => return Some(Err(malformed())),
+            },
+            '=' => DifferenceColumnRef::IdenticalSequence {
+                sequence: characters,
+            },
+            '-' => DifferenceColumnRef::Deletion {
+                missing_query_characters: characters,
+            },
+            '+' => DifferenceColumnRef::Insertion {
+                superfluous_query_characters: characters,
+            },
+            '*' => {
+                let mut bases = characters.chars();
+                match (bases.next(), bases.next(), bases.next()) {
+                    (Some(reference), Some(query), None) => {
+                        DifferenceColumnRef::Mismatch { reference, query }
+                    }
+                    _ => return Some(Err(malformed())),
+                }
             }
-            "de:f:" => {
-                gap_compressed_per_base_sequence_divergence = Some(
-                    extract_column_value(string)?
-                        .parse()
-                        .map_err(|_| Error::Message("Malformed de:f:".to_string()))?,
-                )
+            '~' => {
+                let intron = split_at_char_boundary(characters, 2).and_then(|(donor, rest)| {
+                    let (length, acceptor) =
+                        split_at_char_boundary(rest, rest.len().checked_sub(2)?)?;
+                    if donor.chars().count() != 2 || acceptor.chars().count() != 2 {
+                        return None;
+                    }
+                    Some(DifferenceColumnRef::Intron {
+                        donor,
+                        length: length.parse().ok()?,
+                        acceptor,
+                    })
+                });
+                match intron {
+                    Some(intron) => intron,
+                    None => return Some(Err(malformed())),
+                }
             }
-            "rl:i:" => {
-                length_of_query_regions_with_repetitive_seeds = Some(
-                    extract_column_value(string)?
-                        .parse()
-                        .map_err(|_| Error::Message("Malformed rl:i:".to_string()))?,
-                )
+            _ => return Some(Err(malformed())),
+        };
+        self.remaining = &string[limit..];
+
+        Some(Ok(difference_column))
+    }
+}
+
+impl FromStr for Tag {
+    type Err = Error;
+
+    /// Parses a tag in SAM format, e.g. `zd:i:3`.
+    fn from_str(string: &str) -> Result<Self> {
+        let header = string
+            .get(..5)
+            .ok_or_else(|| Error::UnexpectedOptionalColumn {
+                column_header: string.to_string(),
+            })?;
+        parse_tag(header, &string[5..])
+    }
+}
+
+/// Parses a tag given its header (e.g. `zd:i:`) and its value.
+fn parse_tag(header: &str, value: &str) -> Result<Tag> {
+    let (name, value_type) = parse_tag_header(header)?;
+
+    let malformed_value = || Error::MalformedTagValue {
+        tag: header.to_string(),
+        value: value.to_string(),
+    };
+    let value = match value_type {
+        b'A' => {
+            let mut characters = value.chars();
+            match (characters.next(), characters.next()) {
+                (Some(character), None) => TagValue::Character(character),
+                _ => return Err(malformed_value()),
             }
-            other => {
-                warn!("Found unknown field: {other}");
-                let mut unknown_field = other.to_string();
-                unknown_field.push_str(extract_column_value(string)?);
-                unknown_fields.push(unknown_field);
+        }
+        b'i' => TagValue::Integer(value.parse().map_err(|_| malformed_value())?),
+        b'f' => TagValue::Float(value.parse().map_err(|_| malformed_value())?),
+        b'Z' => TagValue::String(value.to_string()),
+        b'H' => {
+            if value.len() % 2 != 0 {
+                return Err(malformed_value());
             }
+            TagValue::Hex(
+                (0..value.len())
+                    .step_by(2)
+                    .map(|index| {
+                        value
+                            .get(index..index + 2)
+                            .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                    })
+                    .collect::<Option<_>>()
+                    .ok_or_else(malformed_value)?,
+            )
+        }
+        b'B' => {
+            let mut elements = value.split(',');
+            let subtype = elements.next().unwrap();
+            TagValue::Array(match subtype {
+                "c" => TagArray::Int8(parse_array_elements(elements).ok_or_else(malformed_value)?),
+                "C" => TagArray::UInt8(parse_array_elements(elements).ok_or_else(malformed_value)?),
+                "s" => TagArray::Int16(parse_array_elements(elements).ok_or_else(malformed_value)?),
+                "S" => {
+                    TagArray::UInt16(parse_array_elements(elements).ok_or_else(malformed_value)?)
+                }
+                "i" => TagArray::Int32(parse_array_elements(elements).ok_or_else(malformed_value)?),
+                "I" => {
+                    TagArray::UInt32(parse_array_elements(elements).ok_or_else(malformed_value)?)
+                }
+                "f" => TagArray::Float(parse_array_elements(elements).ok_or_else(malformed_value)?),
+                _ => return Err(malformed_value()),
+            })
         }
+        _ => unreachable!("validated by parse_tag_header"),
+    };
+
+    Ok(Tag { name, value })
+}
+
+/// Validates the given tag header (e.g. `zd:i:`) and returns the name and the type of the tag.
+fn parse_tag_header(header: &str) -> Result<([u8; 2], u8)> {
+    let header_bytes = header.as_bytes();
+    if header_bytes.len() != 5
+        || !header_bytes[0].is_ascii_alphabetic()
+        || !header_bytes[1].is_ascii_alphanumeric()
+        || header_bytes[2] != b':'
+        || !matches!(header_bytes[3], b'A' | b'i' | b'f' | b'Z' | b'H' | b'B')
+        || header_bytes[4] != b':'
+    {
+        return Err(Error::UnexpectedOptionalColumn {
+            column_header: header.to_string(),
+        });
     }
 
-    Ok(PAFLine {
-        // required fields
-        query_sequence_name,
-        query_sequence_length,
-        query_start_coordinate,
-        query_end_coordinate,
-        strand,
-        target_sequence_name,
-        target_sequence_length,
-        target_start_coordinate_on_original_strand,
-        target_end_coordinate_on_original_strand,
-        number_of_matching_bases,
-        number_of_bases_and_gaps,
-        mapping_quality,
+    Ok(([header_bytes[0], header_bytes[1]], header_bytes[3]))
+}
 
-        // optional fields
-        alignment_type,
-        number_of_minimisers,
-        chaining_score,
-        best_secondary_chaining_score,
-        total_number_of_mismatches_and_gaps,
-        unknown_md,
-        dp_alignment_score,
-        supplementary_alignments,
-        best_segment_dp_score,
-        number_of_ambiguous_bases,
-        transcript_strand,
-        cigar_string,
-        difference_string,
-        approximate_per_base_sequence_divergence,
-        gap_compressed_per_base_sequence_divergence,
-        length_of_query_regions_with_repetitive_seeds,
-
-        unknown_fields,
+fn parse_array_elements<'input, Element: FromStr>(
+    elements: impl Iterator<Item = &'input str>,
+) -> Option<Vec<Element>> {
+    elements.map(|element| element.parse().ok()).collect()
+}
+
+/// Reuse the allocation of the given buffer, if any, to store the given value.
+fn reuse_string(buffer: Option<String>, value: &str) -> String {
+    let mut result = buffer.unwrap_or_default();
+    reuse_string_into(&mut result, value);
+    result
+}
+
+/// Replace the content of the given string with the given value, reusing its allocation.
+fn reuse_string_into(target: &mut String, value: &str) {
+    target.clear();
+    target.push_str(value);
+}
+
+fn parse_column<'input, Type: FromStr>(
+    string: &mut &'input str,
+    column: &mut ColumnTracker<'input>,
+    allow_eol: bool,
+) -> Result<Type> {
+    let text = extract_required_column(string, column, allow_eol)?;
+    text.parse().map_err(|_| Error::ColumnParseError {
+        text: text.to_string(),
     })
 }
 
-fn parse_column<Type: FromStr>(string: &mut &str, allow_eol: bool) -> Result<Type>
-where
-    Type::Err: 'static + std::error::Error,
-{
+fn extract_required_column<'input>(
+    string: &mut &'input str,
+    column: &mut ColumnTracker<'input>,
+    allow_eol: bool,
+) -> Result<&'input str> {
+    column.next(string);
     let limit = if let Some(limit) = string.find(['\t', '\n']) {
-        if string.chars().nth(limit).unwrap() == '\n' && !allow_eol {
+        if string.as_bytes()[limit] == b'\n' && !allow_eol {
             return Err(Error::UnexpectedEndOfLine);
         }
         limit
@@ -253,14 +1072,19 @@ where
     };
 
     let column = &string[..limit];
-    *string = &string[limit + 1..];
+    *string = &string[(limit + 1).min(string.len())..];
+
+    Ok(column)
+}
 
-    column.parse().map_err(|_| Error::ColumnParseError)
+fn parse_tag_value<Type: FromStr>(tag: &str, value: &str) -> Result<Type> {
+    value.parse().map_err(|_| Error::MalformedTagValue {
+        tag: tag.to_string(),
+        value: value.to_string(),
+    })
 }
 
-fn extract_column_value<'input: 'output, 'output>(
-    string: &mut &'input str,
-) -> Result<&'output str> {
+fn extract_column_value<'input>(string: &mut &'input str) -> &'input str {
     let limit = if let Some(limit) = string.find(['\t', '\n']) {
         limit
     } else {
@@ -270,82 +1094,25 @@ fn extract_column_value<'input: 'output, 'output>(
     let column = &string[..limit];
     *string = &string[(limit + 1).min(string.len())..];
 
-    Ok(column)
+    column
 }
 
-fn parse_cigar(string: &mut &str) -> Result<Cigar> {
-    let mut result = Vec::new();
-
-    while !string.is_empty() && !string.starts_with(['\t', '\n']) {
-        let limit = if let Some(limit) = string.find(['M', 'D', 'I', 'X']) {
-            limit
-        } else {
-            return Err(Error::MalformedCigar);
-        };
-
-        let count = string[..limit]
-            .parse()
-            .map_err(|_| Error::Message(format!("Malformed cigar count: {}", &string[..limit])))?;
-        result.push(match &string[limit..limit + 1] {
-            "M" => CigarColumn::Match(count),
-            "D" => CigarColumn::Deletion(count),
-            "I" => CigarColumn::Insertion(count),
-            "X" => CigarColumn::Mismatch(count),
-            _ => return Err(Error::MalformedCigar),
-        });
-        *string = &string[limit + 1..];
-    }
-
-    if string.starts_with(['\t']) {
-        *string = &string[1..];
+fn split_at_char_boundary(string: &str, index: usize) -> Option<(&str, &str)> {
+    if string.is_char_boundary(index) {
+        Some(string.split_at(index))
+    } else {
+        None
     }
-
-    Ok(Cigar(result))
 }
 
-fn parse_alignment_difference(string: &mut &str) -> Result<AlignmentDifference> {
-    let mut result = Vec::new();
-
-    while !string.is_empty() && !string.starts_with(['\t', '\n']) {
-        let limit = string[1..]
-            .find([':', '-', '+', '*', '\t', '\n'])
-            .map(|limit| limit + 1)
-            .unwrap_or(string.len());
-        let marker = &string[..1];
-        let characters = &string[1..limit];
-        *string = &string[limit..];
-
-        result.push(match marker {
-            ":" => DifferenceColumn::Match {
-                length: characters.parse().map_err(|_| {
-                    Error::Message(format!(
-                        "Malformed alignment difference length: {}",
-                        characters
-                    ))
-                })?,
-            },
-            "-" => DifferenceColumn::Deletion {
-                missing_query_characters: characters.to_string(),
-            },
-            "+" => DifferenceColumn::Insertion {
-                superfluous_query_characters: characters.to_string(),
-            },
-            "*" => {
-                if characters.chars().count() != 2 {
-                    return Err(Error::MalformedAlignmentDifference);
-                }
-                DifferenceColumn::Mismatch {
-                    reference: characters.chars().nth(0).unwrap(),
-                    query: characters.chars().nth(1).unwrap(),
-                }
-            }
-            _ => return Err(Error::MalformedAlignmentDifference),
-        })
+fn malformed_cigar(remaining: &str) -> Error {
+    Error::MalformedCigar {
+        text: remaining.to_string(),
     }
+}
 
-    if string.starts_with(['\t']) {
-        *string = &string[1..];
+fn malformed_alignment_difference(marker: char, characters: &str) -> Error {
+    Error::MalformedAlignmentDifference {
+        text: format!("{marker}{characters}"),
     }
-
-    Ok(AlignmentDifference(result))
 }
@@ -0,0 +1,315 @@
+use crate::data::PAFLine;
+use crate::error::Result;
+use crate::index::IndexKey;
+use crate::input::PafReader;
+use std::collections::HashMap;
+use std::io::BufRead;
+
+/// An in-memory index of PAF lines that answers overlap queries on target or query coordinates.
+///
+/// For each sequence, the alignments are stored in an implicit augmented interval tree,
+/// i.e. an array sorted by start coordinate, where each element stores the maximum end coordinate of its subtree.
+/// Coordinates are on the original strand and regions are zero-based half-open intervals.
+/// Unmapped lines are only indexed by their query coordinates.
+#[derive(Clone, Debug, Default)]
+pub struct AlignmentIndex {
+    lines: Vec<PAFLine>,
+    target_trees: HashMap<String, IntervalTree>,
+    query_trees: HashMap<String, IntervalTree>,
+}
+
+impl AlignmentIndex {
+    /// Build an index over the given lines.
+    pub fn new(lines: Vec<PAFLine>) -> Self {
+        let mut target_intervals: HashMap<_, Vec<_>> = HashMap::new();
+        let mut query_intervals: HashMap<_, Vec<_>> = HashMap::new();
+
+        for (index, line) in lines.iter().enumerate() {
+            if !line.is_unmapped() {
+                target_intervals
+                    .entry(line.target_sequence_name.as_str())
+                    .or_default()
+                    .push(Interval::new(
+                        line.target_start_coordinate_on_original_strand,
+                        line.target_end_coordinate_on_original_strand,
+                        index,
+                    ));
+            }
+            query_intervals
+                .entry(line.query_sequence_name.as_str())
+                .or_default()
+                .push(Interval::new(
+                    line.query_start_coordinate,
+                    line.query_end_coordinate,
+                    index,
+                ));
+        }
+
+        let build_trees = |intervals: HashMap<&str, Vec<Interval>>| {
+            intervals
+                .into_iter()
+                .map(|(name, intervals)| (name.to_string(), IntervalTree::new(intervals)))
+                .collect()
+        };
+        let target_trees = build_trees(target_intervals);
+        let query_trees = build_trees(query_intervals);
+
+        Self {
+            lines,
+            target_trees,
+            query_trees,
+        }
+    }
+
+    /// Read all lines from the given input and build an index over them.
+    pub fn from_reader<Input: BufRead>(input: Input) -> Result<Self> {
+        Ok(Self::new(PafReader::new(input).collect::<Result<_>>()?))
+    }
+
+    /// Returns all indexed lines in their original order.
+    pub fn lines(&self) -> &[PAFLine] {
+        &self.lines
+    }
+
+    /// Returns all indexed lines in their original order.
+    pub fn into_lines(self) -> Vec<PAFLine> {
+        self.lines
+    }
+
+    /// Returns all lines that overlap the given region, ordered by their start coordinate.
+    pub fn overlapping(
+        &self,
+        key: IndexKey,
+        sequence_name: &str,
+        start: usize,
+        end: usize,
+    ) -> Vec<&PAFLine> {
+        self.query_intervals(key, sequence_name, start, end, |_| true)
+    }
+
+    /// Returns all lines whose aligned region contains the given region, ordered by their start coordinate.
+    pub fn containing(
+        &self,
+        key: IndexKey,
+        sequence_name: &str,
+        start: usize,
+        end: usize,
+    ) -> Vec<&PAFLine> {
+        self.query_intervals(key, sequence_name, start, end, |interval| {
+            interval.start <= start && interval.end >= end
+        })
+    }
+
+    /// Returns all lines whose aligned region is contained in the given region, ordered by their start coordinate.
+    pub fn contained_in(
+        &self,
+        key: IndexKey,
+        sequence_name: &str,
+        start: usize,
+        end: usize,
+    ) -> Vec<&PAFLine> {
+        self.query_intervals(key, sequence_name, start, end, |interval| {
+            interval.start >= start && interval.end <= end
+        })
+    }
+
+    /// Returns the line whose aligned region is closest to the given position.
+    ///
+    /// Lines overlapping the position have distance zero.
+    /// Otherwise, the distance is the number of bases between the position and the closest end of the aligned region.
+    /// Ties are broken in favour of the line with the smaller start coordinate.
+    pub fn nearest(&self, key: IndexKey, sequence_name: &str, position: usize) -> Option<&PAFLine> {
+        let tree = self.tree(key, sequence_name)?;
+        if let Some(&index) = tree.overlapping(position, position + 1).first() {
+            return Some(&self.lines[tree.intervals[index].line]);
+        }
+
+        // the first interval starting after the position
+        let right = tree
+            .intervals
+            .partition_point(|interval| interval.start <= position);
+        // the interval with the largest end coordinate among those starting before the position
+        let left = right.checked_sub(1).map(|index| tree.prefix_max_end[index]);
+
+        let distance = |index: usize| tree.intervals[index].distance(position);
+        let nearest = match (left, tree.intervals.get(right)) {
+            (Some(left), Some(_)) if distance(left) <= distance(right) => left,
+            (_, Some(_)) => right,
+            (Some(left), None) => left,
+            (None, None) => return None,
+        };
+        Some(&self.lines[tree.intervals[nearest].line])
+    }
+
+    fn tree(&self, key: IndexKey, sequence_name: &str) -> Option<&IntervalTree> {
+        match key {
+            IndexKey::Target => self.target_trees.get(sequence_name),
+            IndexKey::Query => self.query_trees.get(sequence_name),
+        }
+    }
+
+    fn query_intervals(
+        &self,
+        key: IndexKey,
+        sequence_name: &str,
+        start: usize,
+        end: usize,
+        filter: impl Fn(&Interval) -> bool,
+    ) -> Vec<&PAFLine> {
+        if let Some(tree) = self.tree(key, sequence_name) {
+            let mut indices = tree.overlapping(start, end);
+            indices.sort_unstable();
+            indices
+                .into_iter()
+                .map(|index| &tree.intervals[index])
+                .filter(|interval| filter(interval))
+                .map(|interval| &self.lines[interval.line])
+                .collect()
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+impl FromIterator<PAFLine> for AlignmentIndex {
+    fn from_iter<Iter: IntoIterator<Item = PAFLine>>(iter: Iter) -> Self {
+        Self::new(iter.into_iter().collect())
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Interval {
+    start: usize,
+    end: usize,
+    /// The maximum end coordinate within the subtree rooted at this interval.
+    max_end: usize,
+    /// The index of the line of this interval.
+    line: usize,
+}
+
+impl Interval {
+    fn new(start: usize, end: usize, line: usize) -> Self {
+        Self {
+            start,
+            end,
+            max_end: end,
+            line,
+        }
+    }
+
+    fn distance(&self, position: usize) -> usize {
+        if position < self.start {
+            self.start - position
+        } else if position >= self.end {
+            position + 1 - self.end
+        } else {
+            0
+        }
+    }
+}
+
+/// An implicit augmented interval tree, as in Heng Li's cgranges.
+///
+/// The intervals are sorted by start coordinate, and the interval at index `i` is a node at level `k`,
+/// where `k` is the number of trailing one bits of `i`.
+#[derive(Clone, Debug)]
+struct IntervalTree {
+    intervals: Vec<Interval>,
+    max_level: usize,
+    /// For each index, the index of the interval with the largest end coordinate up to and including the index.
+    prefix_max_end: Vec<usize>,
+}
+
+impl IntervalTree {
+    fn new(mut intervals: Vec<Interval>) -> Self {
+        intervals.sort_unstable_by_key(|interval| (interval.start, interval.end));
+        let length = intervals.len();
+
+        let mut prefix_max_end: Vec<usize> = Vec::with_capacity(length);
+        for index in 0..length {
+            match prefix_max_end.last() {
+                Some(&max_index) if intervals[max_index].end >= intervals[index].end => {
+                    prefix_max_end.push(max_index)
+                }
+                _ => prefix_max_end.push(index),
+            }
+        }
+
+        let mut last_index = 0;
+        let mut last_max_end = 0;
+        for index in (0..length).step_by(2) {
+            last_index = index;
+            last_max_end = intervals[index].end;
+        }
+        let mut level = 1;
+        while 1 << level <= length {
+            let half = 1 << (level - 1);
+            for index in ((half << 1) - 1..length).step_by(half << 2) {
+                let left_max_end = intervals[index - half].max_end;
+                let right_max_end = intervals
+                    .get(index + half)
+                    .map(|interval| interval.max_end)
+                    .unwrap_or(last_max_end);
+                let interval = &mut intervals[index];
+                interval.max_end = interval.end.max(left_max_end).max(right_max_end);
+            }
+
+            last_index = if (last_index >> level) & 1 != 0 {
+                last_index - half
+            } else {
+                last_index + half
+            };
+            if last_index < length {
+                last_max_end = last_max_end.max(intervals[last_index].max_end);
+            }
+            level += 1;
+        }
+
+        Self {
+            intervals,
+            max_level: level - 1,
+            prefix_max_end,
+        }
+    }
+
+    /// Returns the indices of all intervals overlapping the given region, in no particular order.
+    fn overlapping(&self, start: usize, end: usize) -> Vec<usize> {
+        let mut result = Vec::new();
+        if self.intervals.is_empty() {
+            return result;
+        }
+
+        let length = self.intervals.len();
+        let mut stack = vec![((1 << self.max_level) - 1, self.max_level, false)];
+        while let Some((index, level, visited_left)) = stack.pop() {
+            if level <= 3 {
+                // small subtrees are scanned linearly
+                let first = index >> level << level;
+                let limit = (first + (1 << (level + 1)) - 1).min(length);
+                for (offset, interval) in
+                    self.intervals[first.min(length)..limit].iter().enumerate()
+                {
+                    if interval.start >= end {
+                        break;
+                    }
+                    if start < interval.end {
+                        result.push(first + offset);
+                    }
+                }
+            } else if !visited_left {
+                let left_child = index - (1 << (level - 1));
+                stack.push((index, level, true));
+                if left_child >= length || self.intervals[left_child].max_end > start {
+                    stack.push((left_child, level - 1, false));
+                }
+            } else if index < length && self.intervals[index].start < end {
+                if start < self.intervals[index].end {
+                    result.push(index);
+                }
+                stack.push((index + (1 << (level - 1)), level - 1, false));
+            }
+        }
+
+        result
+    }
+}
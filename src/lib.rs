@@ -4,13 +4,42 @@
 //! Read and write minimap2 PAF files.
 //! Lines are internally stored as struct [PAFLine].
 
+/// Evaluate assemblies from their alignments to a reference.
+pub mod assembly;
+/// Read and write BED files.
+pub mod bed;
+/// Call variants from alignment difference strings.
+pub mod call;
+/// Convert between PAF lines and UCSC chain files.
+pub mod chain;
+/// Merge alignments that wrap the origin of circular sequences.
+pub mod circular;
+/// Read and write compressed files.
+pub mod compression;
+/// Compute the depth of coverage of target sequences.
+pub mod coverage;
 /// The data structures storing PAF lines.
 pub mod data;
 /// Error handling types.
 pub mod error;
+/// Random access to PAF files by sequence region.
+pub mod index;
 /// Read PAF lines.
 pub mod input;
+/// Overlap queries over PAF lines in memory.
+pub mod interval;
+/// Lift intervals over from query to target coordinates.
+pub mod liftover;
 /// Output PAF lines.
 pub mod output;
+/// Parse PAF lines on multiple threads.
+#[cfg(feature = "parallel")]
+pub mod parallel;
+/// Project positions between the query and target of an alignment.
+pub mod projection;
+/// Convert between PAF lines and SAM records.
+pub mod sam;
+/// Detect structural variants from the alignments of each query.
+pub mod sv;
 #[cfg(test)]
 mod tests;
@@ -0,0 +1,232 @@
+use crate::bed::{BedReader, BedRecord};
+use crate::data::{AlignmentType, PAFLine, Strand};
+use crate::error::Result;
+use crate::index::IndexKey;
+use crate::interval::AlignmentIndex;
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, Write};
+
+/// Options for lifting intervals over from query to target coordinates.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LiftoverOptions {
+    /// Alignments with a lower mapping quality are ignored.
+    pub min_mapping_quality: u8,
+    /// If true, only primary alignments (`tp:A:P`) are used.
+    pub primary_only: bool,
+    /// If true, intervals are split at insertions and deletions,
+    /// such that each lifted interval is aligned without gaps.
+    pub split_at_indels: bool,
+}
+
+impl LiftoverOptions {
+    /// Ignore alignments with a mapping quality lower than the given one.
+    pub fn with_min_mapping_quality(mut self, min_mapping_quality: u8) -> Self {
+        self.min_mapping_quality = min_mapping_quality;
+        self
+    }
+
+    /// Use only primary alignments (`tp:A:P`), if set to true.
+    pub fn with_primary_only(mut self, primary_only: bool) -> Self {
+        self.primary_only = primary_only;
+        self
+    }
+
+    /// Split lifted intervals at insertions and deletions, if set to true.
+    pub fn with_split_at_indels(mut self, split_at_indels: bool) -> Self {
+        self.split_at_indels = split_at_indels;
+        self
+    }
+
+    fn accepts(&self, line: &PAFLine) -> bool {
+        line.mapping_quality >= self.min_mapping_quality
+            && (!self.primary_only || line.alignment_type == Some(AlignmentType::Primary))
+    }
+}
+
+/// An interval lifted over from the query to the target of an alignment.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LiftedInterval {
+    /// The lifted interval on the target, with the extra fields of the original record.
+    /// On the reverse strand, the strand column is flipped.
+    pub record: BedRecord,
+    /// The fraction of the bases of the original interval that is aligned to bases within the lifted interval.
+    pub mapped_fraction: f64,
+    /// The strand of the alignment the interval was lifted through.
+    pub strand: Strand,
+}
+
+impl Display for LiftedInterval {
+    /// Writes the lifted record as BED line, with the mapped fraction appended as an additional column.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}\t{:.4}", self.record, self.mapped_fraction)
+    }
+}
+
+/// Lifts intervals on query sequences over to target sequences through a set of alignments,
+/// like `paftools.js liftover`.
+///
+/// Alignments are walked through their cigar string or, if it is missing, through their alignment difference string.
+/// Alignments without either are ignored.
+#[derive(Clone, Debug)]
+pub struct Liftover {
+    index: AlignmentIndex,
+    options: LiftoverOptions,
+}
+
+impl Liftover {
+    /// Create a new liftover through the given alignments.
+    pub fn new(index: AlignmentIndex, options: LiftoverOptions) -> Self {
+        Self { index, options }
+    }
+
+    /// Lift the given record on query coordinates over to target coordinates, through all alignments overlapping it.
+    ///
+    /// Returns one interval per alignment, or, if splitting at indels, one interval per gapless block of each alignment.
+    /// Parts of the record that are not aligned are dropped.
+    pub fn lift(&self, record: &BedRecord) -> Vec<LiftedInterval> {
+        let mut result = Vec::new();
+        if record.is_empty() {
+            return result;
+        }
+
+        for line in self.index.overlapping(
+            IndexKey::Query,
+            &record.chromosome,
+            record.start,
+            record.end,
+        ) {
+            if !self.options.accepts(line) {
+                continue;
+            }
+            let blocks = match aligned_blocks(line, record.start, record.end) {
+                Some(blocks) if !blocks.is_empty() => blocks,
+                _ => continue,
+            };
+
+            if self.options.split_at_indels {
+                for block in &blocks {
+                    result.push(lifted_interval(
+                        record,
+                        line,
+                        block.target_start,
+                        block.target_start + block.length,
+                        block.length,
+                    ));
+                }
+            } else {
+                let target_start = blocks.first().unwrap().target_start;
+                let last_block = blocks.last().unwrap();
+                let aligned_length = blocks.iter().map(|block| block.length).sum();
+                result.push(lifted_interval(
+                    record,
+                    line,
+                    target_start,
+                    last_block.target_start + last_block.length,
+                    aligned_length,
+                ));
+            }
+        }
+
+        result
+    }
+
+    /// Lift all records of the given BED input and write the lifted intervals to the given output.
+    ///
+    /// See [Display for LiftedInterval](LiftedInterval#impl-Display-for-LiftedInterval) for the output format.
+    pub fn lift_bed(&self, input: impl BufRead, mut output: impl Write) -> Result<()> {
+        for record in BedReader::new(input) {
+            for interval in self.lift(&record?) {
+                writeln!(output, "{interval}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A gapless block of an alignment.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct AlignedBlock {
+    target_start: usize,
+    length: usize,
+}
+
+/// Returns the gapless blocks of the alignment that align the given query interval, ordered by target coordinate.
+/// Adjacent blocks that are contiguous in both sequences, e.g. `=` and `X` cigar columns, are merged.
+fn aligned_blocks(line: &PAFLine, start: usize, end: usize) -> Option<Vec<AlignedBlock>> {
+    let start = start.max(line.query_start_coordinate);
+    let end = end.min(line.query_end_coordinate);
+    if start >= end {
+        return Some(Vec::new());
+    }
+    let (aligned_start, aligned_end) = match line.strand {
+        Strand::Forward => (
+            start - line.query_start_coordinate,
+            end - line.query_start_coordinate,
+        ),
+        Strand::Reverse => (
+            line.query_end_coordinate - end,
+            line.query_end_coordinate - start,
+        ),
+        Strand::Unmapped => return None,
+    };
+
+    let mut blocks: Vec<AlignedBlock> = Vec::new();
+    // false if a gap follows the last block
+    let mut extends_last_block = false;
+    let mut query_offset = 0;
+    let mut target_offset = line.target_start_coordinate_on_original_strand;
+    for operation in line.alignment_operations()? {
+        if query_offset >= aligned_end {
+            break;
+        }
+
+        if operation.query_length > 0 && operation.target_length > 0 {
+            let block_start = aligned_start.max(query_offset);
+            let block_end = aligned_end.min(query_offset + operation.query_length);
+            if block_start < block_end {
+                let block = AlignedBlock {
+                    target_start: target_offset + block_start - query_offset,
+                    length: block_end - block_start,
+                };
+                match blocks.last_mut() {
+                    Some(last) if extends_last_block => last.length += block.length,
+                    _ => blocks.push(block),
+                }
+                extends_last_block = true;
+            }
+        } else {
+            extends_last_block = false;
+        }
+
+        query_offset += operation.query_length;
+        target_offset += operation.target_length;
+    }
+
+    Some(blocks)
+}
+
+fn lifted_interval(
+    record: &BedRecord,
+    line: &PAFLine,
+    start: usize,
+    end: usize,
+    aligned_length: usize,
+) -> LiftedInterval {
+    let mut extra_fields = record.extra_fields.clone();
+    if line.strand == Strand::Reverse {
+        if let Some(strand) = record.strand() {
+            extra_fields[2] = if strand == '+' { "-" } else { "+" }.to_string();
+        }
+    }
+
+    LiftedInterval {
+        record: BedRecord {
+            chromosome: line.target_sequence_name.clone(),
+            start,
+            end,
+            extra_fields,
+        },
+        mapped_fraction: aligned_length as f64 / record.len() as f64,
+        strand: line.strand,
+    }
+}
@@ -1,83 +1,547 @@
 use crate::data::{
-    AlignmentDifference, AlignmentType, Cigar, CigarColumn, DifferenceColumn, PAFLine,
+    AlignmentDifference, AlignmentType, Cigar, CigarColumn, DifferenceColumn, PAFLine, Strand, Tag,
+    TagArray, TagValue, KNOWN_TAGS,
 };
-use std::fmt::{Display, Formatter};
+use std::fmt::{Display, Formatter, Write as _};
+use std::io::Write;
+
+/// Options for writing PAF lines.
+#[derive(Clone, Debug, Default)]
+pub struct WriterOptions {
+    /// Optional fields with these names are not written, e.g. `*b"cs"`.
+    /// This applies to both known and unknown fields.
+    pub excluded_tags: Vec<[u8; 2]>,
+    /// If set, optional fields with these names are written first, in the given order.
+    /// The remaining fields follow in the default order.
+    /// This overrides the original layout recorded in lossless mode.
+    pub tag_order: Option<Vec<[u8; 2]>>,
+    /// If set, the float values of `de` and `dv` are written with this number of decimal places.
+    pub float_precision: Option<usize>,
+    /// If true, [PAFLine::unknown_fields] are not written.
+    pub omit_unknown_fields: bool,
+}
+
+impl WriterOptions {
+    /// Do not write optional fields with the given names, see [WriterOptions::excluded_tags].
+    pub fn with_excluded_tags(mut self, excluded_tags: impl IntoIterator<Item = [u8; 2]>) -> Self {
+        self.excluded_tags.extend(excluded_tags);
+        self
+    }
+
+    /// Write optional fields in the given order, see [WriterOptions::tag_order].
+    pub fn with_tag_order(mut self, tag_order: impl IntoIterator<Item = [u8; 2]>) -> Self {
+        self.tag_order = Some(tag_order.into_iter().collect());
+        self
+    }
+
+    /// Write `de` and `dv` with the given number of decimal places, see [WriterOptions::float_precision].
+    pub fn with_float_precision(mut self, float_precision: usize) -> Self {
+        self.float_precision = Some(float_precision);
+        self
+    }
+
+    /// Do not write unknown fields, see [WriterOptions::omit_unknown_fields].
+    pub fn with_omit_unknown_fields(mut self, omit_unknown_fields: bool) -> Self {
+        self.omit_unknown_fields = omit_unknown_fields;
+        self
+    }
+
+    fn includes(&self, name: &[u8; 2]) -> bool {
+        !self.excluded_tags.contains(name)
+    }
+}
+
+/// A writer of PAF lines.
+///
+/// Lines are formatted into an internal buffer, which is then written to the output in one piece.
+/// The output is not buffered otherwise, so wrapping it into a [std::io::BufWriter] may improve performance.
+#[derive(Debug)]
+pub struct PafWriter<Output: Write> {
+    output: Output,
+    options: WriterOptions,
+    buffer: String,
+}
+
+impl<Output: Write> PafWriter<Output> {
+    /// Create a new writer that writes PAF lines to the given output.
+    pub fn new(output: Output) -> Self {
+        Self::with_options(output, WriterOptions::default())
+    }
+
+    /// Create a new writer that writes PAF lines to the given output using the given options.
+    pub fn with_options(output: Output, options: WriterOptions) -> Self {
+        Self {
+            output,
+            options,
+            buffer: String::new(),
+        }
+    }
+
+    /// Write the given line, terminated by a newline.
+    pub fn write_line(&mut self, line: &PAFLine) -> std::io::Result<()> {
+        self.buffer.clear();
+        format_line(&mut self.buffer, line, &self.options);
+        self.buffer.push('\n');
+        self.output.write_all(self.buffer.as_bytes())
+    }
+
+    /// Flush the underlying output.
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.output.flush()
+    }
+
+    /// Returns a reference to the underlying output.
+    pub fn get_ref(&self) -> &Output {
+        &self.output
+    }
+
+    /// Returns the underlying output.
+    pub fn into_inner(self) -> Output {
+        self.output
+    }
+}
 
 impl Display for PAFLine {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        // required fields
-        write!(f, "{}", self.query_sequence_name)?;
-        write!(f, "\t{}", self.query_sequence_length)?;
-        write!(f, "\t{}", self.query_start_coordinate)?;
-        write!(f, "\t{}", self.query_end_coordinate)?;
-        write!(f, "\t{}", if self.strand { '+' } else { '-' })?;
-        write!(f, "\t{}", self.target_sequence_name)?;
-        write!(f, "\t{}", self.target_sequence_length)?;
-        write!(f, "\t{}", self.target_start_coordinate_on_original_strand)?;
-        write!(f, "\t{}", self.target_end_coordinate_on_original_strand)?;
-        write!(f, "\t{}", self.number_of_matching_bases)?;
-        write!(f, "\t{}", self.number_of_bases_and_gaps)?;
-        write!(f, "\t{}", self.mapping_quality)?;
+        let mut buffer = String::new();
+        format_line(&mut buffer, self, &WriterOptions::default());
+        f.write_str(&buffer)
+    }
+}
+
+/// Formats the given line without a terminating newline.
+fn format_line(buffer: &mut String, line: &PAFLine, options: &WriterOptions) {
+    // required fields
+    buffer.push_str(&line.query_sequence_name);
+    buffer.push('\t');
+    push_unsigned(buffer, line.query_sequence_length as u64);
+    buffer.push('\t');
+    push_unsigned(buffer, line.query_start_coordinate as u64);
+    buffer.push('\t');
+    push_unsigned(buffer, line.query_end_coordinate as u64);
+    buffer.push('\t');
+    buffer.push(strand_character(line.strand));
+    buffer.push('\t');
+    buffer.push_str(&line.target_sequence_name);
+    buffer.push('\t');
+    push_unsigned(buffer, line.target_sequence_length as u64);
+    buffer.push('\t');
+    push_unsigned(
+        buffer,
+        line.target_start_coordinate_on_original_strand as u64,
+    );
+    buffer.push('\t');
+    push_unsigned(buffer, line.target_end_coordinate_on_original_strand as u64);
+    buffer.push('\t');
+    push_unsigned(buffer, line.number_of_matching_bases as u64);
+    buffer.push('\t');
+    push_unsigned(buffer, line.number_of_bases_and_gaps as u64);
+    buffer.push('\t');
+    push_unsigned(buffer, line.mapping_quality.into());
+
+    format_optional_fields(buffer, line, options);
+}
+
+/// Formats the optional fields of the given line, each preceded by a tab.
+pub(crate) fn format_optional_fields(buffer: &mut String, line: &PAFLine, options: &WriterOptions) {
+    let mut written_known_fields = Vec::new();
+    let mut write_known_field_once =
+        |buffer: &mut String, name: &[u8; 2], original_text: Option<&str>| {
+            if !written_known_fields.contains(name) {
+                written_known_fields.push(*name);
+                if options.includes(name) {
+                    format_known_field(buffer, line, name, original_text, options);
+                    format_raw_field(buffer, line, name);
+                }
+            }
+        };
+    let write_unknown_fields = !options.omit_unknown_fields;
 
-        // optional fields with known order
-        if let Some(x) = self.total_number_of_mismatches_and_gaps {
-            write!(f, "\tNM:i:{x}")?;
+    if let Some(tag_order) = &options.tag_order {
+        for name in tag_order {
+            if KNOWN_TAGS.contains(name) {
+                write_known_field_once(buffer, name, None);
+            } else if write_unknown_fields && options.includes(name) {
+                for unknown_field in line.unknown_fields.iter().filter(|tag| &tag.name == name) {
+                    format_unknown_field(buffer, unknown_field, None);
+                }
+            }
         }
-        if let Some(x) = self.best_segment_dp_score {
-            write!(f, "\tms:i:{x}")?;
+        for name in &KNOWN_TAGS {
+            write_known_field_once(buffer, name, None);
         }
-        if let Some(x) = self.dp_alignment_score {
-            write!(f, "\tAS:i:{x}")?;
+        if write_unknown_fields {
+            for unknown_field in &line.unknown_fields {
+                if options.includes(&unknown_field.name) && !tag_order.contains(&unknown_field.name)
+                {
+                    format_unknown_field(buffer, unknown_field, None);
+                }
+            }
         }
-        if let Some(x) = self.number_of_ambiguous_bases {
-            write!(f, "\tnn:i:{x}")?;
+    } else if let Some(original_fields) = &line.original_fields {
+        // replay the original layout, and write fields that were added afterwards at the end
+        let mut unknown_fields = line.unknown_fields.iter();
+        for original_field in original_fields {
+            if original_field.known {
+                write_known_field_once(
+                    buffer,
+                    &original_field.name,
+                    original_field.text.as_deref(),
+                );
+            } else if let Some(unknown_field) = unknown_fields.next() {
+                if write_unknown_fields && options.includes(&unknown_field.name) {
+                    format_unknown_field(buffer, unknown_field, original_field.text.as_deref());
+                }
+            }
+        }
+        for name in &KNOWN_TAGS {
+            write_known_field_once(buffer, name, None);
         }
-        if let Some(x) = &self.alignment_type {
-            write!(f, "\ttp:A:{x}")?;
+        if write_unknown_fields {
+            for unknown_field in unknown_fields {
+                if options.includes(&unknown_field.name) {
+                    format_unknown_field(buffer, unknown_field, None);
+                }
+            }
         }
-        if let Some(x) = self.number_of_minimisers {
-            write!(f, "\tcm:i:{x}")?;
+    } else {
+        for name in &KNOWN_TAGS {
+            write_known_field_once(buffer, name, None);
         }
-        if let Some(x) = self.chaining_score {
-            write!(f, "\ts1:i:{x}")?;
+        if write_unknown_fields {
+            for unknown_field in &line.unknown_fields {
+                if options.includes(&unknown_field.name) {
+                    format_unknown_field(buffer, unknown_field, None);
+                }
+            }
         }
-        if let Some(x) = self.best_secondary_chaining_score {
-            write!(f, "\ts2:i:{x}")?;
+    }
+}
+
+/// Formats the known optional field with the given name, if it is present in the given line.
+/// If the original text of the value is given and still represents the value, it is written instead of the value.
+fn format_known_field(
+    buffer: &mut String,
+    line: &PAFLine,
+    name: &[u8; 2],
+    original_text: Option<&str>,
+    options: &WriterOptions,
+) {
+    match name {
+        b"NM" => {
+            if let Some(x) = line.total_number_of_mismatches_and_gaps {
+                buffer.push_str("\tNM:i:");
+                push_unsigned_field(buffer, x as u64, original_text);
+            }
         }
-        if let Some(x) = self.gap_compressed_per_base_sequence_divergence {
-            write!(f, "\tde:f:{x}")?;
+        b"ms" => {
+            if let Some(x) = line.best_segment_dp_score {
+                buffer.push_str("\tms:i:");
+                push_signed_field(buffer, x as i64, original_text);
+            }
         }
-        if let Some(x) = self.length_of_query_regions_with_repetitive_seeds {
-            write!(f, "\trl:i:{x}")?;
+        b"AS" => {
+            if let Some(x) = line.dp_alignment_score {
+                buffer.push_str("\tAS:i:");
+                push_signed_field(buffer, x as i64, original_text);
+            }
         }
-
-        // optional fields without known order, or that do not contradict the known order (cg and cs)
-        // the order is the same as in the man page at https://lh3.github.io/minimap2/minimap2.html#10
-        if let Some(x) = &self.unknown_md {
-            write!(f, "\tMD:Z:{x}")?;
+        b"nn" => {
+            if let Some(x) = line.number_of_ambiguous_bases {
+                buffer.push_str("\tnn:i:");
+                push_unsigned_field(buffer, x as u64, original_text);
+            }
+        }
+        b"tp" => {
+            if let Some(x) = &line.alignment_type {
+                write!(buffer, "\ttp:A:{x}").unwrap();
+            }
+        }
+        b"cm" => {
+            if let Some(x) = line.number_of_minimisers {
+                buffer.push_str("\tcm:i:");
+                push_unsigned_field(buffer, x as u64, original_text);
+            }
+        }
+        b"s1" => {
+            if let Some(x) = line.chaining_score {
+                buffer.push_str("\ts1:i:");
+                push_signed_field(buffer, x as i64, original_text);
+            }
+        }
+        b"s2" => {
+            if let Some(x) = line.best_secondary_chaining_score {
+                buffer.push_str("\ts2:i:");
+                push_signed_field(buffer, x as i64, original_text);
+            }
+        }
+        b"de" => {
+            if let Some(x) = line.gap_compressed_per_base_sequence_divergence {
+                buffer.push_str("\tde:f:");
+                push_float(buffer, x, original_text, options.float_precision);
+            }
+        }
+        b"rl" => {
+            if let Some(x) = line.length_of_query_regions_with_repetitive_seeds {
+                buffer.push_str("\trl:i:");
+                push_unsigned_field(buffer, x as u64, original_text);
+            }
+        }
+        b"MD" => {
+            if let Some(x) = &line.unknown_md {
+                buffer.push_str("\tMD:Z:");
+                buffer.push_str(x);
+            }
+        }
+        b"SA" => {
+            if let Some(x) = &line.supplementary_alignments {
+                buffer.push_str("\tSA:Z:");
+                buffer.push_str(x);
+            }
+        }
+        b"ts" => {
+            if let Some(x) = &line.transcript_strand {
+                buffer.push_str("\tts:A:");
+                buffer.push_str(x);
+            }
         }
-        if let Some(x) = &self.supplementary_alignments {
-            write!(f, "\tSA:Z:{x}")?;
+        b"cg" => {
+            if let Some(x) = &line.cigar_string {
+                buffer.push_str("\tcg:Z:");
+                format_cigar(buffer, x);
+            }
         }
-        if let Some(x) = &self.transcript_strand {
-            write!(f, "\tts:A:{x}")?;
+        b"cs" => {
+            if let Some(x) = &line.difference_string {
+                buffer.push_str("\tcs:Z:");
+                format_alignment_difference(buffer, x);
+            }
         }
-        if let Some(x) = &self.cigar_string {
-            write!(f, "\tcg:Z:{x}")?;
+        b"dv" => {
+            if let Some(x) = line.approximate_per_base_sequence_divergence {
+                buffer.push_str("\tdv:f:");
+                push_float(buffer, x, original_text, options.float_precision);
+            }
         }
-        if let Some(x) = &self.difference_string {
-            write!(f, "\tcs:Z:{x}")?;
+        _ => {}
+    }
+}
+
+/// Formats the raw field with the given name, if it is present in the given line.
+fn format_raw_field(buffer: &mut String, line: &PAFLine, name: &[u8; 2]) {
+    if let Some(raw_tag) = line.raw_tag(name) {
+        buffer.push('\t');
+        buffer.push(char::from(raw_tag.name[0]));
+        buffer.push(char::from(raw_tag.name[1]));
+        buffer.push(':');
+        buffer.push(char::from(raw_tag.value_type));
+        buffer.push(':');
+        buffer.push_str(&raw_tag.value);
+    }
+}
+
+/// Formats the given unknown field.
+/// If the original text of the value is given and still represents the value, it is written instead of the value.
+fn format_unknown_field(buffer: &mut String, unknown_field: &Tag, original_text: Option<&str>) {
+    if let Some(text) = original_text {
+        let header = format!(
+            "{}{}:{}:",
+            char::from(unknown_field.name[0]),
+            char::from(unknown_field.name[1]),
+            unknown_field.value.type_character()
+        );
+        if format!("{header}{text}").parse::<Tag>().ok().as_ref() == Some(unknown_field) {
+            buffer.push('\t');
+            buffer.push_str(&header);
+            buffer.push_str(text);
+            return;
         }
-        if let Some(x) = self.approximate_per_base_sequence_divergence {
-            write!(f, "\tdv:f:{x}")?;
+    }
+
+    write!(buffer, "\t{unknown_field}").unwrap();
+}
+
+fn push_float(
+    buffer: &mut String,
+    value: f64,
+    original_text: Option<&str>,
+    float_precision: Option<usize>,
+) {
+    match (float_precision, original_text) {
+        (Some(precision), _) => write!(buffer, "{value:.precision$}").unwrap(),
+        (None, Some(text)) if text.parse::<f64>().ok() == Some(value) => buffer.push_str(text),
+        (None, _) => write!(buffer, "{value}").unwrap(),
+    }
+}
+
+/// Formats an unsigned integer, or its original text if that still represents it, e.g. with leading zeros.
+fn push_unsigned_field(buffer: &mut String, value: u64, original_text: Option<&str>) {
+    match original_text {
+        Some(text) if text.parse::<u64>().ok() == Some(value) => buffer.push_str(text),
+        _ => push_unsigned(buffer, value),
+    }
+}
+
+/// Formats a signed integer, or its original text if that still represents it, e.g. with a plus sign.
+fn push_signed_field(buffer: &mut String, value: i64, original_text: Option<&str>) {
+    match original_text {
+        Some(text) if text.parse::<i64>().ok() == Some(value) => buffer.push_str(text),
+        _ => push_signed(buffer, value),
+    }
+}
+
+/// Formats an unsigned integer without going through [std::fmt].
+pub(crate) fn push_unsigned(buffer: &mut String, mut value: u64) {
+    let mut digits = [0; 20];
+    let mut start = digits.len();
+    loop {
+        start -= 1;
+        digits[start] = b'0' + (value % 10) as u8;
+        value /= 10;
+        if value == 0 {
+            break;
         }
+    }
+
+    buffer.push_str(std::str::from_utf8(&digits[start..]).unwrap());
+}
+
+/// Formats a signed integer without going through [std::fmt].
+pub(crate) fn push_signed(buffer: &mut String, value: i64) {
+    if value < 0 {
+        buffer.push('-');
+    }
+    push_unsigned(buffer, value.unsigned_abs());
+}
 
-        // unknown fields
-        for unknown_field in &self.unknown_fields {
-            write!(f, "\t{unknown_field}")?;
+pub(crate) fn format_cigar(buffer: &mut String, cigar: &Cigar) {
+    for cigar_column in &cigar.0 {
+        push_unsigned(buffer, cigar_column.length() as u64);
+        buffer.push(match cigar_column {
+            CigarColumn::Match(_) => 'M',
+            CigarColumn::Insertion(_) => 'I',
+            CigarColumn::Deletion(_) => 'D',
+            CigarColumn::Mismatch(_) => 'X',
+            CigarColumn::SequenceMatch(_) => '=',
+            CigarColumn::Skip(_) => 'N',
+            CigarColumn::SoftClip(_) => 'S',
+            CigarColumn::HardClip(_) => 'H',
+            CigarColumn::Padding(_) => 'P',
+        });
+    }
+}
+
+fn format_alignment_difference(buffer: &mut String, alignment_difference: &AlignmentDifference) {
+    for difference_column in &alignment_difference.0 {
+        match difference_column {
+            DifferenceColumn::Match { length } => {
+                buffer.push(':');
+                push_unsigned(buffer, *length as u64);
+            }
+            DifferenceColumn::IdenticalSequence { sequence } => {
+                buffer.push('=');
+                buffer.push_str(sequence);
+            }
+            DifferenceColumn::Insertion {
+                superfluous_query_characters,
+            } => {
+                buffer.push('+');
+                buffer.push_str(superfluous_query_characters);
+            }
+            DifferenceColumn::Deletion {
+                missing_query_characters,
+            } => {
+                buffer.push('-');
+                buffer.push_str(missing_query_characters);
+            }
+            DifferenceColumn::Mismatch { reference, query } => {
+                buffer.push('*');
+                buffer.push(*reference);
+                buffer.push(*query);
+            }
+            DifferenceColumn::Intron {
+                donor,
+                length,
+                acceptor,
+            } => {
+                buffer.push('~');
+                buffer.push_str(donor);
+                push_unsigned(buffer, *length as u64);
+                buffer.push_str(acceptor);
+            }
+        }
+    }
+}
+
+impl Display for Tag {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}{}:{}:{}",
+            char::from(self.name[0]),
+            char::from(self.name[1]),
+            self.value.type_character(),
+            self.value
+        )
+    }
+}
+
+/// Writes the value without the type.
+impl Display for TagValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TagValue::Character(value) => write!(f, "{value}"),
+            TagValue::Integer(value) => write!(f, "{value}"),
+            TagValue::Float(value) => write!(f, "{value}"),
+            TagValue::String(value) => write!(f, "{value}"),
+            TagValue::Hex(value) => {
+                for byte in value {
+                    write!(f, "{byte:02X}")?;
+                }
+                Ok(())
+            }
+            TagValue::Array(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+impl Display for TagArray {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        fn write_elements<Element: Display>(
+            f: &mut Formatter<'_>,
+            subtype: char,
+            elements: &[Element],
+        ) -> std::fmt::Result {
+            write!(f, "{subtype}")?;
+            for element in elements {
+                write!(f, ",{element}")?;
+            }
+            Ok(())
+        }
+
+        match self {
+            TagArray::Int8(elements) => write_elements(f, 'c', elements),
+            TagArray::UInt8(elements) => write_elements(f, 'C', elements),
+            TagArray::Int16(elements) => write_elements(f, 's', elements),
+            TagArray::UInt16(elements) => write_elements(f, 'S', elements),
+            TagArray::Int32(elements) => write_elements(f, 'i', elements),
+            TagArray::UInt32(elements) => write_elements(f, 'I', elements),
+            TagArray::Float(elements) => write_elements(f, 'f', elements),
         }
+    }
+}
+
+impl Display for Strand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_char(strand_character(*self))
+    }
+}
 
-        Ok(())
+fn strand_character(strand: Strand) -> char {
+    match strand {
+        Strand::Forward => '+',
+        Strand::Reverse => '-',
+        Strand::Unmapped => '*',
     }
 }
 
@@ -98,46 +562,16 @@ impl Display for AlignmentType {
 
 impl Display for Cigar {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        for cigar_column in &self.0 {
-            write!(
-                f,
-                "{}{}",
-                match cigar_column {
-                    CigarColumn::Match(length)
-                    | CigarColumn::Insertion(length)
-                    | CigarColumn::Deletion(length)
-                    | CigarColumn::Mismatch(length) => length,
-                },
-                match cigar_column {
-                    CigarColumn::Match(_) => "M",
-                    CigarColumn::Insertion(_) => "I",
-                    CigarColumn::Deletion(_) => "D",
-                    CigarColumn::Mismatch(_) => "X",
-                }
-            )?;
-        }
-
-        Ok(())
+        let mut buffer = String::new();
+        format_cigar(&mut buffer, self);
+        f.write_str(&buffer)
     }
 }
 
 impl Display for AlignmentDifference {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        for difference_column in &self.0 {
-            match difference_column {
-                DifferenceColumn::Match { length } => write!(f, ":{length}")?,
-                DifferenceColumn::Insertion {
-                    superfluous_query_characters,
-                } => write!(f, "+{superfluous_query_characters}")?,
-                DifferenceColumn::Deletion {
-                    missing_query_characters,
-                } => write!(f, "-{missing_query_characters}")?,
-                DifferenceColumn::Mismatch { reference, query } => {
-                    write!(f, "*{reference}{query}")?
-                }
-            }
-        }
-
-        Ok(())
+        let mut buffer = String::new();
+        format_alignment_difference(&mut buffer, self);
+        f.write_str(&buffer)
     }
 }
@@ -0,0 +1,213 @@
+use crate::data::PAFLine;
+use crate::error::Result;
+use crate::input::{PafReader, ParserOptions};
+use rayon::prelude::*;
+use std::collections::VecDeque;
+use std::io::BufRead;
+
+/// The default approximate size of the chunks that are parsed by a single thread, in bytes.
+pub const DEFAULT_CHUNK_SIZE: usize = 1 << 20;
+
+/// Parse all lines in the given buffer on multiple threads.
+///
+/// The buffer can for example be a memory-mapped file.
+/// The lines are returned in their original order.
+/// If parsing fails, the error of the first erroneous line is returned.
+pub fn parse_buffer_parallel(buffer: &[u8], options: &ParserOptions) -> Result<Vec<PAFLine>> {
+    map_parallel(buffer, options, |line| line)
+}
+
+/// Parse all lines in the given buffer on multiple threads and apply the given function to each line.
+///
+/// The results are returned in the original order of the lines.
+/// If parsing fails, the error of the first erroneous line is returned.
+pub fn map_parallel<Output: Send>(
+    buffer: &[u8],
+    options: &ParserOptions,
+    function: impl Fn(PAFLine) -> Output + Sync + Send,
+) -> Result<Vec<Output>> {
+    let chunks = split_into_chunks(buffer, DEFAULT_CHUNK_SIZE);
+    let results: Vec<_> = chunks
+        .par_iter()
+        .map(|chunk| {
+            PafReader::with_options(chunk.text, options.clone())
+                .starting_at(chunk.line_number, chunk.byte_offset)
+                .map(|line| line.map(&function))
+                .collect::<Result<Vec<_>>>()
+        })
+        .collect();
+
+    let mut output = Vec::new();
+    for result in results {
+        output.extend(result?);
+    }
+    Ok(output)
+}
+
+/// Parse all lines in the given buffer on multiple threads and call the given function on each line.
+///
+/// The function is called in no particular order.
+/// If parsing fails, the error of one of the erroneous lines is returned, and the function may not have been called on all correct lines.
+pub fn for_each_parallel(
+    buffer: &[u8],
+    options: &ParserOptions,
+    function: impl Fn(PAFLine) + Sync + Send,
+) -> Result<()> {
+    split_into_chunks(buffer, DEFAULT_CHUNK_SIZE)
+        .par_iter()
+        .try_for_each(|chunk| {
+            for line in PafReader::with_options(chunk.text, options.clone())
+                .starting_at(chunk.line_number, chunk.byte_offset)
+            {
+                function(line?);
+            }
+            Ok(())
+        })
+}
+
+/// A reader of PAF lines that parses batches of lines on multiple threads.
+///
+/// The lines are yielded in their original order, like from a [PafReader].
+#[derive(Debug)]
+pub struct ParallelPafReader<Input: BufRead> {
+    input: Input,
+    options: ParserOptions,
+    chunk_size: usize,
+    chunks_per_batch: usize,
+    batch: Vec<u8>,
+    parsed_lines: VecDeque<Result<PAFLine>>,
+    line_number: usize,
+    byte_offset: u64,
+}
+
+impl<Input: BufRead> ParallelPafReader<Input> {
+    /// Create a new reader that reads PAF lines from the given input.
+    pub fn new(input: Input) -> Self {
+        Self::with_options(input, ParserOptions::default())
+    }
+
+    /// Create a new reader that reads PAF lines from the given input using the given options.
+    pub fn with_options(input: Input, options: ParserOptions) -> Self {
+        Self {
+            input,
+            options,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            chunks_per_batch: rayon::current_num_threads() * 4,
+            batch: Vec::new(),
+            parsed_lines: VecDeque::new(),
+            line_number: 0,
+            byte_offset: 0,
+        }
+    }
+
+    /// Set the approximate size of the chunks that are parsed by a single thread, in bytes.
+    ///
+    /// The reader buffers four chunks per thread of the rayon thread pool at a time,
+    /// i.e. about `chunk_size * 4 * threads` bytes of input, plus the lines parsed from them. The default is [DEFAULT_CHUNK_SIZE].
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Read the next batch of lines and parse it.
+    /// Returns `false` if the input has no further lines.
+    fn parse_next_batch(&mut self) -> Result<bool> {
+        self.batch.clear();
+        let batch_size = self.chunk_size * self.chunks_per_batch;
+        while self.batch.len() < batch_size {
+            if self.input.read_until(b'\n', &mut self.batch)? == 0 {
+                break;
+            }
+        }
+        if self.batch.is_empty() {
+            return Ok(false);
+        }
+
+        let mut chunks = split_into_chunks(&self.batch, self.chunk_size);
+        for chunk in &mut chunks {
+            chunk.line_number += self.line_number;
+            chunk.byte_offset += self.byte_offset;
+        }
+        let options = &self.options;
+        let parsed_chunks: Vec<_> = chunks
+            .par_iter()
+            .map(|chunk| parse_chunk(chunk, options))
+            .collect();
+
+        if let Some(last_chunk) = chunks.last() {
+            self.line_number = last_chunk.line_number + count_lines(last_chunk.text);
+        }
+        self.byte_offset += self.batch.len() as u64;
+        self.parsed_lines
+            .extend(parsed_chunks.into_iter().flatten());
+        Ok(true)
+    }
+}
+
+impl<Input: BufRead> Iterator for ParallelPafReader<Input> {
+    type Item = Result<PAFLine>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.parsed_lines.is_empty() {
+            match self.parse_next_batch() {
+                Ok(true) => {}
+                Ok(false) => return None,
+                Err(error) => return Some(Err(error)),
+            }
+        }
+
+        self.parsed_lines.pop_front()
+    }
+}
+
+/// A chunk of complete lines of the input.
+#[derive(Debug)]
+struct Chunk<'input> {
+    text: &'input [u8],
+    /// The number of lines before this chunk.
+    line_number: usize,
+    /// The offset of this chunk in bytes from the start of the input.
+    byte_offset: u64,
+}
+
+/// Split the given buffer into chunks of approximately the given size that end at line boundaries.
+fn split_into_chunks(buffer: &[u8], chunk_size: usize) -> Vec<Chunk<'_>> {
+    let mut chunks = Vec::new();
+    let mut line_number = 0;
+    let mut offset = 0;
+
+    while offset < buffer.len() {
+        let limit = (offset + chunk_size).min(buffer.len());
+        let end = buffer[limit..]
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .map(|position| limit + position + 1)
+            .unwrap_or(buffer.len());
+        let text = &buffer[offset..end];
+
+        chunks.push(Chunk {
+            text,
+            line_number,
+            byte_offset: offset as u64,
+        });
+        line_number += count_lines(text);
+        offset = end;
+    }
+
+    chunks
+}
+
+fn count_lines(text: &[u8]) -> usize {
+    let newlines = text.iter().filter(|&&byte| byte == b'\n').count();
+    if text.last().map(|&byte| byte != b'\n').unwrap_or(false) {
+        newlines + 1
+    } else {
+        newlines
+    }
+}
+
+fn parse_chunk(chunk: &Chunk, options: &ParserOptions) -> Vec<Result<PAFLine>> {
+    PafReader::with_options(chunk.text, options.clone())
+        .starting_at(chunk.line_number, chunk.byte_offset)
+        .collect()
+}
@@ -0,0 +1,186 @@
+use crate::data::{DifferenceColumn, PAFLine, Strand};
+
+/// The kind of alignment column a projected position falls into.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProjectionKind {
+    /// The position is aligned to a base of the other sequence, either as match or as mismatch.
+    Match,
+    /// The position is in a part of the query that is missing from the target.
+    Insertion,
+    /// The position is in a part of the target that is missing from the query, e.g. a deletion or an intron.
+    Deletion,
+}
+
+/// The result of projecting a position from one sequence of an alignment to the other.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ProjectedPosition {
+    /// The projected position on the original strand of the other sequence.
+    ///
+    /// If the kind is [ProjectionKind::Match], this is the zero-based position of the aligned base.
+    /// Otherwise, the position falls into a gap of the other sequence,
+    /// and this is the zero-based boundary between the two bases flanking the gap,
+    /// i.e. the number of bases before the gap on the original strand.
+    pub position: usize,
+    /// The kind of alignment column the position falls into.
+    pub kind: ProjectionKind,
+}
+
+/// An operation of an alignment, consuming the given number of query and target characters.
+/// Matches consume the same number of characters from both sequences.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct AlignmentOperation {
+    pub(crate) query_length: usize,
+    pub(crate) target_length: usize,
+}
+
+impl AlignmentOperation {
+    fn new(query_length: usize, target_length: usize) -> Self {
+        Self {
+            query_length,
+            target_length,
+        }
+    }
+}
+
+impl PAFLine {
+    /// Projects the given position on the original strand of the query to the target.
+    ///
+    /// The alignment is walked through the cigar string or, if it is missing, through the alignment difference string.
+    /// Returns `None` if the line is unmapped, has neither string, or if the position is outside of the aligned part of the query.
+    pub fn project_query_to_target(&self, position: usize) -> Option<ProjectedPosition> {
+        if position < self.query_start_coordinate || position >= self.query_end_coordinate {
+            return None;
+        }
+        let aligned_position = self.query_to_aligned(position)?;
+
+        let mut query_offset = 0;
+        let mut target_offset = 0;
+        for operation in self.alignment_operations()? {
+            if aligned_position < query_offset + operation.query_length {
+                let (target_offset, kind) = if operation.target_length == 0 {
+                    (target_offset, ProjectionKind::Insertion)
+                } else {
+                    (
+                        target_offset + aligned_position - query_offset,
+                        ProjectionKind::Match,
+                    )
+                };
+                return Some(ProjectedPosition {
+                    position: self.target_start_coordinate_on_original_strand + target_offset,
+                    kind,
+                });
+            }
+            query_offset += operation.query_length;
+            target_offset += operation.target_length;
+        }
+
+        None
+    }
+
+    /// Projects the given position on the target to the original strand of the query.
+    ///
+    /// On the reverse strand, query positions decrease while target positions increase.
+    /// Returns `None` if the line is unmapped, has neither a cigar nor an alignment difference string,
+    /// or if the position is outside of the aligned part of the target.
+    pub fn project_target_to_query(&self, position: usize) -> Option<ProjectedPosition> {
+        if position < self.target_start_coordinate_on_original_strand
+            || position >= self.target_end_coordinate_on_original_strand
+        {
+            return None;
+        }
+        let aligned_position = position - self.target_start_coordinate_on_original_strand;
+
+        let mut query_offset = 0;
+        let mut target_offset = 0;
+        for operation in self.alignment_operations()? {
+            if aligned_position < target_offset + operation.target_length {
+                return Some(if operation.query_length == 0 {
+                    ProjectedPosition {
+                        position: self.aligned_boundary_to_query(query_offset)?,
+                        kind: ProjectionKind::Deletion,
+                    }
+                } else {
+                    ProjectedPosition {
+                        position: self
+                            .aligned_to_query(query_offset + aligned_position - target_offset)?,
+                        kind: ProjectionKind::Match,
+                    }
+                });
+            }
+            query_offset += operation.query_length;
+            target_offset += operation.target_length;
+        }
+
+        None
+    }
+
+    /// Returns the operations of the alignment, taken from the cigar string or, if it is missing, from the alignment difference string.
+    ///
+    /// Operations that consume neither sequence are skipped.
+    pub(crate) fn alignment_operations(
+        &self,
+    ) -> Option<Box<dyn Iterator<Item = AlignmentOperation> + '_>> {
+        if self.is_unmapped() {
+            return None;
+        }
+
+        if let Some(cigar) = &self.cigar_string {
+            Some(Box::new(cigar.0.iter().filter_map(|column| {
+                let length = column.length();
+                let operation = AlignmentOperation::new(
+                    if column.consumes_query() { length } else { 0 },
+                    if column.consumes_target() { length } else { 0 },
+                );
+                (operation != AlignmentOperation::new(0, 0)).then(|| operation)
+            })))
+        } else if let Some(difference) = &self.difference_string {
+            Some(Box::new(difference.0.iter().map(|column| match column {
+                DifferenceColumn::Match { length } => AlignmentOperation::new(*length, *length),
+                DifferenceColumn::IdenticalSequence { sequence } => {
+                    AlignmentOperation::new(sequence.len(), sequence.len())
+                }
+                DifferenceColumn::Insertion {
+                    superfluous_query_characters,
+                } => AlignmentOperation::new(superfluous_query_characters.len(), 0),
+                DifferenceColumn::Deletion {
+                    missing_query_characters,
+                } => AlignmentOperation::new(0, missing_query_characters.len()),
+                DifferenceColumn::Mismatch { .. } => AlignmentOperation::new(1, 1),
+                DifferenceColumn::Intron { length, .. } => AlignmentOperation::new(0, *length),
+            })))
+        } else {
+            None
+        }
+    }
+
+    /// Converts a position on the original strand of the query into an offset from the start of the alignment.
+    /// Returns `None` if the position is before the start of the alignment.
+    fn query_to_aligned(&self, position: usize) -> Option<usize> {
+        match self.strand {
+            Strand::Forward => position.checked_sub(self.query_start_coordinate),
+            Strand::Reverse => self.query_end_coordinate.checked_sub(position + 1),
+            Strand::Unmapped => None,
+        }
+    }
+
+    /// Converts an offset from the start of the alignment into a position on the original strand of the query.
+    /// Returns `None` if a reverse strand offset points before the start of the query.
+    pub(crate) fn aligned_to_query(&self, offset: usize) -> Option<usize> {
+        match self.strand {
+            Strand::Forward => Some(self.query_start_coordinate + offset),
+            Strand::Reverse => self.query_end_coordinate.checked_sub(offset + 1),
+            Strand::Unmapped => None,
+        }
+    }
+
+    /// Converts the boundary before the given offset from the start of the alignment
+    /// into a boundary between two bases on the original strand of the query.
+    /// Returns `None` if a reverse strand offset points before the start of the query.
+    pub(crate) fn aligned_boundary_to_query(&self, offset: usize) -> Option<usize> {
+        match self.strand {
+            Strand::Forward => Some(self.query_start_coordinate + offset),
+            Strand::Reverse => self.query_end_coordinate.checked_sub(offset),
+            Strand::Unmapped => None,
+        }
+    }
+}
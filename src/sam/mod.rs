@@ -0,0 +1,702 @@
+use crate::data::{AlignmentType, Cigar, CigarColumn, CigarRef, PAFLine, Strand};
+use crate::error::{Error, Result};
+use crate::input::{parse_optional_fields_into, strip_line_terminator, ParserOptions};
+use crate::output::{format_cigar, format_optional_fields, push_unsigned, WriterOptions};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, Write};
+
+/// The FLAG bit of reads mapped to the reverse strand.
+pub const FLAG_REVERSE: u16 = 0x10;
+/// The FLAG bit of unmapped reads.
+pub const FLAG_UNMAPPED: u16 = 0x4;
+/// The FLAG bit of secondary alignments.
+pub const FLAG_SECONDARY: u16 = 0x100;
+/// The FLAG bit of supplementary alignments.
+pub const FLAG_SUPPLEMENTARY: u16 = 0x800;
+
+/// The header of a SAM file, listing the reference sequences.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SamHeader {
+    /// The names and lengths of the reference sequences, written as `@SQ` lines.
+    pub reference_sequences: Vec<(String, usize)>,
+}
+
+impl SamHeader {
+    /// Create a header with the target sequences of the given lines, in the order of their first occurrence.
+    pub fn from_lines<'line>(lines: impl IntoIterator<Item = &'line PAFLine>) -> Self {
+        let mut reference_sequences = Vec::new();
+        let mut known_names = HashSet::new();
+        for line in lines {
+            if !line.is_unmapped() && known_names.insert(line.target_sequence_name.as_str()) {
+                reference_sequences.push((
+                    line.target_sequence_name.clone(),
+                    line.target_sequence_length,
+                ));
+            }
+        }
+
+        Self {
+            reference_sequences,
+        }
+    }
+}
+
+impl Display for SamHeader {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "@HD\tVN:1.6\tSO:unsorted")?;
+        for (name, length) in &self.reference_sequences {
+            writeln!(f, "@SQ\tSN:{name}\tLN:{length}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The sequence and base qualities of a query, as read from a FASTA or FASTQ file.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct QuerySequence {
+    /// The bases of the query on its original strand.
+    pub sequence: String,
+    /// The base qualities of the query on its original strand, if read from a FASTQ file.
+    pub quality: Option<String>,
+}
+
+/// A set of query sequences, used to fill the SEQ and QUAL columns of SAM records.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct QuerySequences {
+    sequences: HashMap<String, QuerySequence>,
+}
+
+impl QuerySequences {
+    /// Read all sequences from the given FASTA or FASTQ input.
+    ///
+    /// The format is detected from the first character of the input.
+    /// The name of a sequence is the first word of its header.
+    /// FASTA sequences may span multiple lines, while FASTQ records must consist of exactly four lines,
+    /// with as many base qualities as bases.
+    pub fn from_reader(mut input: impl BufRead) -> Result<Self> {
+        let mut sequences = HashMap::new();
+        let mut line = String::new();
+        let mut byte_offset = 0;
+        // returns the byte offset of the start of the line, or None at the end of the input
+        let mut read_line = |line: &mut String| -> Result<Option<u64>> {
+            line.clear();
+            let length = input.read_line(line)?;
+            line.truncate(line.trim_end_matches(&['\n', '\r'][..]).len());
+            let line_byte_offset = byte_offset;
+            byte_offset += length as u64;
+            Ok(if length > 0 {
+                Some(line_byte_offset)
+            } else {
+                None
+            })
+        };
+
+        let mut line_byte_offset = read_line(&mut line)?;
+        let mut current_line = 1;
+        while let Some(header_byte_offset) = line_byte_offset {
+            if line.is_empty() {
+                line_byte_offset = read_line(&mut line)?;
+                current_line += 1;
+                continue;
+            }
+            let name_of = |header: &str| header.split_whitespace().next().unwrap_or("").to_string();
+
+            if let Some(header) = line.strip_prefix('>') {
+                let name = name_of(header);
+                let mut sequence = String::new();
+                loop {
+                    line_byte_offset = read_line(&mut line)?;
+                    current_line += 1;
+                    if line_byte_offset.is_none() || line.starts_with('>') {
+                        break;
+                    }
+                    sequence.push_str(line.trim());
+                }
+                sequences.insert(
+                    name,
+                    QuerySequence {
+                        sequence,
+                        quality: None,
+                    },
+                );
+            } else if let Some(header) = line.strip_prefix('@') {
+                let name = name_of(header);
+                let header_line = current_line;
+                let mut record = [String::new(), String::new(), String::new()];
+                let mut record_byte_offsets = [0; 3];
+                for (field, field_byte_offset) in record.iter_mut().zip(&mut record_byte_offsets) {
+                    match read_line(field)? {
+                        Some(offset) => *field_byte_offset = offset,
+                        None => {
+                            return Err(Error::UnexpectedEndOfFile.locate(
+                                header_line,
+                                1,
+                                header_byte_offset,
+                            ))
+                        }
+                    }
+                    current_line += 1;
+                }
+                let [sequence, separator, quality] = record;
+                let [_, separator_byte_offset, quality_byte_offset] = record_byte_offsets;
+                if !separator.starts_with('+') {
+                    return Err(Error::UnexpectedCharacter { text: separator }.locate(
+                        header_line + 2,
+                        1,
+                        separator_byte_offset,
+                    ));
+                }
+                if quality.len() < sequence.len() {
+                    return Err(Error::UnexpectedEndOfLine.locate(
+                        header_line + 3,
+                        quality.len() + 1,
+                        quality_byte_offset + quality.len() as u64,
+                    ));
+                }
+                if quality.len() > sequence.len() {
+                    let excess = quality.get(sequence.len()..).unwrap_or(&quality);
+                    return Err(Error::UnexpectedCharacter {
+                        text: excess.to_string(),
+                    }
+                    .locate(
+                        header_line + 3,
+                        sequence.len() + 1,
+                        quality_byte_offset + sequence.len() as u64,
+                    ));
+                }
+                sequences.insert(
+                    name,
+                    QuerySequence {
+                        sequence,
+                        quality: Some(quality),
+                    },
+                );
+                line_byte_offset = read_line(&mut line)?;
+                current_line += 1;
+            } else {
+                return Err(Error::UnexpectedCharacter { text: line.clone() }.locate(
+                    current_line,
+                    1,
+                    header_byte_offset,
+                ));
+            }
+        }
+
+        Ok(Self { sequences })
+    }
+
+    /// Returns the sequence with the given name.
+    pub fn get(&self, name: &str) -> Option<&QuerySequence> {
+        self.sequences.get(name)
+    }
+
+    /// Adds a sequence with the given name, replacing any previous sequence with that name.
+    pub fn insert(&mut self, name: impl Into<String>, sequence: QuerySequence) {
+        self.sequences.insert(name.into(), sequence);
+    }
+}
+
+/// A writer of SAM records converted from PAF lines.
+///
+/// The query ends that are not part of the alignment become soft clips.
+/// All optional fields except for `cg` are written as SAM tags.
+#[derive(Debug)]
+pub struct SamWriter<Output: Write> {
+    output: Output,
+    sequences: Option<QuerySequences>,
+    buffer: String,
+}
+
+impl<Output: Write> SamWriter<Output> {
+    /// Create a new writer that writes SAM records to the given output, with SEQ and QUAL set to `*`.
+    pub fn new(output: Output) -> Self {
+        Self {
+            output,
+            sequences: None,
+            buffer: String::new(),
+        }
+    }
+
+    /// Create a new writer that writes SAM records to the given output,
+    /// taking SEQ and QUAL from the given query sequences.
+    pub fn with_sequences(output: Output, sequences: QuerySequences) -> Self {
+        Self {
+            output,
+            sequences: Some(sequences),
+            buffer: String::new(),
+        }
+    }
+
+    /// Write the given header.
+    pub fn write_header(&mut self, header: &SamHeader) -> std::io::Result<()> {
+        write!(self.output, "{header}")
+    }
+
+    /// Write the given line as SAM record, terminated by a newline.
+    pub fn write_line(&mut self, line: &PAFLine) -> std::io::Result<()> {
+        self.buffer.clear();
+        let sequence = self
+            .sequences
+            .as_ref()
+            .and_then(|sequences| sequences.get(&line.query_sequence_name));
+        format_sam_record(&mut self.buffer, line, sequence);
+        self.buffer.push('\n');
+        self.output.write_all(self.buffer.as_bytes())
+    }
+
+    /// Flush the underlying output.
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.output.flush()
+    }
+
+    /// Returns the underlying output.
+    pub fn into_inner(self) -> Output {
+        self.output
+    }
+}
+
+/// A streaming reader that converts SAM records into PAF lines, like `paftools.js sam2paf`.
+///
+/// The query coordinates are computed from the soft and hard clips of the CIGAR, which are stripped from the cigar string of the PAF line.
+/// The target length is taken from the `@SQ` header line of the target, and the number of matching bases
+/// from the `=` CIGAR operators, the `MD` tag or the `NM` tag, in this order of preference.
+/// Secondary alignments (FLAG 0x100) become [AlignmentType::Secondary] and all other alignments [AlignmentType::Primary],
+/// unless the record has a `tp` tag. Supplementary alignments (FLAG 0x800) keep their `SA` tag.
+/// All tags are parsed like the optional fields of a PAF line.
+///
+/// Unmapped records (FLAG 0x4) and records without CIGAR become unmapped PAF lines, see [PAFLine::is_unmapped].
+#[derive(Debug)]
+pub struct SamReader<Input: BufRead> {
+    input: Input,
+    options: ParserOptions,
+    header: SamHeader,
+    reference_lengths: HashMap<String, usize>,
+    buffer: String,
+    line_number: usize,
+    byte_offset: u64,
+}
+
+impl<Input: BufRead> SamReader<Input> {
+    /// Create a new reader that reads SAM records from the given input.
+    pub fn new(input: Input) -> Self {
+        Self::with_options(input, ParserOptions::default())
+    }
+
+    /// Create a new reader that reads SAM records from the given input, parsing their tags using the given options.
+    pub fn with_options(input: Input, options: ParserOptions) -> Self {
+        Self {
+            input,
+            options,
+            header: SamHeader::default(),
+            reference_lengths: HashMap::new(),
+            buffer: String::new(),
+            line_number: 0,
+            byte_offset: 0,
+        }
+    }
+
+    /// Returns the reference sequences of the header lines read so far.
+    ///
+    /// The header is complete once the first record has been read.
+    pub fn header(&self) -> &SamHeader {
+        &self.header
+    }
+
+    /// Read the next record and convert it into a PAF line.
+    /// Returns `None` if the input has no further records.
+    pub fn read_line(&mut self) -> Result<Option<PAFLine>> {
+        loop {
+            self.buffer.clear();
+            let line_byte_offset = self.byte_offset;
+            let length = self.input.read_line(&mut self.buffer)?;
+            if length == 0 {
+                return Ok(None);
+            }
+            self.line_number += 1;
+            self.byte_offset += length as u64;
+
+            let line = strip_line_terminator(&self.buffer);
+            if line.is_empty() {
+                continue;
+            } else if line.starts_with('@') {
+                if let Some(reference) = parse_reference_sequence(line) {
+                    let (name, length) = reference
+                        .map_err(|error| error.relocate(self.line_number, line_byte_offset))?;
+                    self.reference_lengths.insert(name.clone(), length);
+                    self.header.reference_sequences.push((name, length));
+                }
+                continue;
+            }
+
+            return parse_sam_record(line, &self.reference_lengths, &self.options)
+                .map(Some)
+                .map_err(|error| error.relocate(self.line_number, line_byte_offset));
+        }
+    }
+}
+
+impl<Input: BufRead> Iterator for SamReader<Input> {
+    type Item = Result<PAFLine>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_line().transpose()
+    }
+}
+
+/// Parses the name and length of an `@SQ` header line.
+/// Returns `None` for other header lines.
+fn parse_reference_sequence(line: &str) -> Option<Result<(String, usize)>> {
+    if !line.starts_with("@SQ\t") {
+        return None;
+    }
+
+    let mut name = None;
+    let mut length = None;
+    let mut byte_offset = 0;
+    for (index, field) in line.split('\t').enumerate() {
+        if let Some(value) = field.strip_prefix("SN:") {
+            name = Some(value.to_string());
+        } else if let Some(value) = field.strip_prefix("LN:") {
+            match value.parse() {
+                Ok(value) => length = Some(value),
+                Err(_) => {
+                    return Some(Err(Error::ColumnParseError {
+                        text: field.to_string(),
+                    }
+                    .locate(1, index + 1, byte_offset as u64)))
+                }
+            }
+        }
+        byte_offset += field.len() + 1;
+    }
+
+    Some(match (name, length) {
+        (Some(name), Some(length)) => Ok((name, length)),
+        _ => Err(Error::UnexpectedEndOfLine.locate(1, 1, 0)),
+    })
+}
+
+/// Parses a SAM record, without line terminator, into a PAF line.
+fn parse_sam_record(
+    text: &str,
+    reference_lengths: &HashMap<String, usize>,
+    options: &ParserOptions,
+) -> Result<PAFLine> {
+    let mut remaining = text;
+    let mut index = 0;
+    let mut next_column = || {
+        index += 1;
+        let byte_offset = text.len() - remaining.len();
+        if index > 1 && byte_offset == text.len() {
+            return Err(Error::UnexpectedEndOfLine.locate(1, index, byte_offset as u64));
+        }
+        let (column, rest) = match remaining.find('\t') {
+            Some(limit) => (&remaining[..limit], &remaining[limit + 1..]),
+            None => (remaining, &remaining[remaining.len()..]),
+        };
+        remaining = rest;
+        Ok((column, index, byte_offset))
+    };
+    let parse_error = |column: &str, index: usize, byte_offset: usize| {
+        Error::ColumnParseError {
+            text: column.to_string(),
+        }
+        .locate(1, index, byte_offset as u64)
+    };
+
+    let (query_name, _, _) = next_column()?;
+    let (flag, flag_index, flag_offset) = next_column()?;
+    let flag: u16 = flag
+        .parse()
+        .map_err(|_| parse_error(flag, flag_index, flag_offset))?;
+    let (target_name, _, _) = next_column()?;
+    let (position, position_index, position_offset) = next_column()?;
+    let position: usize = position
+        .parse()
+        .map_err(|_| parse_error(position, position_index, position_offset))?;
+    let (mapping_quality, mapping_quality_index, mapping_quality_offset) = next_column()?;
+    let mapping_quality: u8 = mapping_quality.parse().map_err(|_| {
+        parse_error(
+            mapping_quality,
+            mapping_quality_index,
+            mapping_quality_offset,
+        )
+    })?;
+    let (cigar, cigar_index, cigar_offset) = next_column()?;
+    let cigar_columns = if cigar == "*" {
+        Vec::new()
+    } else {
+        CigarRef(cigar)
+            .iter()
+            .collect::<Result<_>>()
+            .map_err(|error| error.locate(1, cigar_index, cigar_offset as u64))?
+    };
+    // RNEXT, PNEXT and TLEN
+    for _ in 0..3 {
+        next_column()?;
+    }
+    let (sequence, _, _) = next_column()?;
+    next_column()?;
+
+    let mut line = PAFLine {
+        query_sequence_name: query_name.to_string(),
+        ..PAFLine::default()
+    };
+    let first_optional_column = index + 1;
+    parse_optional_fields_into(
+        text,
+        &mut remaining,
+        first_optional_column,
+        &mut line,
+        options,
+    )?;
+
+    if flag & FLAG_UNMAPPED != 0 || cigar_columns.is_empty() {
+        line.query_sequence_length = if sequence == "*" { 0 } else { sequence.len() };
+        line.strand = Strand::Unmapped;
+        line.target_sequence_name = "*".to_string();
+        line.mapping_quality = 0;
+        return Ok(line);
+    }
+
+    let is_clip = |column: &CigarColumn| {
+        matches!(column, CigarColumn::SoftClip(_) | CigarColumn::HardClip(_))
+    };
+    let leading_clip: usize = cigar_columns
+        .iter()
+        .take_while(|column| is_clip(column))
+        .map(CigarColumn::length)
+        .sum();
+    let trailing_clip: usize = cigar_columns
+        .iter()
+        .rev()
+        .take_while(|column| is_clip(column))
+        .map(CigarColumn::length)
+        .sum();
+    let cigar = Cigar(
+        cigar_columns
+            .into_iter()
+            .filter(|column| !is_clip(column))
+            .collect(),
+    );
+    let aligned_query_length = cigar.query_length();
+    let aligned_target_length = cigar.target_length();
+    let query_length = leading_clip + aligned_query_length + trailing_clip;
+
+    line.strand = if flag & FLAG_REVERSE != 0 {
+        Strand::Reverse
+    } else {
+        Strand::Forward
+    };
+    let (start_clip, end_clip) = if line.strand == Strand::Reverse {
+        (trailing_clip, leading_clip)
+    } else {
+        (leading_clip, trailing_clip)
+    };
+    line.query_sequence_length = query_length;
+    line.query_start_coordinate = start_clip;
+    line.query_end_coordinate = query_length - end_clip;
+
+    line.target_sequence_name = target_name.to_string();
+    line.target_start_coordinate_on_original_strand = position.saturating_sub(1);
+    line.target_end_coordinate_on_original_strand =
+        line.target_start_coordinate_on_original_strand + aligned_target_length;
+    line.target_sequence_length = match reference_lengths.get(target_name) {
+        Some(length) => *length,
+        None => {
+            log::warn!("Found no @SQ header line for reference sequence {target_name}");
+            line.target_end_coordinate_on_original_strand
+        }
+    };
+
+    // like paftools.js sam2paf, the block length excludes introns
+    let mut alignment_matches = 0;
+    let mut sequence_matches = None;
+    let mut gaps = 0;
+    for column in &cigar.0 {
+        match column {
+            CigarColumn::Match(length) | CigarColumn::Mismatch(length) => {
+                alignment_matches += length
+            }
+            CigarColumn::SequenceMatch(length) => {
+                alignment_matches += length;
+                *sequence_matches.get_or_insert(0) += length;
+            }
+            CigarColumn::Insertion(length) | CigarColumn::Deletion(length) => gaps += length,
+            _ => {}
+        }
+    }
+    line.number_of_bases_and_gaps = alignment_matches + gaps;
+    line.number_of_matching_bases = if let Some(sequence_matches) = sequence_matches {
+        sequence_matches
+    } else if let Some(md) = &line.unknown_md {
+        md.split(|character: char| !character.is_ascii_digit())
+            .filter_map(|number| number.parse::<usize>().ok())
+            .sum()
+    } else if let Some(nm) = line.total_number_of_mismatches_and_gaps {
+        line.number_of_bases_and_gaps.saturating_sub(nm)
+    } else {
+        log::warn!(
+            "Found neither MD nor NM tag for {query_name}, counting all aligned bases as matches"
+        );
+        alignment_matches
+    };
+    line.mapping_quality = mapping_quality;
+    line.cigar_string = Some(cigar);
+
+    if line.alignment_type.is_none() {
+        line.alignment_type = Some(if flag & FLAG_SECONDARY != 0 {
+            AlignmentType::Secondary
+        } else {
+            AlignmentType::Primary
+        });
+    }
+
+    Ok(line)
+}
+
+/// Returns the FLAG of the SAM record of the given line.
+pub fn sam_flag(line: &PAFLine) -> u16 {
+    let mut flag = 0;
+    match line.strand {
+        Strand::Forward => {}
+        Strand::Reverse => flag |= FLAG_REVERSE,
+        Strand::Unmapped => flag |= FLAG_UNMAPPED,
+    }
+    if matches!(
+        line.alignment_type,
+        Some(AlignmentType::Secondary | AlignmentType::SecondaryInversion)
+    ) {
+        flag |= FLAG_SECONDARY;
+    }
+    flag
+}
+
+/// Returns the SAM cigar of the given line, i.e. its cigar string with soft clips for the unaligned query ends.
+///
+/// If the line has no cigar string, it is computed from the alignment difference string.
+/// Returns `None` if the line is unmapped or has neither string.
+pub fn sam_cigar(line: &PAFLine) -> Option<Cigar> {
+    if line.is_unmapped() {
+        return None;
+    }
+    let cigar = if let Some(cigar) = &line.cigar_string {
+        cigar.clone()
+    } else {
+        line.difference_string.as_ref()?.to_cigar()
+    };
+
+    let mut clips = (
+        line.query_start_coordinate,
+        line.query_sequence_length
+            .saturating_sub(line.query_end_coordinate),
+    );
+    if line.strand == Strand::Reverse {
+        clips = (clips.1, clips.0);
+    }
+
+    let mut result = Vec::with_capacity(cigar.0.len() + 2);
+    if clips.0 > 0 {
+        result.push(CigarColumn::SoftClip(clips.0));
+    }
+    result.extend(cigar.0);
+    if clips.1 > 0 {
+        result.push(CigarColumn::SoftClip(clips.1));
+    }
+    Some(Cigar(result))
+}
+
+fn format_sam_record(buffer: &mut String, line: &PAFLine, sequence: Option<&QuerySequence>) {
+    let unmapped = line.is_unmapped();
+    buffer.push_str(&line.query_sequence_name);
+    buffer.push('\t');
+    push_unsigned(buffer, sam_flag(line).into());
+    buffer.push('\t');
+    if unmapped {
+        buffer.push_str("*\t0\t0\t");
+    } else {
+        buffer.push_str(&line.target_sequence_name);
+        buffer.push('\t');
+        push_unsigned(
+            buffer,
+            line.target_start_coordinate_on_original_strand as u64 + 1,
+        );
+        buffer.push('\t');
+        push_unsigned(buffer, line.mapping_quality.into());
+        buffer.push('\t');
+    }
+    if let Some(cigar) = sam_cigar(line) {
+        format_cigar(buffer, &cigar);
+    } else {
+        buffer.push('*');
+    }
+    buffer.push_str("\t*\t0\t0\t");
+
+    // secondary alignments omit the sequence, like in the SAM output of minimap2
+    let sequence = sequence.filter(|sequence| {
+        if sequence.sequence.len() != line.query_sequence_length {
+            log::warn!(
+                "Length of sequence {} does not match the query length of its PAF line",
+                line.query_sequence_name
+            );
+            false
+        } else {
+            sam_flag(line) & FLAG_SECONDARY == 0
+        }
+    });
+    match sequence {
+        Some(sequence) if line.strand == Strand::Reverse => {
+            buffer.extend(sequence.sequence.chars().rev().map(complement));
+            buffer.push('\t');
+            match &sequence.quality {
+                Some(quality) => buffer.extend(quality.chars().rev()),
+                None => buffer.push('*'),
+            }
+        }
+        Some(sequence) => {
+            buffer.push_str(&sequence.sequence);
+            buffer.push('\t');
+            buffer.push_str(sequence.quality.as_deref().unwrap_or("*"));
+        }
+        None => buffer.push_str("*\t*"),
+    }
+
+    format_optional_fields(
+        buffer,
+        line,
+        &WriterOptions::default().with_excluded_tags([*b"cg"]),
+    );
+}
+
+/// Returns the complement of the given nucleotide, keeping its case.
+/// Characters other than IUPAC nucleotide codes are returned unchanged.
+pub(crate) fn complement(base: char) -> char {
+    match base {
+        'A' => 'T',
+        'C' => 'G',
+        'G' => 'C',
+        'T' | 'U' => 'A',
+        'R' => 'Y',
+        'Y' => 'R',
+        'K' => 'M',
+        'M' => 'K',
+        'B' => 'V',
+        'V' => 'B',
+        'D' => 'H',
+        'H' => 'D',
+        'a' => 't',
+        'c' => 'g',
+        'g' => 'c',
+        't' | 'u' => 'a',
+        'r' => 'y',
+        'y' => 'r',
+        'k' => 'm',
+        'm' => 'k',
+        'b' => 'v',
+        'v' => 'b',
+        'd' => 'h',
+        'h' => 'd',
+        other => other,
+    }
+}
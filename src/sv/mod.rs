@@ -0,0 +1,416 @@
+use crate::data::{AlignmentType, CigarColumn, PAFLine, Strand};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+
+/// Options for detecting structural variants.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SvOptions {
+    /// Deletions, insertions and duplications shorter than this are not reported.
+    pub min_size: usize,
+    /// Alignments with a lower mapping quality are ignored.
+    pub min_mapping_quality: u8,
+    /// Alignments with fewer bases and gaps (column 11) are ignored.
+    pub min_alignment_length: usize,
+}
+
+impl Default for SvOptions {
+    fn default() -> Self {
+        Self {
+            min_size: 50,
+            min_mapping_quality: 0,
+            min_alignment_length: 0,
+        }
+    }
+}
+
+impl SvOptions {
+    /// Report only deletions, insertions and duplications of at least the given size.
+    pub fn with_min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// Ignore alignments with a mapping quality lower than the given one.
+    pub fn with_min_mapping_quality(mut self, min_mapping_quality: u8) -> Self {
+        self.min_mapping_quality = min_mapping_quality;
+        self
+    }
+
+    /// Ignore alignments with fewer than the given number of bases and gaps.
+    pub fn with_min_alignment_length(mut self, min_alignment_length: usize) -> Self {
+        self.min_alignment_length = min_alignment_length;
+        self
+    }
+
+    fn accepts(&self, line: &PAFLine) -> bool {
+        !line.is_unmapped()
+            && !matches!(
+                line.alignment_type,
+                Some(AlignmentType::Secondary | AlignmentType::SecondaryInversion)
+            )
+            && line.mapping_quality >= self.min_mapping_quality
+            && line.number_of_bases_and_gaps >= self.min_alignment_length
+    }
+}
+
+/// The type of a structural variant.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SvType {
+    /// Target bases that are missing from the query.
+    Deletion,
+    /// Query bases that are missing from the target.
+    Insertion,
+    /// A change of strand between two alignments to the same target.
+    Inversion,
+    /// A change of target between two alignments.
+    Translocation,
+    /// Target bases that are aligned twice in a row by the query.
+    Duplication,
+}
+
+impl SvType {
+    /// Returns the abbreviation of this type as used in `SVTYPE` of VCF, e.g. `DEL`.
+    pub fn abbreviation(&self) -> &'static str {
+        match self {
+            SvType::Deletion => "DEL",
+            SvType::Insertion => "INS",
+            SvType::Inversion => "INV",
+            SvType::Translocation => "TRA",
+            SvType::Duplication => "DUP",
+        }
+    }
+}
+
+impl Display for SvType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.abbreviation())
+    }
+}
+
+/// One side of a structural variant on the target.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Breakend {
+    /// The name of the target sequence.
+    pub sequence_name: String,
+    /// The zero-based boundary between two bases of the target at which the variant starts or ends.
+    pub position: usize,
+    /// The strand of the alignment on this side of the variant.
+    pub strand: Strand,
+}
+
+/// A structural variant of a query against the target.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StructuralVariant {
+    /// The type of the variant.
+    pub sv_type: SvType,
+    /// The number of deleted, inserted or duplicated bases, and `None` for inversions and translocations.
+    pub length: Option<usize>,
+    /// The first breakend. For variants within a single target, this is the one with the lower position.
+    pub first: Breakend,
+    /// The second breakend.
+    pub second: Breakend,
+    /// The name of the query sequence.
+    pub query_name: String,
+    /// The zero-based start of the variant on the original strand of the query,
+    /// i.e. of the query bases between the two alignments or of the inserted bases.
+    pub query_start: usize,
+    /// The zero-based exclusive end of the variant on the original strand of the query.
+    pub query_end: usize,
+}
+
+impl StructuralVariant {
+    /// Returns the BEDPE line of this variant, without line terminator.
+    ///
+    /// Each breakend is a one-base interval starting at its position, and the name is the type of the variant.
+    pub fn format_bedpe(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t.\t{}\t{}",
+            self.first.sequence_name,
+            self.first.position,
+            self.first.position + 1,
+            self.second.sequence_name,
+            self.second.position,
+            self.second.position + 1,
+            self.sv_type,
+            self.first.strand,
+            self.second.strand,
+        )
+    }
+}
+
+/// Detects structural variants from the junctions between the alignments of each query,
+/// and from the long insertions and deletions within single alignments.
+///
+/// The alignments of a query are ordered by their query coordinates, and each pair of adjacent alignments is classified as
+/// translocation if the targets differ, as inversion if the strands differ,
+/// as duplication if the second alignment continues before the end of the first alignment on the target,
+/// and its overlap on the target exceeds the distance on the query by at least `min_size` bases,
+/// and otherwise as deletion or insertion if the distance on the target differs from the distance on the query by at least `min_size` bases.
+/// Alignments that overlap by the same amount on the query and on the target are consistent and yield no variant.
+/// Within single alignments, insertions and deletions are taken from the cigar string.
+///
+/// Unmapped lines and secondary alignments are ignored.
+/// The variants are ordered by the first occurrence of their query in the given lines, and then by query coordinate.
+pub fn detect_structural_variants(
+    lines: &[PAFLine],
+    options: &SvOptions,
+) -> Vec<StructuralVariant> {
+    let mut variants = Vec::new();
+    for query_lines in group_by_query(lines, options) {
+        let mut query_variants = Vec::new();
+        for line in &query_lines {
+            query_variants.extend(alignment_variants(line, options));
+        }
+        for pair in query_lines.windows(2) {
+            query_variants.extend(junction_variant(pair[0], pair[1], options));
+        }
+        query_variants.sort_by_key(|variant| (variant.query_start, variant.query_end));
+        variants.extend(query_variants);
+    }
+    variants
+}
+
+/// Returns the accepted alignments of each query, sorted by query coordinates,
+/// with the queries in the order of their first occurrence.
+fn group_by_query<'line>(lines: &'line [PAFLine], options: &SvOptions) -> Vec<Vec<&'line PAFLine>> {
+    let mut queries: Vec<Vec<&PAFLine>> = Vec::new();
+    let mut query_indices = HashMap::new();
+    for line in lines.iter().filter(|line| options.accepts(line)) {
+        let index = *query_indices
+            .entry(line.query_sequence_name.as_str())
+            .or_insert_with(|| {
+                queries.push(Vec::new());
+                queries.len() - 1
+            });
+        queries[index].push(line);
+    }
+
+    for query_lines in &mut queries {
+        query_lines.sort_by_key(|line| (line.query_start_coordinate, line.query_end_coordinate));
+    }
+    queries
+}
+
+/// Write the given variants as BEDPE lines, see [StructuralVariant::format_bedpe].
+pub fn write_bedpe(variants: &[StructuralVariant], mut output: impl Write) -> std::io::Result<()> {
+    for variant in variants {
+        writeln!(output, "{}", variant.format_bedpe())?;
+    }
+    Ok(())
+}
+
+/// Write the given variants as VCF 4.2 with symbolic alleles, e.g. `<DEL>`, and the given contigs in the header.
+///
+/// The contigs can be taken from the alignments, e.g. as [SamHeader::from_lines](crate::sam::SamHeader::from_lines).
+/// The variants are written in the order of the contigs of their first breakend and then by position.
+/// `POS` and `END` are the bases before the first and second breakend, and translocations have their second contig in `CHR2`.
+/// The reference allele is `N`, as the target sequence is unknown.
+pub fn write_vcf(
+    variants: &[StructuralVariant],
+    contigs: &[(String, usize)],
+    mut output: impl Write,
+) -> std::io::Result<()> {
+    writeln!(output, "##fileformat=VCFv4.2")?;
+    writeln!(output, "##source={}", env!("CARGO_PKG_NAME"))?;
+    for (name, length) in contigs {
+        writeln!(output, "##contig=<ID={name},length={length}>")?;
+    }
+    for (id, description) in [
+        ("DEL", "Deletion"),
+        ("INS", "Insertion"),
+        ("INV", "Inversion"),
+        ("DUP", "Duplication"),
+        ("TRA", "Translocation"),
+    ] {
+        writeln!(output, "##ALT=<ID={id},Description=\"{description}\">")?;
+    }
+    for header in [
+        "##INFO=<ID=SVTYPE,Number=1,Type=String,Description=\"Type of the structural variant\">",
+        "##INFO=<ID=SVLEN,Number=1,Type=Integer,Description=\"Length of the structural variant, negative for deletions\">",
+        "##INFO=<ID=END,Number=1,Type=Integer,Description=\"Position of the second breakend\">",
+        "##INFO=<ID=CHR2,Number=1,Type=String,Description=\"Contig of the second breakend\">",
+        "##INFO=<ID=QNAME,Number=1,Type=String,Description=\"Name of the query sequence\">",
+        "##INFO=<ID=QSTART,Number=1,Type=Integer,Description=\"Zero-based start of the variant on the original strand of the query\">",
+        "##INFO=<ID=QEND,Number=1,Type=Integer,Description=\"Zero-based exclusive end of the variant on the original strand of the query\">",
+        "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO",
+    ] {
+        writeln!(output, "{header}")?;
+    }
+
+    let contig_indices: HashMap<&str, usize> = contigs
+        .iter()
+        .enumerate()
+        .map(|(index, (name, _))| (name.as_str(), index))
+        .collect();
+    let mut sorted_variants: Vec<_> = variants.iter().collect();
+    sorted_variants.sort_by_key(|variant| {
+        (
+            contig_indices
+                .get(variant.first.sequence_name.as_str())
+                .copied()
+                .unwrap_or(usize::MAX),
+            variant.first.position,
+        )
+    });
+
+    for variant in sorted_variants {
+        let mut info = format!("SVTYPE={}", variant.sv_type);
+        if let Some(length) = variant.length {
+            if variant.sv_type == SvType::Deletion {
+                info.push_str(&format!(";SVLEN=-{length}"));
+            } else {
+                info.push_str(&format!(";SVLEN={length}"));
+            }
+        }
+        if variant.second.sequence_name != variant.first.sequence_name {
+            info.push_str(&format!(";CHR2={}", variant.second.sequence_name));
+        }
+        info.push_str(&format!(
+            ";END={};QNAME={};QSTART={};QEND={}",
+            variant.second.position.max(1),
+            variant.query_name,
+            variant.query_start,
+            variant.query_end
+        ));
+        writeln!(
+            output,
+            "{}\t{}\t.\tN\t<{}>\t.\tPASS\t{info}",
+            variant.first.sequence_name,
+            variant.first.position.max(1),
+            variant.sv_type,
+        )?;
+    }
+    Ok(())
+}
+
+/// Returns the variant at the junction between the given alignments, where the first one precedes the second one on the query.
+fn junction_variant(
+    first: &PAFLine,
+    second: &PAFLine,
+    options: &SvOptions,
+) -> Option<StructuralVariant> {
+    // the target positions at the end of the first and at the start of the second alignment, in the direction of the query
+    let first_end = if first.strand == Strand::Reverse {
+        first.target_start_coordinate_on_original_strand
+    } else {
+        first.target_end_coordinate_on_original_strand
+    };
+    let second_start = if second.strand == Strand::Reverse {
+        second.target_end_coordinate_on_original_strand
+    } else {
+        second.target_start_coordinate_on_original_strand
+    };
+    let query_start = first
+        .query_end_coordinate
+        .min(second.query_start_coordinate);
+    let query_end = first
+        .query_end_coordinate
+        .max(second.query_start_coordinate);
+    let query_gap = second.query_start_coordinate as isize - first.query_end_coordinate as isize;
+
+    let (sv_type, length) = if first.target_sequence_name != second.target_sequence_name {
+        (SvType::Translocation, None)
+    } else if first.strand != second.strand {
+        (SvType::Inversion, None)
+    } else {
+        let target_gap = if first.strand == Strand::Reverse {
+            first_end as isize - second_start as isize
+        } else {
+            second_start as isize - first_end as isize
+        };
+        let min_size = options.min_size as isize;
+        // a duplication repeats more target bases on the query than the query overlaps itself
+        if target_gap < 0 && query_gap - target_gap >= min_size {
+            (SvType::Duplication, Some((query_gap - target_gap) as usize))
+        } else if target_gap - query_gap >= min_size {
+            (SvType::Deletion, Some((target_gap - query_gap) as usize))
+        } else if query_gap - target_gap >= min_size {
+            (SvType::Insertion, Some((query_gap - target_gap) as usize))
+        } else {
+            return None;
+        }
+    };
+
+    let mut breakends = [
+        Breakend {
+            sequence_name: first.target_sequence_name.clone(),
+            position: first_end,
+            strand: first.strand,
+        },
+        Breakend {
+            sequence_name: second.target_sequence_name.clone(),
+            position: second_start,
+            strand: second.strand,
+        },
+    ];
+    if sv_type != SvType::Translocation && breakends[0].position > breakends[1].position {
+        breakends.swap(0, 1);
+    }
+    let [first_breakend, second_breakend] = breakends;
+
+    Some(StructuralVariant {
+        sv_type,
+        length,
+        first: first_breakend,
+        second: second_breakend,
+        query_name: first.query_sequence_name.clone(),
+        query_start,
+        query_end,
+    })
+}
+
+/// Returns the insertions and deletions of at least the minimum size within the cigar string of the given alignment.
+fn alignment_variants(line: &PAFLine, options: &SvOptions) -> Vec<StructuralVariant> {
+    let mut variants = Vec::new();
+    let cigar = match &line.cigar_string {
+        Some(cigar) => cigar,
+        None => return variants,
+    };
+
+    let mut query_offset = 0;
+    let mut target_position = line.target_start_coordinate_on_original_strand;
+    for column in &cigar.0 {
+        let length = column.length();
+        let (sv_type, target_length, query_length) = match column {
+            CigarColumn::Deletion(_) => (SvType::Deletion, length, 0),
+            CigarColumn::Insertion(_) => (SvType::Insertion, 0, length),
+            _ => {
+                if column.consumes_query() {
+                    query_offset += length;
+                }
+                if column.consumes_target() {
+                    target_position += length;
+                }
+                continue;
+            }
+        };
+
+        if length >= options.min_size {
+            // the boundaries of the variant in the direction of the alignment, converted to the original strand of the query
+            let boundaries = (
+                line.aligned_boundary_to_query(query_offset),
+                line.aligned_boundary_to_query(query_offset + query_length),
+            );
+            if let (Some(start), Some(end)) = boundaries {
+                let breakend = |position| Breakend {
+                    sequence_name: line.target_sequence_name.clone(),
+                    position,
+                    strand: line.strand,
+                };
+                variants.push(StructuralVariant {
+                    sv_type,
+                    length: Some(length),
+                    first: breakend(target_position),
+                    second: breakend(target_position + target_length),
+                    query_name: line.query_sequence_name.clone(),
+                    query_start: start.min(end),
+                    query_end: start.max(end),
+                });
+            }
+        }
+        query_offset += query_length;
+        target_position += target_length;
+    }
+    variants
+}
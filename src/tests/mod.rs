@@ -1,4 +1,10 @@
-use crate::input::{parse_input_read, parse_input_str};
+use crate::data::{
+    AlignmentType, CigarColumn, DifferenceColumn, DifferenceColumnRef, PAFLine, Strand, Tag,
+    TagArray, TagValue,
+};
+use crate::error::{Error, ErrorPosition};
+use crate::input::{parse_input_read, parse_input_str, FieldMode, PafReader, ParserOptions};
+use crate::output::{PafWriter, WriterOptions};
 use std::fmt::Write;
 
 #[test]
@@ -17,3 +23,1404 @@ ctg1	3423273	0	35825	-	NC_000913.3	3421989	0	35833	35825	35833	60	NM:i:8	ms:i:35
 
     assert_eq!(input, output);
 }
+
+#[test]
+fn test_paf_reader_read_into() {
+    let input = "read1\t1000\t10\t990\t+\tcontig1\t5000\t100\t1080\t970\t980\t60\tNM:i:10\tzd:i:3\tcg:Z:500M2D478M\nread2\t500\t0\t500\t-\tcontig2\t800\t300\t800\t500\t500\t0";
+    let expected = parse_input_str(input).unwrap();
+    assert_eq!(expected.len(), 2);
+    assert_eq!(expected[1].mapping_quality, 0);
+    assert!(expected[1].cigar_string.is_none());
+    assert!(expected[1].unknown_fields.is_empty());
+
+    let mut reader = PafReader::new(input.as_bytes());
+    let mut line = PAFLine::default();
+    assert!(reader.read_into(&mut line).unwrap());
+    assert_eq!(line, expected[0]);
+    assert!(reader.read_into(&mut line).unwrap());
+    assert_eq!(line, expected[1]);
+    assert!(!reader.read_into(&mut line).unwrap());
+    assert_eq!(line, expected[1]);
+}
+
+#[test]
+fn test_error_position() {
+    let input = "read1\t1000\t10\t990\t+\tcontig1\t5000\t100\t1080\t970\t980\t60\tNM:i:10\nread2\t500\t0\t500\t-\tcontig2\t800\t300\t800\t500\t500\t0\tNM:i:10\tcm:i:x7\n";
+    let error = parse_input_str(input).unwrap_err();
+    assert_eq!(
+        error.position(),
+        Some(&ErrorPosition {
+            line: 2,
+            byte_offset: 117,
+            column: 14,
+            tag: Some("cm:i:".to_string()),
+        })
+    );
+    assert!(matches!(
+        &error,
+        Error::Located { error, .. } if matches!(
+            error.as_ref(),
+            Error::MalformedTagValue { tag, value } if tag == "cm:i:" && value == "x7"
+        )
+    ));
+    assert_eq!(
+        error.to_string(),
+        "error: malformed value `x7` for tag `cm:i:`\n  --> line 2, column 14 (`cm:i:`), byte offset 117"
+    );
+
+    let error = parse_input_str("read1\t1000\t10\t990\t?\tcontig1").unwrap_err();
+    let position = error.position().unwrap();
+    assert_eq!(
+        (position.line, position.column, position.byte_offset),
+        (1, 5, 18)
+    );
+    assert_eq!(position.tag, None);
+}
+
+#[test]
+fn test_unmapped_records() {
+    let input = "read1\t1000\t0\t0\t*\t*\t0\t0\t0\t0\t0\t0\trl:i:0\nread2\t500\t0\t500\t-\tcontig2\t800\t300\t800\t500\t500\t0\n";
+    let parsed_lines = parse_input_str(input).unwrap();
+    assert!(parsed_lines[0].is_unmapped());
+    assert_eq!(parsed_lines[0].strand, Strand::Unmapped);
+    assert_eq!(parsed_lines[0].target_sequence_name, "*");
+    assert!(!parsed_lines[1].is_unmapped());
+    assert_eq!(parsed_lines[1].strand, Strand::Reverse);
+
+    let mut output = String::new();
+    for line in &parsed_lines {
+        writeln!(output, "{line}").unwrap();
+    }
+    assert_eq!(input, output);
+}
+
+#[test]
+fn test_full_cigar_operator_set() {
+    let input = "read1\t1000\t10\t990\t+\tcontig1\t5000\t100\t1180\t970\t1080\t60\tcg:Z:3S10=1X100=100N850M5I2D4P6H\n";
+    let parsed_lines = parse_input_str(input).unwrap();
+    let cigar = parsed_lines[0].cigar_string.as_ref().unwrap();
+    assert_eq!(cigar.0[0], CigarColumn::SoftClip(3));
+    assert_eq!(cigar.0[3], CigarColumn::SequenceMatch(100));
+    assert_eq!(cigar.0[4], CigarColumn::Skip(100));
+    assert_eq!(cigar.query_length(), 3 + 10 + 1 + 100 + 850 + 5);
+    assert_eq!(cigar.target_length(), 10 + 1 + 100 + 100 + 850 + 2);
+    assert!(!CigarColumn::HardClip(6).consumes_query());
+    assert!(!CigarColumn::Padding(4).consumes_target());
+
+    let mut output = String::new();
+    for line in &parsed_lines {
+        writeln!(output, "{line}").unwrap();
+    }
+    assert_eq!(input, output);
+}
+
+#[test]
+fn test_long_form_alignment_difference() {
+    let input = "read1\t30\t0\t30\t+\tcontig1\t130\t0\t130\t28\t131\t60\tcg:Z:6M1I4M1D5M100N14M\tcs:Z:=ACGTAC+g=TTGA-c=AC*ag=TA~gt100ag=ACGTACGTACGTAC\n";
+    let parsed_lines = parse_input_str(input).unwrap();
+    let difference = parsed_lines[0].difference_string.as_ref().unwrap();
+    assert!(difference.is_long_form());
+    assert_eq!(
+        difference.0[7],
+        DifferenceColumn::Intron {
+            donor: "gt".to_string(),
+            length: 100,
+            acceptor: "ag".to_string(),
+        }
+    );
+
+    let mut output = String::new();
+    for line in &parsed_lines {
+        writeln!(output, "{line}").unwrap();
+    }
+    assert_eq!(input, output);
+
+    let short_form = difference.to_short_form();
+    assert!(!short_form.is_long_form());
+    assert_eq!(short_form.to_string(), ":6+g:4-c:2*ag:2~gt100ag:14");
+
+    let target = format!("ACGTACTTGAcACATA{}ACGTACGTACGTAC", "n".repeat(100));
+    assert_eq!(short_form.to_long_form(&target).as_ref(), Some(difference));
+    assert_eq!(short_form.to_long_form(&target[..50]), None);
+}
+
+#[test]
+fn test_typed_unknown_fields() {
+    let input = "read1\t1000\t10\t990\t+\tcontig1\t5000\t100\t1080\t970\t980\t60\tNM:i:10\tzd:i:-3\tzc:A:x\tzf:f:0.25\tzs:Z:some text\tzh:H:1AE301\tzb:B:s,-1,2,300\tze:B:f\n";
+    let parsed_lines = parse_input_str(input).unwrap();
+    let line = &parsed_lines[0];
+    assert_eq!(line.unknown_fields.len(), 7);
+    assert_eq!(line.tag_i64(b"zd"), Some(-3));
+    assert_eq!(line.tag_char(b"zc"), Some('x'));
+    assert_eq!(line.tag_f64(b"zf"), Some(0.25));
+    assert_eq!(line.tag_str(b"zs"), Some("some text"));
+    assert_eq!(
+        line.tag(b"zh"),
+        Some(&TagValue::Hex(vec![0x1A, 0xE3, 0x01]))
+    );
+    assert_eq!(
+        line.tag(b"zb"),
+        Some(&TagValue::Array(TagArray::Int16(vec![-1, 2, 300])))
+    );
+    assert_eq!(line.tag_i64(b"zs"), None);
+    assert_eq!(line.tag(b"NM"), None);
+
+    let mut output = String::new();
+    for line in &parsed_lines {
+        writeln!(output, "{line}").unwrap();
+    }
+    assert_eq!(input, output);
+
+    let tag: Tag = "zd:i:-3".parse().unwrap();
+    assert_eq!(tag.to_string(), "zd:i:-3");
+    assert!("zd:i:x".parse::<Tag>().is_err());
+    assert!("zd:Q:x".parse::<Tag>().is_err());
+}
+
+#[test]
+fn test_lossless_round_trip() {
+    let input = "read1\t1000\t10\t990\t+\tcontig1\t5000\t100\t1080\t970\t980\t60\ttp:A:P\tde:f:1e-04\tzh:H:1ae3\tNM:i:010\tcg:Z:980M\tzf:f:0.50\tNM:i:11\tdv:f:0.0100\ts1:i:+25\tzd:i:+1\tzi:i:007\n";
+
+    let default_output = parse_input_str(input).unwrap()[0].to_string() + "\n";
+    assert_ne!(input, default_output);
+
+    let options = ParserOptions::default().with_lossless(true);
+    let parsed_lines = PafReader::with_options(input.as_bytes(), options)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let line = &parsed_lines[0];
+    assert_eq!(line.total_number_of_mismatches_and_gaps, Some(10));
+    assert_eq!(line.tag_i64(b"NM"), Some(11));
+    assert_eq!(line.chaining_score, Some(25));
+    assert_eq!(line.tag_i64(b"zd"), Some(1));
+    assert_eq!(
+        line.gap_compressed_per_base_sequence_divergence,
+        Some(0.0001)
+    );
+
+    let mut output = String::new();
+    for line in &parsed_lines {
+        writeln!(output, "{line}").unwrap();
+    }
+    assert_eq!(input, output);
+
+    // changed values are written anew, and added fields are appended
+    let mut line = line.clone();
+    line.gap_compressed_per_base_sequence_divergence = Some(0.5);
+    line.number_of_minimisers = Some(7);
+    line.chaining_score = Some(26);
+    assert_eq!(line.to_string(), "read1\t1000\t10\t990\t+\tcontig1\t5000\t100\t1080\t970\t980\t60\ttp:A:P\tde:f:0.5\tzh:H:1ae3\tNM:i:010\tcg:Z:980M\tzf:f:0.50\tNM:i:11\tdv:f:0.0100\ts1:i:26\tzd:i:+1\tzi:i:007\tcm:i:7");
+}
+
+#[test]
+fn test_paf_writer() {
+    let input = "read1\t1000\t10\t990\t-\tcontig1\t5000\t100\t1080\t970\t980\t60\tNM:i:10\tAS:i:-20\ttp:A:P\tde:f:0.0123\tSA:Z:contig2,5,+,10M,60,0;\tcg:Z:980M\tcs:Z::980\tzd:i:3\n";
+    let parsed_lines = parse_input_str(input).unwrap();
+
+    let mut writer = PafWriter::new(Vec::new());
+    for line in &parsed_lines {
+        writer.write_line(line).unwrap();
+    }
+    assert_eq!(input.as_bytes(), writer.into_inner().as_slice());
+
+    let options = WriterOptions::default()
+        .with_excluded_tags([*b"cg", *b"cs", *b"SA"])
+        .with_tag_order([*b"tp", *b"zd", *b"de"])
+        .with_float_precision(2)
+        .with_omit_unknown_fields(false);
+    let mut writer = PafWriter::with_options(Vec::new(), options);
+    writer.write_line(&parsed_lines[0]).unwrap();
+    assert_eq!(
+        String::from_utf8(writer.into_inner()).unwrap(),
+        "read1\t1000\t10\t990\t-\tcontig1\t5000\t100\t1080\t970\t980\t60\ttp:A:P\tzd:i:3\tde:f:0.01\tNM:i:10\tAS:i:-20\n"
+    );
+
+    let options = WriterOptions::default().with_omit_unknown_fields(true);
+    let mut writer = PafWriter::with_options(Vec::new(), options);
+    writer.write_line(&parsed_lines[0]).unwrap();
+    assert!(!String::from_utf8(writer.into_inner())
+        .unwrap()
+        .contains("zd:i:3"));
+}
+
+#[test]
+fn test_borrowed_lines() {
+    let input = "read1\t1000\t10\t990\t+\tcontig1\t5000\t100\t1080\t970\t980\t60\tNM:i:10\tzd:i:3\tSA:Z:contig2,5,+,10M,60,0;\tcg:Z:500M2D478M\tcs:Z::500-ac:478\tzs:Z:text\nread2\t500\t0\t500\t-\tcontig2\t800\t300\t800\t500\t500\t0\tcg:Z:5Q\n";
+    let expected = parse_input_str(&input[..input.find("read2").unwrap()]).unwrap();
+
+    let mut reader = PafReader::new(input.as_bytes());
+    let line = reader.read_ref().unwrap().unwrap();
+    assert_eq!(line.query_sequence_name, "read1");
+    assert_eq!(line.supplementary_alignments, Some("contig2,5,+,10M,60,0;"));
+    assert_eq!(
+        line.cigar_string.unwrap().iter().nth(1).unwrap().unwrap(),
+        CigarColumn::Deletion(2)
+    );
+    assert_eq!(
+        line.difference_string
+            .unwrap()
+            .iter()
+            .nth(1)
+            .unwrap()
+            .unwrap(),
+        DifferenceColumnRef::Deletion {
+            missing_query_characters: "ac"
+        }
+    );
+    assert_eq!(line.unknown_fields.iter().count(), 2);
+    assert_eq!(line.tag(b"zs").unwrap().value, "text");
+    assert_eq!(
+        line.tag(b"zd").unwrap().parse_value().unwrap(),
+        TagValue::Integer(3)
+    );
+    assert_eq!(line.to_owned().unwrap(), expected[0]);
+
+    // the malformed cigar is only detected when it is accessed
+    let line = reader.read_ref().unwrap().unwrap();
+    assert!(line.cigar_string.unwrap().to_owned().is_err());
+    assert!(line.to_owned().is_err());
+    assert!(reader.read_ref().unwrap().is_none());
+}
+
+#[test]
+fn test_projected_parsing() {
+    let input = "read1\t1000\t10\t990\t+\tcontig1\t5000\t100\t1080\t970\t980\t60\tNM:i:10\tms:i:900\ttp:A:P\tcg:Z:500M2D478M\tcs:Z::500-ac:478\tzd:i:3\n";
+
+    let options = ParserOptions::default().with_only_fields([*b"tp", *b"NM"]);
+    let line = PafReader::with_options(input.as_bytes(), options)
+        .next()
+        .unwrap()
+        .unwrap();
+    assert_eq!(line.total_number_of_mismatches_and_gaps, Some(10));
+    assert_eq!(line.alignment_type, Some(AlignmentType::Primary));
+    assert_eq!(line.best_segment_dp_score, None);
+    assert_eq!(line.cigar_string, None);
+    assert!(line.unknown_fields.is_empty());
+    assert!(line.raw_fields.is_empty());
+
+    let options = ParserOptions::default()
+        .with_field_mode(*b"cg", FieldMode::Raw)
+        .with_field_mode(*b"cs", FieldMode::Raw)
+        .with_field_mode(*b"ms", FieldMode::Skip);
+    let line = PafReader::with_options(input.as_bytes(), options)
+        .next()
+        .unwrap()
+        .unwrap();
+    assert_eq!(line.cigar_string, None);
+    assert_eq!(line.difference_string, None);
+    assert_eq!(line.best_segment_dp_score, None);
+    assert_eq!(line.tag_i64(b"zd"), Some(3));
+    assert_eq!(
+        line.raw_cigar_string()
+            .unwrap()
+            .iter()
+            .nth(1)
+            .unwrap()
+            .unwrap(),
+        CigarColumn::Deletion(2)
+    );
+    assert_eq!(
+        line.raw_difference_string().unwrap().to_owned().unwrap(),
+        parse_input_str(input).unwrap()[0]
+            .difference_string
+            .clone()
+            .unwrap()
+    );
+    assert_eq!(
+        line.to_string(),
+        "read1\t1000\t10\t990\t+\tcontig1\t5000\t100\t1080\t970\t980\t60\tNM:i:10\ttp:A:P\tcg:Z:500M2D478M\tcs:Z::500-ac:478\tzd:i:3"
+    );
+
+    // raw fields keep their position in lossless mode
+    let input = "read1\t1000\t10\t990\t+\tcontig1\t5000\t100\t1080\t970\t980\t60\tcg:Z:500M2D478M\tNM:i:10\n";
+    let options = ParserOptions::default()
+        .with_lossless(true)
+        .with_field_mode(*b"cg", FieldMode::Raw);
+    let line = PafReader::with_options(input.as_bytes(), options)
+        .next()
+        .unwrap()
+        .unwrap();
+    assert_eq!(line.to_string() + "\n", input);
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn test_parallel_parsing() {
+    use crate::parallel::{
+        for_each_parallel, map_parallel, parse_buffer_parallel, ParallelPafReader,
+    };
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let mut input = String::new();
+    for index in 0..1000 {
+        writeln!(
+            input,
+            "read{index}\t1000\t10\t990\t+\tcontig1\t5000\t{index}\t1080\t970\t980\t60\tNM:i:10"
+        )
+        .unwrap();
+    }
+    let expected = parse_input_str(&input).unwrap();
+    let options = ParserOptions::default();
+
+    assert_eq!(
+        parse_buffer_parallel(input.as_bytes(), &options).unwrap(),
+        expected
+    );
+    assert_eq!(
+        map_parallel(input.as_bytes(), &options, |line| line
+            .target_start_coordinate_on_original_strand)
+        .unwrap(),
+        (0..1000).collect::<Vec<_>>()
+    );
+    let count = AtomicUsize::new(0);
+    for_each_parallel(input.as_bytes(), &options, |_| {
+        count.fetch_add(1, Ordering::Relaxed);
+    })
+    .unwrap();
+    assert_eq!(count.into_inner(), 1000);
+
+    let lines: Vec<_> = ParallelPafReader::new(input.as_bytes())
+        .with_chunk_size(100)
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(lines, expected);
+
+    // errors are located in the whole input
+    input.push_str("read\t1000\tx\n");
+    let error = ParallelPafReader::new(input.as_bytes())
+        .with_chunk_size(100)
+        .find_map(Result::err)
+        .unwrap();
+    assert_eq!(error.position().unwrap().line, 1001);
+    assert!(parse_buffer_parallel(input.as_bytes(), &options).is_err());
+}
+
+#[cfg(all(feature = "gzip", feature = "zstd"))]
+#[test]
+fn test_compression() {
+    use crate::compression::{decompress, BgzfWriter, CompressedWriter, Compression};
+    use std::io::Read;
+
+    let input =
+        "read1\t1000\t10\t990\t+\tcontig1\t5000\t100\t1080\t970\t980\t60\tNM:i:10\n".repeat(5000);
+    let expected = parse_input_str(&input).unwrap();
+
+    for compression in [
+        Compression::None,
+        Compression::Gzip,
+        Compression::Bgzf,
+        Compression::Zstd,
+    ] {
+        let mut writer = PafWriter::new(CompressedWriter::new(Vec::new(), compression).unwrap());
+        for line in &expected {
+            writer.write_line(line).unwrap();
+        }
+        let compressed = writer.into_inner().finish().unwrap();
+        assert_eq!(Compression::detect(&compressed), compression);
+
+        let mut reader = decompress(std::io::Cursor::new(compressed)).unwrap();
+        assert_eq!(parse_input_read(&mut reader).unwrap(), expected);
+    }
+
+    // BGZF files end with an empty block and can be read by plain gzip decoders
+    let mut writer = BgzfWriter::new(Vec::new());
+    std::io::Write::write_all(&mut writer, input.as_bytes()).unwrap();
+    assert!(writer.virtual_position() > 1 << 16);
+    let compressed = writer.finish().unwrap();
+    assert!(compressed.ends_with(&crate::compression::BGZF_EOF_BLOCK));
+    let mut decompressed = String::new();
+    flate2::read::MultiGzDecoder::new(compressed.as_slice())
+        .read_to_string(&mut decompressed)
+        .unwrap();
+    assert_eq!(decompressed, input);
+}
+
+#[test]
+fn test_paf_index() {
+    use crate::index::{IndexKey, IndexedPafReader, PafIndex};
+
+    let mut input = String::new();
+    for index in 0..2000 {
+        let contig = if index % 2 == 0 { "chr1" } else { "chr7" };
+        let start = index * 10_000;
+        writeln!(
+            input,
+            "read{index}\t1000\t0\t1000\t+\t{contig}\t100000000\t{start}\t{}\t1000\t1000\t60",
+            start + 1000
+        )
+        .unwrap();
+    }
+    writeln!(input, "read0\t1000\t0\t1000\t*\t*\t0\t0\t0\t0\t0\t255").unwrap();
+    let lines = parse_input_str(&input).unwrap();
+
+    let directory = std::env::temp_dir().join(format!("paf-index-test-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    #[cfg_attr(not(feature = "gzip"), allow(unused_mut))]
+    let mut paths = vec![directory.join("alignments.paf")];
+    std::fs::write(&paths[0], &input).unwrap();
+    #[cfg(feature = "gzip")]
+    {
+        use crate::compression::{CompressedWriter, Compression};
+        let path = directory.join("alignments.paf.gz");
+        let mut writer = PafWriter::new(
+            CompressedWriter::new(std::fs::File::create(&path).unwrap(), Compression::Bgzf)
+                .unwrap(),
+        );
+        for line in &lines {
+            writer.write_line(line).unwrap();
+        }
+        writer.into_inner().finish().unwrap();
+        paths.push(path);
+    }
+
+    for path in &paths {
+        for key in [IndexKey::Target, IndexKey::Query] {
+            let index = PafIndex::build(path, key).unwrap();
+            index.save(PafIndex::sidecar_path(path, key)).unwrap();
+        }
+
+        let mut reader = IndexedPafReader::open(path, IndexKey::Target).unwrap();
+        let expected: Vec<_> = lines
+            .iter()
+            .filter(|line| {
+                line.target_sequence_name == "chr7"
+                    && line.target_start_coordinate_on_original_strand < 2_000_000
+                    && line.target_end_coordinate_on_original_strand > 1_000_000
+            })
+            .cloned()
+            .collect();
+        assert_eq!(expected.len(), 50);
+        assert_eq!(
+            reader.query("chr7", 1_000_000, 2_000_000).unwrap(),
+            expected
+        );
+        assert!(reader.query("chr2", 0, 1_000_000).unwrap().is_empty());
+        assert_eq!(reader.query_sequence("chr1").unwrap().len(), 1000);
+
+        let mut reader = IndexedPafReader::open(path, IndexKey::Query).unwrap();
+        let read0 = reader.query_sequence("read0").unwrap();
+        assert_eq!(read0.len(), 2);
+        assert!(read0[1].is_unmapped());
+        assert_eq!(reader.index().sequence_names().count(), 2000);
+    }
+
+    // errors of queried lines are located by their offset
+    let index = PafIndex::build(&paths[0], IndexKey::Target).unwrap();
+    let line_offset = input.find("read1\t").unwrap();
+    let column_offset = line_offset + input[line_offset..].find("\t60\n").unwrap() + 1;
+    let mut corrupted = input.clone();
+    corrupted.replace_range(column_offset..column_offset + 2, "6x");
+    let mut reader = IndexedPafReader::new(std::io::Cursor::new(corrupted), index).unwrap();
+    let error = reader.query("chr7", 0, 1000).unwrap_err();
+    let position = error.position().unwrap();
+    assert_eq!((position.line, position.column), (0, 12));
+    assert_eq!(position.byte_offset, column_offset as u64);
+
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_alignment_index() {
+    use crate::index::IndexKey;
+    use crate::interval::AlignmentIndex;
+
+    // pseudo-random alignments of varying lengths
+    let mut input = String::new();
+    let mut state = 12345usize;
+    for index in 0..500 {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        let start = (state >> 20) % 100_000;
+        let length = 1 + (state >> 40) % if index % 10 == 0 { 20_000 } else { 1000 };
+        writeln!(
+            input,
+            "read{}\t{length}\t0\t{length}\t+\tchr1\t200000\t{start}\t{}\t{length}\t{length}\t60",
+            index % 100,
+            start + length
+        )
+        .unwrap();
+    }
+    let lines = parse_input_str(&input).unwrap();
+    let index = AlignmentIndex::from_reader(input.as_bytes()).unwrap();
+
+    let mut sorted_lines: Vec<_> = lines.iter().collect();
+    sorted_lines.sort_by_key(|line| {
+        (
+            line.target_start_coordinate_on_original_strand,
+            line.target_end_coordinate_on_original_strand,
+        )
+    });
+    let target = |line: &&PAFLine| {
+        (
+            line.target_start_coordinate_on_original_strand,
+            line.target_end_coordinate_on_original_strand,
+        )
+    };
+    for (start, end) in [
+        (0, 1),
+        (5000, 7000),
+        (50_000, 50_100),
+        (0, 200_000),
+        (150_000, 160_000),
+    ] {
+        let expected: Vec<_> = sorted_lines
+            .iter()
+            .copied()
+            .filter(|line| target(line).0 < end && target(line).1 > start)
+            .collect();
+        let actual = index.overlapping(IndexKey::Target, "chr1", start, end);
+        assert_eq!(
+            actual.iter().map(target).collect::<Vec<_>>(),
+            expected.iter().map(target).collect::<Vec<_>>()
+        );
+
+        let containing = index.containing(IndexKey::Target, "chr1", start, end);
+        assert!(containing
+            .iter()
+            .all(|line| target(line).0 <= start && target(line).1 >= end));
+        assert_eq!(
+            containing.len(),
+            expected
+                .iter()
+                .filter(|line| target(line).0 <= start && target(line).1 >= end)
+                .count()
+        );
+        let contained = index.contained_in(IndexKey::Target, "chr1", start, end);
+        assert_eq!(
+            contained.len(),
+            expected
+                .iter()
+                .filter(|line| target(line).0 >= start && target(line).1 <= end)
+                .count()
+        );
+    }
+
+    for position in [0, 12_345, 99_999, 150_000] {
+        let distance = |line: &PAFLine| {
+            let (start, end) = target(&line);
+            if position < start {
+                start - position
+            } else if position >= end {
+                position + 1 - end
+            } else {
+                0
+            }
+        };
+        let nearest = index.nearest(IndexKey::Target, "chr1", position).unwrap();
+        assert_eq!(distance(nearest), lines.iter().map(distance).min().unwrap());
+    }
+    assert!(index.nearest(IndexKey::Target, "chr2", 0).is_none());
+
+    let read7 = index.overlapping(IndexKey::Query, "read7", 0, 1);
+    assert_eq!(read7.len(), 5);
+    assert!(read7.iter().all(|line| line.query_sequence_name == "read7"));
+}
+
+#[test]
+fn test_coordinate_projection() {
+    use crate::projection::{ProjectedPosition, ProjectionKind};
+
+    let input = "read1\t30\t10\t24\t+\tcontig1\t500\t100\t113\t12\t15\t60\tcg:Z:5M2I3M1D4M\n\
+                 read1\t30\t10\t24\t-\tcontig1\t500\t100\t113\t12\t15\t60\tcg:Z:5M2I3M1D4M\n\
+                 read1\t30\t10\t24\t+\tcontig1\t500\t100\t113\t12\t15\t60\tcs:Z::5+ac:3-g:4\n";
+    let lines = parse_input_str(input).unwrap();
+    let position = |position, kind| Some(ProjectedPosition { position, kind });
+
+    for line in [&lines[0], &lines[2]] {
+        assert_eq!(
+            line.project_query_to_target(12),
+            position(102, ProjectionKind::Match)
+        );
+        assert_eq!(
+            line.project_query_to_target(15),
+            position(105, ProjectionKind::Insertion)
+        );
+        assert_eq!(
+            line.project_query_to_target(17),
+            position(105, ProjectionKind::Match)
+        );
+        assert_eq!(
+            line.project_target_to_query(108),
+            position(20, ProjectionKind::Deletion)
+        );
+        assert_eq!(
+            line.project_target_to_query(109),
+            position(20, ProjectionKind::Match)
+        );
+        assert_eq!(line.project_query_to_target(9), None);
+        assert_eq!(line.project_target_to_query(113), None);
+    }
+
+    let reverse = &lines[1];
+    assert_eq!(
+        reverse.project_query_to_target(23),
+        position(100, ProjectionKind::Match)
+    );
+    assert_eq!(
+        reverse.project_query_to_target(10),
+        position(112, ProjectionKind::Match)
+    );
+    assert_eq!(
+        reverse.project_query_to_target(18),
+        position(105, ProjectionKind::Insertion)
+    );
+    assert_eq!(
+        reverse.project_target_to_query(108),
+        position(14, ProjectionKind::Deletion)
+    );
+    assert_eq!(
+        reverse.project_target_to_query(109),
+        position(13, ProjectionKind::Match)
+    );
+
+    for line in &lines {
+        for query_position in 10..24 {
+            if let Some(ProjectedPosition {
+                position: target_position,
+                kind: ProjectionKind::Match,
+            }) = line.project_query_to_target(query_position)
+            {
+                assert_eq!(
+                    line.project_target_to_query(target_position),
+                    position(query_position, ProjectionKind::Match)
+                );
+            }
+        }
+    }
+    // a cigar string with more query bases than the query interval does not project before the query start
+    let inconsistent =
+        parse_input_str("read2\t30\t0\t5\t-\tcontig1\t500\t100\t110\t10\t10\t60\tcg:Z:10M\n")
+            .unwrap();
+    assert_eq!(
+        inconsistent[0].project_target_to_query(102),
+        position(2, ProjectionKind::Match)
+    );
+    assert_eq!(inconsistent[0].project_target_to_query(108), None);
+}
+
+#[test]
+fn test_bed_liftover() {
+    use crate::bed::{BedReader, BedRecord};
+    use crate::interval::AlignmentIndex;
+    use crate::liftover::{Liftover, LiftoverOptions};
+
+    let paf = "read1\t30\t10\t24\t+\tchr1\t500\t100\t113\t12\t15\t60\ttp:A:P\tcg:Z:5M2I3M1D4M\n\
+               read1\t30\t10\t24\t-\tchr2\t500\t100\t113\t12\t15\t3\ttp:A:S\tcg:Z:5M2I3M1D4M\n";
+    let index = AlignmentIndex::from_reader(paf.as_bytes()).unwrap();
+    let bed = "track name=genes\nread1\t11\t20\tgene1\t0\t+\nread2\t0\t10\n";
+    let records: Vec<_> = BedReader::new(bed.as_bytes())
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].to_string(), "read1\t11\t20\tgene1\t0\t+");
+
+    let liftover = Liftover::new(index.clone(), LiftoverOptions::default());
+    let lifted = liftover.lift(&records[0]);
+    assert_eq!(lifted.len(), 2);
+    assert_eq!(lifted[0].to_string(), "chr1\t101\t108\tgene1\t0\t+\t0.7778");
+    assert_eq!(lifted[1].to_string(), "chr2\t104\t112\tgene1\t0\t-\t0.7778");
+    assert!(liftover.lift(&records[1]).is_empty());
+
+    let liftover = Liftover::new(
+        index.clone(),
+        LiftoverOptions::default().with_split_at_indels(true),
+    );
+    let lifted: Vec<_> = liftover
+        .lift(&BedRecord::new("read1", 11, 20))
+        .into_iter()
+        .map(|interval| {
+            (
+                interval.record.chromosome,
+                interval.record.start,
+                interval.record.end,
+            )
+        })
+        .collect();
+    assert_eq!(
+        lifted,
+        [
+            ("chr1".to_string(), 101, 105),
+            ("chr1".to_string(), 105, 108),
+            ("chr2".to_string(), 104, 105),
+            ("chr2".to_string(), 105, 108),
+            ("chr2".to_string(), 109, 112),
+        ]
+    );
+
+    let liftover = Liftover::new(
+        index,
+        LiftoverOptions::default()
+            .with_min_mapping_quality(5)
+            .with_primary_only(true),
+    );
+    let mut output = Vec::new();
+    liftover.lift_bed(bed.as_bytes(), &mut output).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "chr1\t101\t108\tgene1\t0\t+\t0.7778\n"
+    );
+
+    let error = BedReader::new("chr1\t10\tx\n".as_bytes())
+        .next()
+        .unwrap()
+        .unwrap_err();
+    assert_eq!(error.position().unwrap().column, 3);
+}
+
+#[test]
+fn test_chain_conversion() {
+    use crate::chain::{write_chains, ChainReader, ChainRecord};
+
+    let input = "read1\t30\t10\t24\t+\tchr1\t500\t100\t113\t12\t15\t60\tAS:i:20\tcg:Z:5M2I3M1D4M\n\
+                 read1\t30\t10\t24\t-\tchr2\t500\t100\t113\t12\t15\t60\tcs:Z::5+ac:3-g:4\n\
+                 read2\t30\t10\t24\t-\t*\t0\t0\t0\t0\t0\t0\n";
+    let lines = parse_input_str(input).unwrap();
+
+    let mut output = Vec::new();
+    assert_eq!(write_chains(&lines, &mut output).unwrap(), 2);
+    let output = String::from_utf8(output).unwrap();
+    assert_eq!(
+        output,
+        "chain 20 chr1 500 + 100 113 read1 30 + 10 24 1\n5\t0\t2\n3\t1\t0\n4\n\n\
+         chain 12 chr2 500 + 100 113 read1 30 - 6 20 2\n5\t0\t2\n3\t1\t0\n4\n\n"
+    );
+
+    let chains: Vec<_> = ChainReader::new(output.as_bytes())
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(chains.len(), 2);
+    for (chain, line) in chains.iter().zip(&lines) {
+        let converted = chain.to_paf_line();
+        assert_eq!(converted.strand, line.strand);
+        assert_eq!(converted.query_start_coordinate, 10);
+        assert_eq!(converted.query_end_coordinate, 24);
+        assert_eq!(
+            converted.cigar_string.as_ref().unwrap().to_string(),
+            "5M2I3M1D4M"
+        );
+        assert_eq!(
+            ChainRecord::from_paf_line(&converted, chain.id).as_ref(),
+            Some(chain)
+        );
+        assert_eq!(&chain.swapped().swapped(), chain);
+    }
+
+    // swapping projects the same positions in the other direction
+    let swapped = chains[1].swapped().to_paf_line();
+    assert_eq!(swapped.target_sequence_name, "read1");
+    assert_eq!(swapped.query_start_coordinate, 100);
+    assert_eq!(swapped.target_end_coordinate_on_original_strand, 24);
+    for query_position in 10..24 {
+        if let Some(target) = lines[1].project_query_to_target(query_position) {
+            if target.kind == crate::projection::ProjectionKind::Match {
+                assert_eq!(
+                    swapped
+                        .project_target_to_query(query_position)
+                        .unwrap()
+                        .position,
+                    target.position
+                );
+            }
+        }
+    }
+
+    // gaps at the ends of the alignment are not part of the chain
+    let gapped =
+        parse_input_str("read3\t30\t10\t24\t+\tchr3\t500\t100\t111\t8\t17\t60\tcg:Z:2I1D8M4I2D\n")
+            .unwrap();
+    let mut output = Vec::new();
+    write_chains(&gapped, &mut output).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "chain 8 chr3 500 + 101 109 read3 30 + 12 20 1\n8\n\n"
+    );
+
+    let error = ChainReader::new("chain 1 chr1 500 + 0 10 read1 30 + 0\n".as_bytes())
+        .next()
+        .unwrap()
+        .unwrap_err();
+    let position = error.position().unwrap();
+    assert_eq!(
+        (position.line, position.column, position.byte_offset),
+        (1, 12, 36)
+    );
+    let error = ChainReader::new("chain 1 chr1 5x0 + 0 10 read1 30 + 0 10 1\n10\n".as_bytes())
+        .next()
+        .unwrap()
+        .unwrap_err();
+    let position = error.position().unwrap();
+    assert_eq!(
+        (position.line, position.column, position.byte_offset),
+        (1, 4, 13)
+    );
+    let error = ChainReader::new("chain 1 chr1 500 + 0 10 read1 30 + 0 10 1\n5 x 0\n".as_bytes())
+        .next()
+        .unwrap()
+        .unwrap_err();
+    let position = error.position().unwrap();
+    assert_eq!(
+        (position.line, position.column, position.byte_offset),
+        (2, 2, 44)
+    );
+}
+
+#[test]
+fn test_sam_output() {
+    use crate::sam::{sam_cigar, QuerySequence, QuerySequences, SamHeader, SamWriter};
+
+    let input =
+        "read1\t30\t10\t24\t+\tchr1\t500\t100\t113\t12\t15\t60\tNM:i:3\ttp:A:P\tcg:Z:5M2I3M1D4M\n\
+                 read1\t30\t10\t24\t-\tchr2\t400\t100\t113\t12\t15\t0\ttp:A:S\tcs:Z::5+ac:3-g:4\n\
+                 read2\t8\t0\t0\t*\t*\t0\t0\t0\t0\t0\t0\n";
+    let lines = parse_input_str(input).unwrap();
+
+    let header = SamHeader::from_lines(&lines);
+    assert_eq!(
+        header.to_string(),
+        "@HD\tVN:1.6\tSO:unsorted\n@SQ\tSN:chr1\tLN:500\n@SQ\tSN:chr2\tLN:400\n"
+    );
+
+    let mut sequences = QuerySequences::from_reader(
+        "@read1 description\nAAAAAAAAAACCCCCCCCCCGGGGGGGGGG\n+\n0123456789012345678901234567890\n"
+            .as_bytes(),
+    );
+    let position = sequences.as_ref().unwrap_err().position().unwrap();
+    assert_eq!(
+        (position.line, position.column, position.byte_offset),
+        (4, 31, 82)
+    );
+    let error = QuerySequences::from_reader("@read1\nACGT\n-\n0123\n".as_bytes()).unwrap_err();
+    let position = error.position().unwrap();
+    assert_eq!((position.line, position.byte_offset), (3, 12));
+    let error = QuerySequences::from_reader("\u{feff}>chr1\nACGT\n".as_bytes()).unwrap_err();
+    assert_eq!(error.position().unwrap().line, 1);
+    sequences = QuerySequences::from_reader(
+        "@read1 description\nAAAAAAAAAACCCCCCCCCCGGGGGGGGGT\n+\n012345678901234567890123456789\n"
+            .as_bytes(),
+    );
+    let mut sequences = sequences.unwrap();
+    sequences.insert(
+        "read2",
+        QuerySequence {
+            sequence: "ACGTACGT".to_string(),
+            quality: None,
+        },
+    );
+
+    let mut writer = SamWriter::with_sequences(Vec::new(), sequences);
+    writer.write_header(&header).unwrap();
+    for line in &lines {
+        writer.write_line(line).unwrap();
+    }
+    let output = String::from_utf8(writer.into_inner()).unwrap();
+    let records: Vec<_> = output.lines().skip(3).collect();
+    assert_eq!(
+        records,
+        [
+            "read1\t0\tchr1\t101\t60\t10S5M2I3M1D4M6S\t*\t0\t0\t\
+             AAAAAAAAAACCCCCCCCCCGGGGGGGGGT\t012345678901234567890123456789\tNM:i:3\ttp:A:P",
+            "read1\t272\tchr2\t101\t0\t6S5M2I3M1D4M10S\t*\t0\t0\t*\t*\ttp:A:S\tcs:Z::5+ac:3-g:4",
+            "read2\t4\t*\t0\t0\t*\t*\t0\t0\tACGTACGT\t*",
+        ]
+    );
+    // a query end past the query length does not produce a clip
+    let line = parse_input_str("read3\t10\t2\t12\t+\tchr1\t500\t0\t10\t10\t10\t60\tcg:Z:10M\n")
+        .unwrap()
+        .remove(0);
+    assert_eq!(sam_cigar(&line).unwrap().to_string(), "2S10M");
+}
+
+#[test]
+fn test_sam_input() {
+    use crate::sam::{SamHeader, SamReader, SamWriter};
+
+    let input = "@HD\tVN:1.6\tSO:unsorted\n\
+                 @SQ\tSN:chr1\tLN:500\n\
+                 @PG\tID:minimap2\n\
+                 read1\t0\tchr1\t101\t60\t10S5M2I3M1D4M6S\t*\t0\t0\t*\t*\tNM:i:4\tMD:Z:5A2^G4\n\
+                 read1\t2064\tchr1\t201\t30\t6H5=2I3=1D1X3=10H\t*\t0\t0\t*\t*\tSA:Z:chr1,101,+,10S14M6S,60,3;\n\
+                 read2\t256\tchr1\t1\t0\t4M\t*\t0\t0\t*\t*\tNM:i:1\n\
+                 read3\t4\t*\t0\t0\t*\t*\t0\t0\tACGT\t*\n";
+    let mut reader = SamReader::new(input.as_bytes());
+    let lines: Vec<_> = reader.by_ref().collect::<Result<_, _>>().unwrap();
+    assert_eq!(
+        reader.header().reference_sequences,
+        [("chr1".to_string(), 500)]
+    );
+    assert_eq!(lines.len(), 4);
+
+    let mut writer = PafWriter::new(Vec::new());
+    for line in &lines {
+        writer.write_line(line).unwrap();
+    }
+    assert_eq!(
+        String::from_utf8(writer.into_inner()).unwrap(),
+        "read1\t30\t10\t24\t+\tchr1\t500\t100\t113\t11\t15\t60\tNM:i:4\ttp:A:P\tMD:Z:5A2^G4\tcg:Z:5M2I3M1D4M\n\
+         read1\t30\t10\t24\t-\tchr1\t500\t200\t213\t11\t15\t30\ttp:A:P\tSA:Z:chr1,101,+,10S14M6S,60,3;\tcg:Z:5=2I3=1D1X3=\n\
+         read2\t4\t0\t4\t+\tchr1\t500\t0\t4\t3\t4\t0\tNM:i:1\ttp:A:S\tcg:Z:4M\n\
+         read3\t4\t0\t0\t*\t*\t0\t0\t0\t0\t0\t0\n"
+    );
+
+    // converting to SAM and back keeps the alignments, but not the length of unmapped queries without sequence
+    let mut writer = SamWriter::new(Vec::new());
+    writer.write_header(&SamHeader::from_lines(&lines)).unwrap();
+    for line in &lines {
+        writer.write_line(line).unwrap();
+    }
+    let sam = writer.into_inner();
+    let converted: Vec<_> = SamReader::new(sam.as_slice())
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(converted[..3], lines[..3]);
+    assert_eq!(converted[3].query_sequence_length, 0);
+
+    let error = SamReader::new("read1\t0\tchr1\tx\t60\t4M\t*\t0\t0\t*\t*\n".as_bytes())
+        .next()
+        .unwrap()
+        .unwrap_err();
+    let position = error.position().unwrap();
+    assert_eq!(
+        (position.line, position.column, position.byte_offset),
+        (1, 4, 13)
+    );
+}
+
+#[test]
+fn test_bed_output() {
+    use crate::bed::{bed12_record, BedFormat, BedReader, BedWriter};
+
+    let input = "read1\t30\t10\t24\t+\tchr1\t500\t100\t113\t12\t15\t60\tcg:Z:5M2I3M1D4M\n\
+                 read2\t300\t0\t300\t-\tchr1\t5000\t1000\t1550\t300\t550\t12\tcg:Z:100M200N150M50N50M\n\
+                 read3\t300\t0\t300\t+\tchr2\t5000\t1000\t1550\t300\t550\t5\tcs:Z::100~gt200ag:150~gt50ag:50\n\
+                 read4\t8\t0\t0\t*\t*\t0\t0\t0\t0\t0\t0\n";
+    let lines = parse_input_str(input).unwrap();
+    let write = |format| {
+        let mut writer = BedWriter::new(Vec::new(), format);
+        for line in &lines {
+            writer.write_line(line).unwrap();
+        }
+        String::from_utf8(writer.into_inner()).unwrap()
+    };
+
+    assert_eq!(
+        write(BedFormat::Target),
+        "chr1\t100\t113\tread1\t60\t+\nchr1\t1000\t1550\tread2\t12\t-\nchr2\t1000\t1550\tread3\t5\t+\n"
+    );
+    assert_eq!(
+        write(BedFormat::Query),
+        "read1\t10\t24\tchr1\t60\t+\nread2\t0\t300\tchr1\t12\t-\nread3\t0\t300\tchr2\t5\t+\n"
+    );
+    assert_eq!(
+        write(BedFormat::Bedpe).lines().next().unwrap(),
+        "read1\t10\t24\tchr1\t100\t113\tread1\t60\t+\t+"
+    );
+
+    let bed12 = write(BedFormat::Bed12);
+    let records: Vec<_> = BedReader::new(bed12.as_bytes())
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(records[0].extra_fields[6..], ["2", "8,4,", "0,9,"]);
+    for record in &records[1..] {
+        assert_eq!(
+            record.extra_fields[3..],
+            ["1000", "1550", "0", "3", "100,150,50,", "0,300,500,"]
+        );
+    }
+    // an inverted target interval without alignment strings yields an empty block
+    let inverted =
+        parse_input_str("read5\t30\t0\t30\t+\tchr1\t500\t120\t100\t10\t10\t60\n").unwrap();
+    let record = bed12_record(&inverted[0]);
+    assert_eq!((record.start, record.end), (120, 120));
+}
+
+#[test]
+fn test_variant_calling() {
+    use crate::call::{CallOptions, VariantCaller};
+    use crate::sam::{QuerySequence, QuerySequences};
+
+    let input = "read1\t17\t0\t17\t+\tchr1\t200\t100\t117\t13\t19\t60\tcs:Z::5*ag:3+ac:2-tt:4\n\
+                 read2\t20\t2\t18\t-\tchr1\t200\t114\t130\t14\t16\t60\tcs:Z::1*ga:4*ct:9\n\
+                 read3\t20\t0\t0\t*\t*\t0\t0\t0\t0\t0\t0\n";
+    let lines = parse_input_str(input).unwrap();
+    let mut reference = QuerySequences::default();
+    reference.insert(
+        "chr1",
+        QuerySequence {
+            sequence: "acgt".repeat(50),
+            quality: None,
+        },
+    );
+    let options = CallOptions::default()
+        .with_min_coverage_length(10)
+        .with_min_variant_length(10);
+
+    let mut output = Vec::new();
+    let caller = VariantCaller::with_reference(options.clone(), reference.clone());
+    assert_eq!(caller.write_vcf(&lines, "sample", &mut output).unwrap(), 4);
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.starts_with("##fileformat=VCFv4.2\n"));
+    assert!(output.contains("##contig=<ID=chr1,length=200>\n"));
+    let records: Vec<_> = output
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect();
+    assert_eq!(
+        records,
+        [
+            "chr1\t106\t.\tA\tG\t.\tPASS\tQNAME=read1;QSTART=5;QSTRAND=+\tGT\t1",
+            "chr1\t109\t.\tA\tAAC\t.\tPASS\tQNAME=read1;QSTART=9;QSTRAND=+\tGT\t1",
+            "chr1\t111\t.\tGTT\tG\t.\tPASS\tQNAME=read1;QSTART=13;QSTRAND=+\tGT\t1",
+            "chr1\t121\t.\tC\tT\t.\tPASS\tQNAME=read2;QSTART=11;QSTRAND=-\tGT\t1",
+        ]
+    );
+
+    // without reference, indels are only called if the base before them is in the alignment difference string
+    let variants = VariantCaller::new(options.clone()).call(&lines);
+    assert_eq!(variants.len(), 2);
+    assert!(variants
+        .iter()
+        .all(|variant| variant.reference.len() == 1 && variant.alternative.len() == 1));
+    let mismatch_anchored =
+        parse_input_str("read4\t12\t0\t12\t+\tchr1\t200\t100\t111\t10\t12\t60\tcs:Z::5*ag+ac:5\n")
+            .unwrap();
+    let variants = VariantCaller::new(options.clone()).call(&mismatch_anchored);
+    assert_eq!(variants.len(), 2);
+    assert_eq!(
+        (
+            variants[1].reference.as_str(),
+            variants[1].alternative.as_str()
+        ),
+        ("A", "AAC")
+    );
+
+    // regions covered by several alignments have no variants, even if only one of them is long enough for calling
+    let variants =
+        VariantCaller::with_reference(options.with_min_variant_length(17), reference).call(&lines);
+    assert_eq!(variants.len(), 3);
+    assert!(VariantCaller::default().call(&lines).is_empty());
+}
+
+#[test]
+fn test_structural_variants() {
+    use crate::sam::SamHeader;
+    use crate::sv::{detect_structural_variants, write_bedpe, write_vcf, SvOptions, SvType};
+
+    let input = "read1\t10000\t3000\t6000\t+\tchr1\t20000\t4600\t7600\t3000\t3000\t60\n\
+                 read1\t10000\t0\t3000\t+\tchr1\t20000\t1000\t4100\t3000\t3100\t60\tcg:Z:1000M100D2000M\n\
+                 read1\t10000\t6000\t8000\t-\tchr1\t20000\t8000\t10000\t2000\t2000\t60\n\
+                 read1\t10000\t8000\t10000\t+\tchr2\t5000\t100\t2100\t2000\t2000\t60\n\
+                 read1\t10000\t0\t2000\t+\tchr2\t5000\t0\t2000\t2000\t2000\t0\ttp:A:S\n\
+                 read2\t2000\t0\t1000\t+\tchr1\t20000\t0\t1000\t1000\t1000\t60\n\
+                 read2\t2000\t1000\t2000\t+\tchr1\t20000\t800\t1800\t1000\t1000\t60\n\
+                 read3\t2000\t0\t1000\t-\tchr1\t20000\t1000\t2000\t1000\t1000\t60\n\
+                 read3\t2000\t1300\t2000\t-\tchr1\t20000\t300\t1000\t700\t700\t60\n";
+    let lines = parse_input_str(input).unwrap();
+
+    let variants = detect_structural_variants(&lines, &SvOptions::default());
+    let types: Vec<_> = variants.iter().map(|variant| variant.sv_type).collect();
+    assert_eq!(
+        types,
+        [
+            SvType::Deletion,
+            SvType::Deletion,
+            SvType::Inversion,
+            SvType::Translocation,
+            SvType::Duplication,
+            SvType::Insertion,
+        ]
+    );
+    assert_eq!(variants[4].length, Some(200));
+    assert_eq!(variants[5].length, Some(300));
+    assert_eq!(
+        (variants[5].query_start, variants[5].query_end),
+        (1000, 1300)
+    );
+
+    // alignments that overlap equally on the query and the target are consistent
+    let overlapping = parse_input_str(
+        "read4\t100000\t0\t50000\t+\tchr3\t300000\t100000\t150000\t50000\t50000\t60\n\
+         read4\t100000\t48000\t100000\t+\tchr3\t300000\t148000\t200000\t52000\t52000\t60\n",
+    )
+    .unwrap();
+    assert!(detect_structural_variants(&overlapping, &SvOptions::default()).is_empty());
+
+    let mut output = Vec::new();
+    write_bedpe(&variants, &mut output).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "chr1\t2000\t2001\tchr1\t2100\t2101\tDEL\t.\t+\t+\n\
+         chr1\t4100\t4101\tchr1\t4600\t4601\tDEL\t.\t+\t+\n\
+         chr1\t7600\t7601\tchr1\t10000\t10001\tINV\t.\t+\t-\n\
+         chr1\t8000\t8001\tchr2\t100\t101\tTRA\t.\t-\t+\n\
+         chr1\t800\t801\tchr1\t1000\t1001\tDUP\t.\t+\t+\n\
+         chr1\t1000\t1001\tchr1\t1000\t1001\tINS\t.\t-\t-\n"
+    );
+
+    let mut output = Vec::new();
+    let contigs = SamHeader::from_lines(&lines).reference_sequences;
+    write_vcf(&variants, &contigs, &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("##contig=<ID=chr2,length=5000>\n"));
+    let records: Vec<_> = output
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect();
+    assert_eq!(records.len(), 6);
+    assert_eq!(
+        records[0],
+        "chr1\t800\t.\tN\t<DUP>\t.\tPASS\tSVTYPE=DUP;SVLEN=200;END=1000;QNAME=read2;QSTART=1000;QEND=1000"
+    );
+    assert_eq!(
+        records[3],
+        "chr1\t4100\t.\tN\t<DEL>\t.\tPASS\tSVTYPE=DEL;SVLEN=-500;END=4600;QNAME=read1;QSTART=3000;QEND=3000"
+    );
+    assert!(records[5].contains("SVTYPE=TRA;CHR2=chr2;END=100;"));
+
+    let variants = detect_structural_variants(&lines, &SvOptions::default().with_min_size(150));
+    assert_eq!(variants.len(), 5);
+}
+
+#[test]
+fn test_circular_merging() {
+    use crate::circular::{
+        merge_circular_alignments, origin_rotation, CircularSequences, Rotation,
+    };
+
+    // split at the origin of the target like the ctg1 alignments in test_real_samples
+    let input = "ctg1\t1000\t300\t1000\t-\tchrC\t1000\t290\t1000\t690\t710\t60\tNM:i:20\tcg:Z:400M10D300M\n\
+                 ctg1\t1000\t0\t300\t-\tchrC\t1000\t0\t300\t300\t300\t50\tNM:i:0\tcg:Z:300M\n\
+                 ctg2\t500\t400\t500\t+\tchrL\t10000\t1000\t1100\t100\t100\t60\tcs:Z::100\n\
+                 ctg2\t500\t0\t200\t+\tchrL\t10000\t1100\t1300\t199\t200\t60\tcs:Z::50*ag:149\n";
+    let lines = parse_input_str(input).unwrap();
+
+    assert_eq!(
+        merge_circular_alignments(&lines, &CircularSequences::default()),
+        lines
+    );
+
+    let circular = CircularSequences::default()
+        .with_targets(["chrC"])
+        .with_queries(["ctg2"]);
+    let merged = merge_circular_alignments(&lines, &circular);
+    let mut output = String::new();
+    for line in &merged {
+        writeln!(output, "{line}").unwrap();
+    }
+    assert_eq!(
+        output,
+        "ctg1\t1000\t0\t1000\t-\tchrC\t1000\t290\t1300\t990\t1010\t50\tNM:i:20\tcg:Z:400M10D600M\n\
+         ctg2\t500\t400\t700\t+\tchrL\t10000\t1000\t1300\t299\t300\t60\tcs:Z::150*ag:149\n"
+    );
+    assert_eq!(
+        merged[0].project_target_to_query(999).unwrap().position,
+        lines[0].project_target_to_query(999).unwrap().position
+    );
+
+    let rotation = Rotation {
+        reverse_complement: true,
+        offset: 700,
+    };
+    assert_eq!(origin_rotation(&lines, "ctg1", "chrC"), Some(rotation));
+    assert_eq!(origin_rotation(&merged, "ctg1", "chrC"), Some(rotation));
+    assert_eq!(origin_rotation(&lines, "ctg2", "chrL"), None);
+    let empty = parse_input_str("ctg3\t0\t0\t0\t+\tchrC\t1000\t0\t100\t100\t100\t60\n").unwrap();
+    assert_eq!(origin_rotation(&empty, "ctg3", "chrC"), None);
+}
+
+#[test]
+fn test_assembly_evaluation() {
+    use crate::assembly::{evaluate_assembly, EvaluationOptions};
+
+    let input = "ctgA\t6000\t0\t3000\t+\tchr1\t10000\t0\t2999\t2997\t3000\t60\tcs:Z::1000*ag:998+t:1000\n\
+                 ctgA\t6000\t3000\t6000\t+\tchr1\t10000\t5000\t8000\t3000\t3000\t60\n\
+                 ctgA\t6000\t3000\t6000\t+\tchr2\t5000\t0\t3000\t3000\t3000\t0\ttp:A:S\n\
+                 ctgB\t4000\t0\t2000\t+\tchr1\t10000\t7995\t10000\t1998\t2005\t60\tNM:i:7\tcg:Z:1000M5D1000M\n\
+                 ctgB\t4000\t2000\t4000\t-\tchr2\t5000\t0\t2000\t2000\t2000\t60\n\
+                 ctgC\t1000\t0\t0\t*\t*\t0\t0\t0\t0\t0\t0\n";
+    let lines = parse_input_str(input).unwrap();
+
+    let report = evaluate_assembly(&lines, &EvaluationOptions::default());
+    assert_eq!(report.contigs, 3);
+    assert_eq!(report.assembly_length, 11000);
+    assert_eq!(report.reference_length, 15000);
+    assert_eq!(report.aligned_length, 10000);
+    assert_eq!(report.covered_reference_length, 9999);
+    assert_eq!(report.largest_alignment, 3000);
+    assert_eq!((report.na50, report.nga50), (Some(3000), Some(2000)));
+    assert_eq!(
+        (report.relocations, report.inversions, report.translocations),
+        (1, 0, 1)
+    );
+    assert!((report.mismatches_per_100kbp - 60.0).abs() < 1e-9);
+    assert!((report.indels_per_100kbp - 40.0).abs() < 1e-9);
+
+    let mut tsv = Vec::new();
+    report.write_tsv(&mut tsv).unwrap();
+    let tsv = String::from_utf8(tsv).unwrap();
+    assert!(tsv.starts_with("metric\tvalue\ncontigs\t3\n"));
+    assert!(tsv.contains("\ngenome_fraction\t0.6666\n"));
+    assert!(tsv.contains("\nmisassemblies\t2\n"));
+    let json = report.to_json();
+    assert!(json.starts_with("{\n  \"contigs\": 3,\n"));
+    assert!(json.contains("\n  \"nga50\": 2000,\n"));
+    assert!(json.ends_with("\"indels_per_100kbp\": 40\n}\n"));
+
+    // a higher tolerance accepts the jump of ctgA
+    let report = evaluate_assembly(
+        &lines,
+        &EvaluationOptions::default().with_breakpoint_tolerance(3000),
+    );
+    assert_eq!(report.misassemblies(), 1);
+    let report = evaluate_assembly(&lines[..1], &EvaluationOptions::default());
+    assert_eq!(report.nga50, None);
+
+    // without tolerance, only collinear alignments are no misassembly
+    let collinear = parse_input_str(
+        "ctgD\t2000\t0\t1000\t+\tchr3\t5000\t0\t1000\t1000\t1000\t60\n\
+         ctgD\t2000\t1000\t2000\t+\tchr3\t5000\t1000\t2000\t1000\t1000\t60\n\
+         ctgE\t2000\t0\t1000\t+\tchr3\t5000\t2000\t3000\t1000\t1000\t60\n\
+         ctgE\t2000\t1000\t2000\t+\tchr3\t5000\t3001\t4001\t1000\t1000\t60\n",
+    )
+    .unwrap();
+    let options = EvaluationOptions::default().with_breakpoint_tolerance(0);
+    assert_eq!(
+        evaluate_assembly(&collinear[..2], &options).misassemblies(),
+        0
+    );
+    assert_eq!(evaluate_assembly(&collinear, &options).relocations, 1);
+    assert_eq!(
+        evaluate_assembly(&collinear, &options.with_breakpoint_tolerance(1)).relocations,
+        0
+    );
+
+    // alignments that overlap equally on the contig and the reference are consistent
+    let overlapping = parse_input_str(
+        "ctgF\t100000\t0\t50000\t+\tchr4\t300000\t100000\t150000\t50000\t50000\t60\n\
+         ctgF\t100000\t48000\t100000\t+\tchr4\t300000\t148000\t200000\t52000\t52000\t60\n",
+    )
+    .unwrap();
+    for tolerance in [0, 1000] {
+        let options = EvaluationOptions::default().with_breakpoint_tolerance(tolerance);
+        assert_eq!(evaluate_assembly(&overlapping, &options).misassemblies(), 0);
+    }
+    assert!(report.to_json().contains("\"nga50\": null"));
+}
+
+#[test]
+fn test_coverage() {
+    use crate::coverage::{
+        compute_coverage, write_bedgraph, write_low_coverage_regions, write_window_means,
+        CoverageOptions, DepthRun,
+    };
+
+    let input = "q1\t40\t0\t40\t+\tchr1\t100\t0\t50\t48\t50\t60\ttp:A:P\tcg:Z:20M10D20M\n\
+                 q2\t30\t0\t30\t+\tchr1\t100\t10\t40\t30\t30\t60\ttp:A:P\n\
+                 q3\t100\t0\t100\t+\tchr1\t100\t0\t100\t100\t100\t0\ttp:A:P\n\
+                 q4\t5\t0\t5\t-\tchr2\t10\t5\t10\t5\t5\t60\ttp:A:S\n\
+                 q5\t4\t0\t4\t+\tchr2\t10\t0\t4\t4\t4\t60\ttp:A:P\n\
+                 q6\t5\t0\t5\t+\tchr2\t10\t8\t13\t5\t5\t60\ttp:A:P\n\
+                 q7\t10\t0\t10\t+\tchr2\t10\t0\t10\t5\t10\t60\ttp:A:P\n\
+                 q8\t10\t0\t10\t+\tchr1\t100\t85\t100\t10\t10\t60\ttp:A:P\tcg:Z:5M5N5M\n\
+                 q9\t10\t0\t0\t*\t*\t0\t0\t0\t0\t0\t0\n\
+                 q10\t10\t0\t10\t+\tchr3\t20\t0\t10\t10\t10\t0\ttp:A:P\n\
+                 q11\t10\t0\t10\t+\tchr1\t100\t60\t50\t10\t10\t60\ttp:A:P\n";
+    let lines = parse_input_str(input).unwrap();
+    let options = CoverageOptions::default()
+        .with_min_mapping_quality(5)
+        .with_primary_only(true)
+        .with_min_identity(0.9);
+    let coverage = compute_coverage(&lines, options);
+
+    // chr3 has only an alignment with low mapping quality, so it is uncovered
+    assert_eq!(coverage.len(), 3);
+    assert_eq!(
+        coverage[2].runs,
+        vec![DepthRun {
+            start: 0,
+            end: 20,
+            depth: 0
+        }]
+    );
+    assert_eq!(
+        (coverage[0].name.as_str(), coverage[0].length),
+        ("chr1", 100)
+    );
+    assert_eq!(coverage[0].runs.len(), 9);
+    assert_eq!(
+        coverage[1].runs,
+        vec![
+            DepthRun {
+                start: 0,
+                end: 3,
+                depth: 2
+            },
+            DepthRun {
+                start: 3,
+                end: 4,
+                depth: 1
+            },
+            DepthRun {
+                start: 4,
+                end: 8,
+                depth: 0
+            },
+            DepthRun {
+                start: 8,
+                end: 10,
+                depth: 1
+            },
+        ]
+    );
+    assert_eq!(coverage[0].depth_at(25), 1);
+    assert_eq!(coverage[0].depth_at(92), 0);
+    assert_eq!(coverage[0].depth_at(100), 0);
+
+    let mut bedgraph = Vec::new();
+    write_bedgraph(&coverage, &mut bedgraph).unwrap();
+    assert_eq!(
+        String::from_utf8(bedgraph).unwrap(),
+        "chr1\t0\t10\t1\nchr1\t10\t20\t2\nchr1\t20\t30\t1\nchr1\t30\t40\t2\nchr1\t40\t50\t1\n\
+         chr1\t85\t90\t1\nchr1\t95\t100\t1\n\
+         chr2\t0\t3\t2\nchr2\t3\t4\t1\nchr2\t8\t10\t1\n"
+    );
+
+    let mut windows = Vec::new();
+    write_window_means(&coverage, 50, &mut windows).unwrap();
+    assert_eq!(
+        String::from_utf8(windows).unwrap(),
+        "chromosome\tstart\tend\tmean_depth\n\
+         chr1\t0\t50\t1.4000\nchr1\t50\t100\t0.2000\nchr2\t0\t10\t0.9000\n\
+         chr3\t0\t20\t0.0000\n"
+    );
+
+    let mut low_coverage = Vec::new();
+    write_low_coverage_regions(&coverage, 2, &mut low_coverage).unwrap();
+    assert_eq!(
+        String::from_utf8(low_coverage).unwrap(),
+        "chr1\t0\t10\nchr1\t20\t30\nchr1\t40\t100\nchr2\t3\t10\nchr3\t0\t20\n"
+    );
+
+    // without filters, the alignment of q3 covers all of chr1
+    let coverage = compute_coverage(&lines, CoverageOptions::default());
+    assert!(coverage[0].low_coverage_regions(1).is_empty());
+
+    // alternating targets with enough alignments to merge the depth changes
+    let input: String = (0..3000)
+        .map(|i| {
+            format!(
+                "q{i}\t10\t0\t10\t+\tchr{}\t100\t{}\t{}\t10\t10\t60\ttp:A:P\n",
+                i % 2,
+                i % 50,
+                i % 50 + 10
+            )
+        })
+        .collect();
+    let lines = parse_input_str(&input).unwrap();
+    let coverage = compute_coverage(&lines, CoverageOptions::default());
+    assert_eq!(coverage.len(), 2);
+    assert_eq!(coverage[0].depth_at(0), 60);
+    assert_eq!(coverage[0].depth_at(9), 300);
+    assert_eq!(coverage[1].depth_at(58), 60);
+    assert_eq!(coverage[1].depth_at(59), 0);
+}